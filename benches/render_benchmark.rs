@@ -1,11 +1,18 @@
 use criterion::{Criterion, criterion_main, criterion_group, Throughput};
 use raytracer::loaders::constructors::make_triangle_mesh_from_ply;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use raytracer::loaders::pbrt::{PbrtHeader, PbrtSceneBuilder};
+use raytracer::loaders::{ParamSet, Context};
 use raytracer::sampler::random::RandomSampler;
-use raytracer::Point2i;
-use rand::Rng;
+use raytracer::{Point2i, Point2f, Ray, Transform};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use raytracer::sampler::Sampler;
+use raytracer::bvh::{BVH, SplitMethod};
+use raytracer::primitive::{GeometricPrimitive, Primitive};
+use raytracer::sampling::uniform_sample_sphere;
+use cgmath::InnerSpace;
 
 fn bench(c: &mut Criterion) {
     let path = PathBuf::from("/Users/alex/scenes/pbrt-v3-scenes/ganesha/ganesha.pbrt");
@@ -16,7 +23,7 @@ fn bench(c: &mut Criterion) {
         header.exec_stmt(stmt).unwrap();
     }
 
-    let mut scene_builder = PbrtSceneBuilder::new(base_path);
+    let mut scene_builder = PbrtSceneBuilder::from_header(base_path, &header);
     for stmt in parsed.world {
         scene_builder.exec_stmt(stmt).unwrap();
     }
@@ -44,6 +51,50 @@ fn bench(c: &mut Criterion) {
 
 }
 
-criterion_group!(benches, bench);
+fn load_ply_prims(path: &Path) -> Vec<Box<dyn Primitive>> {
+    let mut params = ParamSet::default();
+    params
+        .with("filename", path.to_str().unwrap().to_string())
+        .with("object_to_world", Transform::identity())
+        .with("reverse_orientation", false);
+    let ctx = Context::new(path.parent().unwrap().into());
+    let mesh = Arc::new(make_triangle_mesh_from_ply(params, &ctx).unwrap());
+    mesh.iter_triangles()
+        .map(|tri| {
+            let prim = GeometricPrimitive { shape: Arc::new(tri), material: None, light: None };
+            Box::new(prim) as Box<dyn Primitive>
+        })
+        .collect()
+}
+
+/// Compares ray traversal through BVHs built with each split method. The mesh can be set with
+/// the `BVH_BENCH_PLY` environment variable, since the ones worth measuring are too large to
+/// keep in the repository.
+fn bench_bvh_split_methods(c: &mut Criterion) {
+    let path = std::env::var("BVH_BENCH_PLY")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| Path::new(env!("CARGO_MANIFEST_DIR")).join("data/rounded_cube.ply"));
+
+    let mut group = c.benchmark_group("BVH traversal");
+    group.throughput(Throughput::Elements(1));
+    for &method in &[SplitMethod::Middle, SplitMethod::EqualCounts, SplitMethod::SAH] {
+        let bvh = BVH::build_with_method(load_ply_prims(&path), method);
+        let (center, radius) = bvh.bounds.bounding_sphere();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // Rays start outside the mesh's bounding sphere and aim at random points inside it.
+        group.bench_function(format!("{:?}", method), |b| {
+            b.iter(|| {
+                let origin = center + 2.0 * radius * uniform_sample_sphere(Point2f::new(rng.gen(), rng.gen()));
+                let target = center + 0.5 * radius * uniform_sample_sphere(Point2f::new(rng.gen(), rng.gen()));
+                let mut ray = Ray::new(origin, (target - origin).normalize());
+                bvh.intersect(&mut ray)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench, bench_bvh_split_methods);
 criterion_main!(benches);
 
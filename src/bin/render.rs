@@ -2,11 +2,8 @@ use std::error::Error;
 use std::env::args;
 use raytracer::loaders::pbrt::{PbrtHeader, PbrtSceneBuilder};
 use raytracer::integrator::SamplerIntegrator;
-use raytracer::integrator::direct_lighting::{DirectLightingIntegrator, LightStrategy};
 use std::fs::File;
 use raytracer::imageio::exr::write_exr;
-use raytracer::integrator::whitted::WhittedIntegrator;
-use raytracer::integrator::path::PathIntegrator;
 use std::path::PathBuf;
 
 use clap::Clap;
@@ -52,7 +49,7 @@ fn main() -> anyhow::Result<()> {
     assert!(filename.contains(".exr"));
 
 
-    let mut scene_builder = PbrtSceneBuilder::new(base_path);
+    let mut scene_builder = PbrtSceneBuilder::from_header(base_path, &header);
     for stmt in parsed.world {
         scene_builder.exec_stmt(stmt)?;
     }
@@ -62,18 +59,11 @@ fn main() -> anyhow::Result<()> {
     let camera = header.make_camera()?;
     let sampler = header.make_sampler(opts.samples)?;
     let film = header.make_film()?;
+    let radiance = header.make_integrator()?;
 
     let mut integrator = SamplerIntegrator {
         camera,
-        // radiance: WhittedIntegrator {
-        //     max_depth: 4
-        // }
-        // radiance: DirectLightingIntegrator {
-        //     strategy: LightStrategy::UniformSampleOne,
-        //     max_depth: 4,
-        //     n_light_samples: vec![],
-        // }
-        radiance: PathIntegrator::new(5, 1.0)
+        radiance,
     };
 
     dbg!(&scene);
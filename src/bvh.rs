@@ -3,19 +3,68 @@ use bumpalo::Bump;
 
 use partition::partition;
 
-use crate::{Ray, SurfaceInteraction};
+use crate::{Ray, SurfaceInteraction, Float, INFINITY};
 use crate::geometry::bounds::Bounds3f;
 use crate::Point3f;
 use crate::primitive::Primitive;
+use crate::morton::morton3;
+use crate::math::ONE_MINUS_EPSILON;
+use std::cmp::Ordering;
 use std::time::Instant;
+use rayon::prelude::*;
 
-#[derive(Copy, Clone)]
+/// How the primitives of a node are divided between its two children during construction.
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum SplitMethod {
+    /// Split at the midpoint of the primitive centroids along the axis of largest extent.
     Middle,
+
+    /// Split into two halves with the same number of primitives.
     EqualCounts,
-    SAH
+
+    /// Split where the surface area heuristic estimates the lowest ray intersection cost,
+    /// evaluated at a fixed number of bucket boundaries along the axis of largest extent.
+    SAH,
+
+    /// Linear BVH construction: primitives are sorted along a Morton curve and clustered into
+    /// treelets which are built in parallel, and the treelets are joined with an SAH build.
+    /// Much faster to build than `SAH` for large scenes at the cost of somewhat worse trees.
+    HLBVH,
+}
+
+impl SplitMethod {
+    /// Parses the split method names used by pbrt's `Accelerator "bvh" "string splitmethod"`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "middle" => Some(SplitMethod::Middle),
+            "equal" => Some(SplitMethod::EqualCounts),
+            "sah" => Some(SplitMethod::SAH),
+            "hlbvh" => Some(SplitMethod::HLBVH),
+            _ => None
+        }
+    }
+}
+
+impl Default for SplitMethod {
+    fn default() -> Self {
+        SplitMethod::SAH
+    }
 }
 
+/// The number of buckets the centroid range is divided into when evaluating SAH splits.
+const N_SAH_BUCKETS: usize = 12;
+
+/// Primitives whose Morton codes agree in these bits are grouped into the same treelet by the
+/// HLBVH build. This is the top 12 of the 30 bits, giving up to 2^12 treelets.
+const TREELET_MASK: u32 = 0b0011_1111_1111_1100_0000_0000_0000_0000;
+
+/// The highest Morton code bit not covered by `TREELET_MASK`.
+const TREELET_FIRST_BIT: i32 = 29 - 12;
+
+/// The SAH split creates a leaf instead of splitting a node with this many primitives or fewer
+/// if that would be cheaper.
+const MAX_PRIMS_IN_NODE: usize = 4;
+
 pub struct BVH<P: AsRef<dyn Primitive> = Box<dyn Primitive>> {
     pub prims: Vec<P>,
     pub bounds: Bounds3f,
@@ -23,8 +72,13 @@ pub struct BVH<P: AsRef<dyn Primitive> = Box<dyn Primitive>> {
 }
 
 impl<P: AsRef<dyn Primitive>> BVH<P> {
+    /// Builds the BVH with the default split method, `SplitMethod::SAH`.
+    pub fn build(prims: Vec<P>) -> Self {
+        Self::build_with_method(prims, SplitMethod::default())
+    }
+
     #[tracing::instrument(skip(prims))]
-    pub fn build(mut prims: Vec<P>) -> Self {
+    pub fn build_with_method(mut prims: Vec<P>, split_method: SplitMethod) -> Self {
         // TODO: figure out prims type. Rc or Box?
 
         let start = Instant::now();
@@ -38,14 +92,23 @@ impl<P: AsRef<dyn Primitive>> BVH<P> {
         }).collect();
 
         let arena = Bump::new();
+        let mut treelet_arenas = vec![];
         let mut prim_ordering: Vec<isize> = Vec::with_capacity(prims.len());
 
-        let root = Self::recursive_build(
-            &arena,
-            &mut prim_info,
-            &mut prim_ordering,
-            SplitMethod::Middle
-        );
+        let root = match split_method {
+            SplitMethod::HLBVH => Self::hlbvh_build(
+                &arena,
+                &mut treelet_arenas,
+                &prim_info,
+                &mut prim_ordering
+            ),
+            _ => Self::recursive_build(
+                &arena,
+                &mut prim_info,
+                &mut prim_ordering,
+                split_method
+            )
+        };
 
         let world_bound = root.bounds();
 
@@ -83,13 +146,7 @@ impl<P: AsRef<dyn Primitive>> BVH<P> {
         // If there is only one primitive or all the centroids lie on the same point
         // (and therefore can't be partitioned), create a leaf node.
         if n_prims == 1 || centroid_bounds.is_point() {
-            let first_prim_idx = prim_ordering.len();
-            for prim in prim_info {
-                prim_ordering.push(prim.prim_id as isize)
-            }
-            let node = arena.alloc(
-                BVHBuildNode::new_leaf(first_prim_idx as u32, n_prims as u16, node_bounds));
-            return node;
+            return Self::make_leaf(arena, prim_info, prim_ordering, node_bounds);
         }
 
         let ax = centroid_bounds.maximum_extent() as usize;
@@ -109,8 +166,24 @@ impl<P: AsRef<dyn Primitive>> BVH<P> {
 
             SplitMethod::EqualCounts => {
                 Self::partition_equal_counts(prim_info, ax)
-            }
-            _ => unimplemented!()
+            },
+
+            SplitMethod::SAH => {
+                if n_prims <= 2 {
+                    Self::partition_equal_counts(prim_info, ax)
+                } else {
+                    match Self::find_sah_split(prim_info, &node_bounds, &centroid_bounds, ax) {
+                        Some(split_bucket) => {
+                            partition(prim_info, |prim| {
+                                sah_bucket(prim, &centroid_bounds, ax) <= split_bucket
+                            })
+                        },
+                        None => return Self::make_leaf(arena, prim_info, prim_ordering, node_bounds)
+                    }
+                }
+            },
+
+            SplitMethod::HLBVH => unreachable!("HLBVH trees are built by hlbvh_build")
         };
 
         let child1 = Self::recursive_build(arena, part1, prim_ordering, split_method);
@@ -119,6 +192,204 @@ impl<P: AsRef<dyn Primitive>> BVH<P> {
         arena.alloc(BVHBuildNode::new_interior([child1, child2], ax as u8))
     }
 
+    fn make_leaf<'a>(
+        arena: &'a Bump,
+        prim_info: &[BVHPrimInfo],
+        prim_ordering: &mut Vec<isize>,
+        node_bounds: Bounds3f,
+    ) -> &'a BVHBuildNode<'a> {
+        let first_prim_idx = prim_ordering.len();
+        for prim in prim_info {
+            prim_ordering.push(prim.prim_id as isize)
+        }
+        arena.alloc(BVHBuildNode::new_leaf(first_prim_idx as u32, prim_info.len() as u16, node_bounds))
+    }
+
+    /// Buckets the primitives by centroid along `ax` and finds the bucket boundary with the
+    /// lowest SAH cost. Returns the index of the last bucket that goes in the first child, or
+    /// `None` if the node is small enough that making it a leaf is cheaper than splitting.
+    fn find_sah_split(
+        prim_info: &[BVHPrimInfo],
+        node_bounds: &Bounds3f,
+        centroid_bounds: &Bounds3f,
+        ax: usize,
+    ) -> Option<usize> {
+        let mut buckets = [(0usize, Bounds3f::empty()); N_SAH_BUCKETS];
+        for prim in prim_info {
+            let b = sah_bucket(prim, centroid_bounds, ax);
+            buckets[b].0 += 1;
+            buckets[b].1 = buckets[b].1.join(&prim.bounds);
+        }
+
+        // The cost of each split is relative to the cost of intersecting one primitive, with
+        // traversal assumed to be 1/8 as expensive.
+        let (min_bucket, min_cost) = (0..N_SAH_BUCKETS - 1)
+            .map(|split| {
+                let (below, above) = buckets.split_at(split + 1);
+                let cost = 0.125
+                    + (sah_side_cost(below) + sah_side_cost(above)) / node_bounds.surface_area();
+                (split, cost)
+            })
+            .fold((0, INFINITY), |min, (split, cost)| {
+                if cost < min.1 { (split, cost) } else { min }
+            });
+
+        let leaf_cost = prim_info.len() as Float;
+        if prim_info.len() > MAX_PRIMS_IN_NODE || min_cost < leaf_cost {
+            Some(min_bucket)
+        } else {
+            None
+        }
+    }
+
+    /// Builds the tree with the HLBVH algorithm. Each treelet is allocated in its own arena from
+    /// `treelet_arenas` so they can be built in parallel; the nodes joining them are allocated
+    /// in `arena`. The primitives end up ordered along the Morton curve.
+    fn hlbvh_build<'a>(
+        arena: &'a Bump,
+        treelet_arenas: &'a mut Vec<Bump>,
+        prim_info: &[BVHPrimInfo],
+        prim_ordering: &mut Vec<isize>,
+    ) -> &'a BVHBuildNode<'a> {
+        let centroid_bounds = prim_info.iter()
+            .fold(Bounds3f::empty(), |bounds, prim| bounds.join_point(prim.centroid));
+
+        let mut morton_prims: Vec<MortonPrim> = prim_info.par_iter()
+            .map(|prim| {
+                let offset = centroid_bounds.offset(&prim.centroid);
+                let code = morton3(
+                    offset.x.min(ONE_MINUS_EPSILON),
+                    offset.y.min(ONE_MINUS_EPSILON),
+                    offset.z.min(ONE_MINUS_EPSILON),
+                );
+                MortonPrim { prim_idx: prim.prim_id, code }
+            })
+            .collect();
+        morton_prims.par_sort_unstable_by_key(|mp| mp.code);
+
+        // Leaves refer to ranges of the Morton-sorted primitives, so that is the final order.
+        prim_ordering.extend(morton_prims.iter().map(|mp| mp.prim_idx as isize));
+
+        // Split the sorted primitives into runs that share the high bits of their codes.
+        let mut treelets: Vec<(usize, &[MortonPrim])> = vec![];
+        let mut start = 0;
+        for end in 1..=morton_prims.len() {
+            if end == morton_prims.len()
+                || (morton_prims[start].code & TREELET_MASK) != (morton_prims[end].code & TREELET_MASK) {
+                treelets.push((start, &morton_prims[start..end]));
+                start = end;
+            }
+        }
+
+        treelet_arenas.resize_with(treelets.len(), Bump::new);
+        let mut treelet_roots: Vec<&'a BVHBuildNode<'a>> = treelet_arenas.par_iter_mut()
+            .zip(treelets.par_iter())
+            .map(|(treelet_arena, &(first_prim_idx, morton_prims))| {
+                let treelet_arena: &'a Bump = treelet_arena;
+                Self::emit_lbvh(treelet_arena, prim_info, morton_prims, first_prim_idx, TREELET_FIRST_BIT)
+            })
+            .collect();
+
+        Self::build_upper_sah(arena, &mut treelet_roots)
+    }
+
+    /// Recursively splits a range of Morton-sorted primitives at the first bit (from
+    /// `bit_index` down) where their codes differ, which is a midpoint split on that bit's
+    /// axis. `first_prim_idx` is the position of the range in the sorted order.
+    fn emit_lbvh<'a>(
+        arena: &'a Bump,
+        prim_info: &[BVHPrimInfo],
+        morton_prims: &[MortonPrim],
+        first_prim_idx: usize,
+        bit_index: i32,
+    ) -> &'a BVHBuildNode<'a> {
+        let n_prims = morton_prims.len();
+        if n_prims <= MAX_PRIMS_IN_NODE {
+            let bounds = morton_prims.iter()
+                .fold(Bounds3f::empty(), |bounds, mp| bounds.join(&prim_info[mp.prim_idx].bounds));
+            return arena.alloc(BVHBuildNode::new_leaf(first_prim_idx as u32, n_prims as u16, bounds));
+        }
+
+        if bit_index < 0 {
+            // All of the codes are the same, so split by count instead.
+            let mid = n_prims / 2;
+            let children = [
+                Self::emit_lbvh(arena, prim_info, &morton_prims[..mid], first_prim_idx, bit_index),
+                Self::emit_lbvh(arena, prim_info, &morton_prims[mid..], first_prim_idx + mid, bit_index),
+            ];
+            return arena.alloc(BVHBuildNode::new_interior(children, 0));
+        }
+
+        let mask = 1 << bit_index;
+        if (morton_prims[0].code & mask) == (morton_prims[n_prims - 1].code & mask) {
+            return Self::emit_lbvh(arena, prim_info, morton_prims, first_prim_idx, bit_index - 1);
+        }
+
+        // The higher bits are all the same, so the primitives with this bit unset come first.
+        let split = morton_prims
+            .binary_search_by(|mp| if mp.code & mask == 0 { Ordering::Less } else { Ordering::Greater })
+            .unwrap_err();
+
+        let children = [
+            Self::emit_lbvh(arena, prim_info, &morton_prims[..split], first_prim_idx, bit_index - 1),
+            Self::emit_lbvh(arena, prim_info, &morton_prims[split..], first_prim_idx + split, bit_index - 1),
+        ];
+        // Morton codes interleave the axes as xyz from the most significant bit.
+        let axis = 2 - (bit_index % 3) as u8;
+        arena.alloc(BVHBuildNode::new_interior(children, axis))
+    }
+
+    /// Joins the treelets into a single tree using SAH splits on their bounds.
+    fn build_upper_sah<'a>(arena: &'a Bump, nodes: &mut [&'a BVHBuildNode<'a>]) -> &'a BVHBuildNode<'a> {
+        if nodes.len() == 1 {
+            return nodes[0];
+        }
+
+        let (node_bounds, centroid_bounds) = nodes.iter()
+            .fold((Bounds3f::empty(), Bounds3f::empty()), |(node_bb, centr_bb), node| {
+                (node_bb.join(&node.bounds()), centr_bb.join_point(node.bounds().centroid()))
+            });
+        let ax = centroid_bounds.maximum_extent() as usize;
+
+        let bucket = |node: &BVHBuildNode| {
+            let b = (N_SAH_BUCKETS as Float * centroid_bounds.offset(&node.bounds().centroid())[ax]) as usize;
+            b.min(N_SAH_BUCKETS - 1)
+        };
+
+        let mut buckets = [(0usize, Bounds3f::empty()); N_SAH_BUCKETS];
+        for &node in nodes.iter() {
+            let b = bucket(node);
+            buckets[b].0 += 1;
+            buckets[b].1 = buckets[b].1.join(&node.bounds());
+        }
+
+        let (min_bucket, _) = (0..N_SAH_BUCKETS - 1)
+            .map(|split| {
+                let (below, above) = buckets.split_at(split + 1);
+                let cost = 0.125
+                    + (sah_side_cost(below) + sah_side_cost(above)) / node_bounds.surface_area();
+                (split, cost)
+            })
+            .fold((0, INFINITY), |min, (split, cost)| {
+                if cost < min.1 { (split, cost) } else { min }
+            });
+
+        // All of the treelets can land in one bucket if their centroids coincide.
+        let n_below: usize = buckets[..=min_bucket].iter().map(|b| b.0).sum();
+        let (part1, part2) = if n_below == 0 || n_below == nodes.len() {
+            let mid = nodes.len() / 2;
+            nodes.split_at_mut(mid)
+        } else {
+            partition(nodes, |&node| bucket(node) <= min_bucket)
+        };
+
+        let children = [
+            Self::build_upper_sah(arena, part1),
+            Self::build_upper_sah(arena, part2),
+        ];
+        arena.alloc(BVHBuildNode::new_interior(children, ax as u8))
+    }
+
     fn partition_equal_counts(prim_info: &mut [BVHPrimInfo], ax: usize)
         -> (&mut [BVHPrimInfo], &mut [BVHPrimInfo])
     {
@@ -301,6 +572,11 @@ enum LinearNodeKind {
     }
 }
 
+struct MortonPrim {
+    prim_idx: usize,
+    code: u32,
+}
+
 struct BVHPrimInfo {
     prim_id: usize,
     bounds: Bounds3f,
@@ -352,6 +628,18 @@ impl<'a> BVHBuildNode<'a> {
 
 }
 
+fn sah_bucket(prim: &BVHPrimInfo, centroid_bounds: &Bounds3f, ax: usize) -> usize {
+    let b = (N_SAH_BUCKETS as Float * centroid_bounds.offset(&prim.centroid)[ax]) as usize;
+    b.min(N_SAH_BUCKETS - 1)
+}
+
+/// The number of primitives on one side of a split times the surface area of their bounds.
+fn sah_side_cost(buckets: &[(usize, Bounds3f)]) -> Float {
+    let (count, bounds) = buckets.iter()
+        .fold((0, Bounds3f::empty()), |(count, bounds), (n, b)| (count + n, bounds.join(b)));
+    if count == 0 { 0.0 } else { count as Float * bounds.surface_area() }
+}
+
 fn apply_permutation<T>(items: &mut [T], indices: &mut [isize]) {
     // https://stackoverflow.com/a/27507869
     assert_eq!(items.len(), indices.len());
@@ -399,6 +687,12 @@ mod tests {
 
     #[test]
     fn test_bvh_intersect_many_nodes() {
+        for &method in &[SplitMethod::Middle, SplitMethod::EqualCounts, SplitMethod::SAH, SplitMethod::HLBVH] {
+            check_bvh_intersect_many_nodes(method);
+        }
+    }
+
+    fn check_bvh_intersect_many_nodes(split_method: SplitMethod) {
         let mut rng = StdRng::from_seed([3; 32]);
         let distr = Uniform::new_inclusive(-10.0, 10.0);
         let tfs: Vec<(Transform, Transform)> = (0..100)
@@ -421,7 +715,7 @@ mod tests {
             })
             .collect();
 
-        let bvh = BVH::build(prims);
+        let bvh = BVH::build_with_method(prims, split_method);
 
         let sphere_surf = UnitSphereSurface::new();
         for i in 0..500 {
@@ -436,10 +730,10 @@ mod tests {
             let expected_test = intersect_test_list(&ray, prims2.as_slice());
             let expected_isect = intersect_list(&mut ray, prims2.as_slice());
 
-            assert_eq!(expected_test, expected_isect.is_some(), "Iteration {}", i);
-            assert_eq!(bvh_isect_test, bvh_isect.is_some(), "Iteration {}", i);
-            assert_eq!(bvh_isect.map(|i| i.hit), expected_isect.map(|i| i.hit), "Iteration {}", i);
-            assert_eq!(bvh_isect_test, expected_test, "Iteration {}", i);
+            assert_eq!(expected_test, expected_isect.is_some(), "{:?} iteration {}", split_method, i);
+            assert_eq!(bvh_isect_test, bvh_isect.is_some(), "{:?} iteration {}", split_method, i);
+            assert_eq!(bvh_isect.map(|i| i.hit), expected_isect.map(|i| i.hit), "{:?} iteration {}", split_method, i);
+            assert_eq!(bvh_isect_test, expected_test, "{:?} iteration {}", split_method, i);
         }
     }
 
@@ -0,0 +1,38 @@
+use crate::{Float, Point2f, Vec2f};
+use crate::filter::Filter;
+
+/// A Gaussian falloff, offset so that it reaches zero at the edge of the filter radius.
+#[derive(Debug)]
+pub struct GaussianFilter {
+    radius: Vec2f,
+    inv_radius: Vec2f,
+    alpha: Float,
+    exp_x: Float,
+    exp_y: Float,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: Vec2f, alpha: Float) -> Self {
+        Self {
+            radius,
+            inv_radius: Vec2f::new(1.0 / radius.x, 1.0 / radius.y),
+            alpha,
+            exp_x: (-alpha * radius.x * radius.x).exp(),
+            exp_y: (-alpha * radius.y * radius.y).exp(),
+        }
+    }
+
+    fn gaussian(&self, d: Float, expv: Float) -> Float {
+        Float::max(0.0, (-self.alpha * d * d).exp() - expv)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn evaluate(&self, p: Point2f) -> Float {
+        self.gaussian(p.x, self.exp_x) * self.gaussian(p.y, self.exp_y)
+    }
+
+    fn radius(&self) -> (Vec2f, Vec2f) {
+        (self.radius, self.inv_radius)
+    }
+}
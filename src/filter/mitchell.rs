@@ -0,0 +1,46 @@
+use crate::{Float, Point2f, Vec2f};
+use crate::filter::Filter;
+
+/// The Mitchell-Netravali family of cubic filters, parameterized by `b` and `c`.
+#[derive(Debug)]
+pub struct MitchellFilter {
+    radius: Vec2f,
+    inv_radius: Vec2f,
+    b: Float,
+    c: Float,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: Vec2f, b: Float, c: Float) -> Self {
+        Self {
+            radius,
+            inv_radius: Vec2f::new(1.0 / radius.x, 1.0 / radius.y),
+            b,
+            c,
+        }
+    }
+
+    /// Evaluates the 1D filter over the range [-1, 1].
+    fn mitchell_1d(&self, x: Float) -> Float {
+        let (b, c) = (self.b, self.c);
+        let x = (2.0 * x).abs();
+        if x > 1.0 {
+            ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x +
+                (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) * (1.0 / 6.0)
+        } else {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x +
+                (-18.0 + 12.0 * b + 6.0 * c) * x * x +
+                (6.0 - 2.0 * b)) * (1.0 / 6.0)
+        }
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn evaluate(&self, p: Point2f) -> Float {
+        self.mitchell_1d(p.x * self.inv_radius.x) * self.mitchell_1d(p.y * self.inv_radius.y)
+    }
+
+    fn radius(&self) -> (Vec2f, Vec2f) {
+        (self.radius, self.inv_radius)
+    }
+}
@@ -1,17 +1,46 @@
 use crate::{Float, Point2f, Vec2f};
 
-pub trait Filter {
+pub mod gaussian;
+pub mod mitchell;
+pub mod sinc;
+pub mod triangle;
+
+pub use gaussian::GaussianFilter;
+pub use mitchell::MitchellFilter;
+pub use sinc::LanczosSincFilter;
+pub use triangle::TriangleFilter;
+
+pub trait Filter: std::fmt::Debug + Sync + Send {
     fn evaluate(&self, p: Point2f) -> Float;
 
     fn radius(&self) -> (Vec2f, Vec2f);
 }
 
+impl Filter for Box<dyn Filter> {
+    fn evaluate(&self, p: Point2f) -> Float {
+        (**self).evaluate(p)
+    }
+
+    fn radius(&self) -> (Vec2f, Vec2f) {
+        (**self).radius()
+    }
+}
+
 #[derive(Debug)]
 pub struct BoxFilter {
     pub radius: Vec2f,
     pub inv_radius: Vec2f,
 }
 
+impl BoxFilter {
+    pub fn new(radius: Vec2f) -> Self {
+        Self {
+            radius,
+            inv_radius: Vec2f::new(1.0 / radius.x, 1.0 / radius.y),
+        }
+    }
+}
+
 impl Filter for BoxFilter {
     fn evaluate(&self, _p: Point2f) -> Float {
         1.0
@@ -30,4 +59,40 @@ impl Default for BoxFilter {
             radius, inv_radius
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_filters_vanish_at_radius() {
+        let radius = Vec2f::new(2.0, 1.5);
+        let filters: Vec<Box<dyn Filter>> = vec![
+            Box::new(GaussianFilter::new(radius, 2.0)),
+            Box::new(MitchellFilter::new(radius, 1.0 / 3.0, 1.0 / 3.0)),
+            Box::new(TriangleFilter::new(radius)),
+        ];
+
+        for filter in filters {
+            assert!(filter.evaluate(Point2f::new(0.0, 0.0)) > 0.0, "{:?}", filter);
+            assert_abs_diff_eq!(filter.evaluate(Point2f::new(2.0, 0.0)), 0.0, epsilon = 1e-5);
+            assert_abs_diff_eq!(filter.evaluate(Point2f::new(0.0, 1.5)), 0.0, epsilon = 1e-5);
+            assert_abs_diff_eq!(
+                filter.evaluate(Point2f::new(0.5, -0.3)),
+                filter.evaluate(Point2f::new(-0.5, 0.3)),
+                epsilon = 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn test_lanczos_sinc_filter() {
+        let filter = LanczosSincFilter::new(Vec2f::new(4.0, 4.0), 3.0);
+        assert_abs_diff_eq!(filter.evaluate(Point2f::new(0.0, 0.0)), 1.0);
+        // zero crossings of the sinc at integers, and clamped outside the radius
+        assert_abs_diff_eq!(filter.evaluate(Point2f::new(1.0, 0.0)), 0.0, epsilon = 1e-6);
+        assert_eq!(filter.evaluate(Point2f::new(0.0, 4.5)), 0.0);
+    }
+}
@@ -0,0 +1,51 @@
+use std::f32::consts::PI;
+
+use crate::{Float, Point2f, Vec2f};
+use crate::filter::Filter;
+
+/// A sinc filter windowed by a Lanczos window, where `tau` is the number of cycles of the sinc
+/// function that pass before it is clamped to zero.
+#[derive(Debug)]
+pub struct LanczosSincFilter {
+    radius: Vec2f,
+    inv_radius: Vec2f,
+    tau: Float,
+}
+
+impl LanczosSincFilter {
+    pub fn new(radius: Vec2f, tau: Float) -> Self {
+        Self {
+            radius,
+            inv_radius: Vec2f::new(1.0 / radius.x, 1.0 / radius.y),
+            tau,
+        }
+    }
+
+    fn windowed_sinc(&self, x: Float, radius: Float) -> Float {
+        let x = x.abs();
+        if x > radius {
+            return 0.0;
+        }
+        let lanczos = sinc(x / self.tau);
+        sinc(x) * lanczos
+    }
+}
+
+fn sinc(x: Float) -> Float {
+    let x = x.abs();
+    if x < 1e-5 {
+        1.0
+    } else {
+        Float::sin(PI * x) / (PI * x)
+    }
+}
+
+impl Filter for LanczosSincFilter {
+    fn evaluate(&self, p: Point2f) -> Float {
+        self.windowed_sinc(p.x, self.radius.x) * self.windowed_sinc(p.y, self.radius.y)
+    }
+
+    fn radius(&self) -> (Vec2f, Vec2f) {
+        (self.radius, self.inv_radius)
+    }
+}
@@ -0,0 +1,28 @@
+use crate::{Float, Point2f, Vec2f};
+use crate::filter::Filter;
+
+/// A tent filter falling off linearly from the center to the edge of the filter radius.
+#[derive(Debug)]
+pub struct TriangleFilter {
+    radius: Vec2f,
+    inv_radius: Vec2f,
+}
+
+impl TriangleFilter {
+    pub fn new(radius: Vec2f) -> Self {
+        Self {
+            radius,
+            inv_radius: Vec2f::new(1.0 / radius.x, 1.0 / radius.y),
+        }
+    }
+}
+
+impl Filter for TriangleFilter {
+    fn evaluate(&self, p: Point2f) -> Float {
+        Float::max(0.0, self.radius.x - p.x.abs()) * Float::max(0.0, self.radius.y - p.y.abs())
+    }
+
+    fn radius(&self) -> (Vec2f, Vec2f) {
+        (self.radius, self.inv_radius)
+    }
+}
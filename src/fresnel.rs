@@ -102,6 +102,39 @@ impl Fresnel for FresnelNoOp {
     }
 }
 
+/// Schlick's weight `(1 - cos_theta)^5` for blending towards full reflectance at grazing
+/// angles.
+pub fn schlick_weight(cos_theta: Float) -> Float {
+    let m = (1.0 - cos_theta).clamp(0.0, 1.0);
+    (m * m) * (m * m) * m
+}
+
+/// Schlick's approximation to the Fresnel reflectance, given the reflectance at normal incidence.
+pub fn fr_schlick(r0: Spectrum, cos_theta: Float) -> Spectrum {
+    r0 + schlick_weight(cos_theta) * (Spectrum::uniform(1.0) - r0)
+}
+
+/// The reflectance at normal incidence of a dielectric with relative index of refraction `eta`.
+pub fn schlick_r0_from_eta(eta: Float) -> Float {
+    sq!(eta - 1.0) / sq!(eta + 1.0)
+}
+
+/// The Fresnel term of the Disney BSDF, which blends from a dielectric to Schlick's
+/// approximation with a colored `r0` as the surface gets more metallic.
+#[derive(Debug)]
+pub struct DisneyFresnel {
+    pub r0: Spectrum,
+    pub metallic: Float,
+    pub eta: Float,
+}
+
+impl Fresnel for DisneyFresnel {
+    fn evaluate(&self, cos_i: Float) -> Spectrum {
+        let dielectric = Spectrum::uniform(fresnel_dielectric(cos_i, 1.0, self.eta));
+        dielectric * (1.0 - self.metallic) + fr_schlick(self.r0, cos_i) * self.metallic
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
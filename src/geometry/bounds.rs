@@ -197,6 +197,11 @@ impl <S: Scalar> Bounds3<S> {
 
 impl Bounds3<Float> {
 
+    pub fn surface_area(&self) -> Float {
+        let d = self.diagonal();
+        2.0 * (d.x * d.y + d.x * d.z + d.y * d.z)
+    }
+
     pub fn offset(&self, p: &Point3<Float>) -> Vec3f {
         let mut o = p - self.min;
         if self.max.x > self.min.x { o.x /= self.max.x - self.min.x };
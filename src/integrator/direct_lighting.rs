@@ -1,7 +1,7 @@
-use crate::integrator::{IntegratorRadiance, uniform_sample_one_light};
-use crate::sampler::Sampler;
+use crate::integrator::{IntegratorRadiance, uniform_sample_one_light, estimate_direct};
+use crate::sampler::{Sampler, SampleArrayId};
 use bumpalo::Bump;
-use crate::{RayDifferential, SurfaceInteraction};
+use crate::{RayDifferential, SurfaceInteraction, Float};
 use crate::spectrum::{Spectrum};
 use crate::scene::Scene;
 use crate::material::TransportMode;
@@ -21,11 +21,16 @@ pub struct DirectLightingIntegrator {
     pub strategy: LightStrategy,
     pub max_depth: u16,
     pub n_light_samples: Vec<usize>,
-//    pub light_sample_ids:
+
+    /// The (light, scattering) sample arrays requested for each light at each depth, stored at
+    /// `depth * n_lights + light_idx`.
+    pub light_sample_ids: Vec<(SampleArrayId, SampleArrayId)>,
 }
 
 impl DirectLightingIntegrator {
-
+    pub fn new(strategy: LightStrategy, max_depth: u16) -> Self {
+        Self { strategy, max_depth, n_light_samples: vec![], light_sample_ids: vec![] }
+    }
 }
 
 impl IntegratorRadiance for DirectLightingIntegrator {
@@ -38,10 +43,13 @@ impl IntegratorRadiance for DirectLightingIntegrator {
                 .map(|light| sampler.round_count(light.n_samples()))
                 .collect();
 
-            for _ in 0..self.max_depth {
+            // The first intersection always gets direct lighting, even with a max depth of 0.
+            self.light_sample_ids.clear();
+            for _ in 0..self.max_depth.max(1) {
                 for &n_samples in &self.n_light_samples {
-                    sampler.request_2d_array(n_samples);
-                    sampler.request_2d_array(n_samples);
+                    let light_id = sampler.request_2d_array(n_samples);
+                    let scattering_id = sampler.request_2d_array(n_samples);
+                    self.light_sample_ids.push((light_id, scattering_id));
                 }
             }
         }
@@ -70,13 +78,15 @@ impl IntegratorRadiance for DirectLightingIntegrator {
 
                     radiance += match self.strategy {
                         LightStrategy::UniformSampleAll => {
+                            let n_lights = scene.lights.len();
+                            let start = depth as usize * n_lights;
                             uniform_sample_all_lights(
                                 &intersect,
                                 &bsdf,
                                 scene,
                                 arena,
                                 sampler,
-                                &self.n_light_samples
+                                &self.light_sample_ids[start..start + n_lights]
                             )
                         },
                         LightStrategy::UniformSampleOne => {
@@ -105,32 +115,33 @@ impl IntegratorRadiance for DirectLightingIntegrator {
     }
 }
 
+/// Estimates direct lighting by taking the number of samples requested in `preprocess` from
+/// every light, using the sample arrays given by `light_sample_ids`.
 fn uniform_sample_all_lights(
     intersect: &SurfaceInteraction,
     bsdf: &Bsdf,
     scene: &Scene,
     arena: &Bump,
     sampler: &mut dyn Sampler,
-    n_light_samples: &[usize],
+    light_sample_ids: &[(SampleArrayId, SampleArrayId)],
 ) -> Spectrum {
-    unimplemented!()
-//    scene.lights.iter().zip(n_light_samples).map(|(light, &n_samples)| {
-//        // TODO: sampler return optional arrays
-//        let u_light_array = sampler.get_2d_array(n_samples);
-//        let u_scattering_array = sampler.get_2d_array(n_samples);
-//
-//        u_light_array.iter().zip(u_scattering_array)
-//            .map(|(&u_light, &u_scattering)| {
-//                estimate_direct(
-//                    bsdf,
-//                    intersect,
-//                    u_scattering,
-//                    *light,
-//                    u_light,
-//                    scene,
-//                    arena,
-////                    sampler, // TODO: ??? would be needed for volumes
-//                )
-//            }).sum::<Spectrum>() / (n_samples as Float)
-//    }).sum()
+    scene.lights.iter().zip(light_sample_ids).map(|(light, &(light_id, scattering_id))| {
+        let u_light_array = sampler.get_2d_array(light_id);
+        let u_scattering_array = sampler.get_2d_array(scattering_id);
+        let n_samples = u_light_array.len();
+
+        let radiance = u_light_array.iter().zip(u_scattering_array)
+            .map(|(&u_light, &u_scattering)| {
+                estimate_direct(
+                    bsdf,
+                    intersect,
+                    u_scattering,
+                    light.as_ref(),
+                    u_light,
+                    scene,
+                    arena,
+                )
+            }).sum::<Spectrum>();
+        radiance / n_samples as Float
+    }).sum()
 }
@@ -5,7 +5,7 @@ use rayon::prelude::*;
 use crate::{abs_dot, Bounds2i, Differential, Float, RayDifferential, SurfaceInteraction, Point2f};
 use crate::camera::Camera;
 use crate::film::Film;
-use crate::filter::BoxFilter;
+use crate::filter::Filter;
 use crate::reflection::bsdf::Bsdf;
 use crate::reflection::BxDFType;
 use crate::sampler::Sampler;
@@ -178,6 +178,50 @@ pub trait IntegratorRadiance: Sync + Send {
     }
 }
 
+/// Allows the radiance integrator to be chosen at runtime, e.g. from the scene description.
+impl IntegratorRadiance for Box<dyn IntegratorRadiance> {
+    fn preprocess(&mut self, scene: &Scene, sampler: &mut dyn Sampler) {
+        (**self).preprocess(scene, sampler)
+    }
+
+    fn incident_radiance(
+        &self,
+        ray: &mut RayDifferential,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        arena: &Bump,
+        depth: u16,
+    ) -> Spectrum {
+        (**self).incident_radiance(ray, scene, sampler, arena, depth)
+    }
+
+    fn specular_reflect(
+        &self,
+        ray: &mut RayDifferential,
+        intersect: &SurfaceInteraction,
+        bsdf: &Bsdf,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        arena: &Bump,
+        depth: u16,
+    ) -> Spectrum {
+        (**self).specular_reflect(ray, intersect, bsdf, scene, sampler, arena, depth)
+    }
+
+    fn specular_transmit(
+        &self,
+        ray: &mut RayDifferential,
+        intersect: &SurfaceInteraction,
+        bsdf: &Bsdf,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        arena: &Bump,
+        depth: u16,
+    ) -> Spectrum {
+        (**self).specular_transmit(ray, intersect, bsdf, scene, sampler, arena, depth)
+    }
+}
+
 impl<R: IntegratorRadiance> SamplerIntegrator<R> {
     fn tile_id(tile: Bounds2i, sample_bounds: Bounds2i) -> u64 {
         let n_cols = sample_bounds.max.x;
@@ -190,7 +234,7 @@ impl<R: IntegratorRadiance> SamplerIntegrator<R> {
         bar
     }
 
-    pub fn render_with_pool(&mut self, scene: &Scene, film: &Film<BoxFilter>, sampler: impl Sampler, pool: &rayon::ThreadPool) {
+    pub fn render_with_pool<F: Filter>(&mut self, scene: &Scene, film: &Film<F>, sampler: impl Sampler, pool: &rayon::ThreadPool) {
         pool.install(|| self.render_parallel(scene, film, sampler))
     }
 
@@ -203,7 +247,7 @@ impl<R: IntegratorRadiance> SamplerIntegrator<R> {
             })
     }
 
-    pub fn render(&mut self, scene: &Scene, film: &Film<BoxFilter>, mut sampler: impl Sampler) {
+    pub fn render<F: Filter>(&mut self, scene: &Scene, film: &Film<F>, mut sampler: impl Sampler) {
         self.radiance.preprocess(scene, &mut sampler);
 //        let total_samples = sample_bounds.area() * self.sampler.samples_per_pixel() as i32;
 //        let progress = indicatif::ProgressBar::new(total_samples as u64);
@@ -215,7 +259,7 @@ impl<R: IntegratorRadiance> SamplerIntegrator<R> {
        progress.finish();
     }
 
-    pub fn render_parallel(&mut self, scene: &Scene, film: &Film<BoxFilter>, mut sampler: impl Sampler) {
+    pub fn render_parallel<F: Filter>(&mut self, scene: &Scene, film: &Film<F>, mut sampler: impl Sampler) {
         self.radiance.preprocess(scene, &mut sampler);
         let tiles: Vec<_> = self.iter_tiles(film.sample_bounds(), sampler).collect();
         let progress = Self::make_progress_bar(film.sample_bounds().area() as u64);
@@ -226,9 +270,9 @@ impl<R: IntegratorRadiance> SamplerIntegrator<R> {
         progress.finish()
     }
 
-    fn render_tile(&self,
+    fn render_tile<F: Filter>(&self,
                    scene: &Scene,
-                   film: &Film<BoxFilter>,
+                   film: &Film<F>,
                    mut tile_sampler: impl Sampler,
                    tile: Bounds2i,
                    progress: &indicatif::ProgressBar
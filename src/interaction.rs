@@ -58,6 +58,7 @@ impl SurfaceHit {
     }
 }
 
+#[derive(Clone)]
 pub struct SurfaceInteraction<'i> {
     pub hit: SurfaceHit,
 
@@ -107,6 +108,26 @@ impl<'i> SurfaceInteraction<'i> {
         }
     }
 
+    /// Sets the shading tangents and the shading normal they imply. Either the geometric
+    /// normal is flipped to the side of the new shading normal, or the other way around.
+    pub fn set_shading_geometry(&mut self, shading_geom: DiffGeom, orientation_is_authoritative: bool) {
+        let mut shading_geom = shading_geom;
+        let mut shading_n = Normal3(shading_geom.dpdu.cross(shading_geom.dpdv).normalize());
+        if orientation_is_authoritative {
+            self.hit.n = self.hit.n.faceforward(shading_n.0);
+        } else {
+            shading_n = shading_n.faceforward(self.hit.n.0);
+        }
+
+        // Keep the tangents from growing without bound through repeated perturbation
+        while shading_geom.dpdu.magnitude2() > 1e16 || shading_geom.dpdv.magnitude2() > 1e16 {
+            shading_geom.dpdu *= 1e-8;
+            shading_geom.dpdv *= 1e-8;
+        }
+
+        self.shading_n = shading_n;
+        self.shading_geom = shading_geom;
+    }
 
     pub fn compute_scattering_functions<'a>(
         &mut self,
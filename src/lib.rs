@@ -40,6 +40,7 @@ pub mod fresnel;
 pub mod material;
 pub mod texture;
 pub mod sampling;
+pub mod lowdiscrepancy;
 pub mod light;
 pub mod loaders;
 pub mod id_arena;
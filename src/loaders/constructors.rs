@@ -1,24 +1,44 @@
 use crate::loaders::{ParamSet, ParamError, Context};
 use crate::shapes::sphere::Sphere;
+use crate::shapes::disk::Disk;
+use crate::shapes::cylinder::Cylinder;
+use crate::shapes::cone::Cone;
+use crate::shapes::paraboloid::Paraboloid;
+use crate::shapes::hyperboloid::Hyperboloid;
+use crate::shapes::curve::{Curve, CurveCommon, CurveType};
+use crate::math::lerp;
 use crate::{Transform, Float, Point3f, Normal3, Vec3f, Point2f};
 use crate::material::matte::MatteMaterial;
 use crate::shapes::triangle::TriangleMesh;
+use crate::shapes::loop_subdiv::loop_subdivide;
+use crate::shapes::heightfield::heightfield;
+use crate::shapes::bilinear::BilinearPatchMesh;
+use crate::shapes::displacement::{displace_mesh, Tessellation};
+use crate::loaders::obj::{ObjMesh, load_obj};
 use crate::light::diffuse::DiffuseAreaLightBuilder;
 use crate::spectrum::Spectrum;
 use crate::texture::checkerboard::{Checkerboard2DTexture};
 use crate::texture::mapping::{TexCoordsMap2D, UVMapping};
 use std::sync::Arc;
-use crate::texture::{Texture, TextureRef};
+use smallvec::SmallVec;
+use crate::texture::{Texture, TextureRef, ConstantTexture};
 use crate::light::distant::DistantLight;
 use crate::light::point::PointLight;
 use crate::mipmap::ImageWrap;
-use crate::imageio::{ImageTexInfo, get_mipmap};
+use crate::imageio::{ImageTexInfo, get_mipmap, load_image};
 use crate::texture::image::ImageTexture;
 use crate::light::infinite::InfiniteAreaLight;
 use crate::material::glass::GlassMaterial;
 use crate::material::metal::{MetalMaterial, RoughnessTex};
 use crate::material::plastic::PlasticMaterial;
+use crate::material::substrate::SubstrateMaterial;
+use crate::material::uber::UberMaterial;
+use crate::material::mix::MixMaterial;
+use crate::material::translucent::TranslucentMaterial;
+use crate::material::disney::{DisneyMaterial, DisneyParams, SpecularEta};
+use crate::material::Material;
 use crate::material::mirror::MirrorMaterial;
+use crate::material::bump::ShadingMap;
 use crate::texture::uv::UVTexture;
 
 type ParamResult<T> = Result<T, ConstructError>;
@@ -35,6 +55,34 @@ impl From<ParamError> for ConstructError {
     }
 }
 
+/// The alpha mask for all rays, and the one for shadow rays only.
+pub type AlphaMasks = (Option<TextureRef<Float>>, Option<TextureRef<Float>>);
+
+/// Takes the "alpha" and "shadowalpha" shape parameters, which can be textures or constants.
+/// Constant alphas of one are fully opaque, so they don't need a mask.
+pub fn make_alpha_masks(params: &mut ParamSet) -> AlphaMasks {
+    let mut alpha_mask = |name: &'static str| match params.get_one::<Float>(name) {
+        Ok(a) if a >= 1.0 => None,
+        Ok(a) => Some(Arc::new(ConstantTexture(a)) as TextureRef<Float>),
+        Err(_) => params.get_one::<TextureRef<Float>>(name).ok(),
+    };
+    (alpha_mask("alpha"), alpha_mask("shadowalpha"))
+}
+
+/// Reads an optional "displacement" texture for a triangle mesh, tessellated until its edges
+/// are no longer than "edgelength", or a fixed number of "levels" of subdivision if given.
+fn make_displacement(params: &mut ParamSet) -> Option<(TextureRef<Float>, Tessellation)> {
+    let displacement = match params.get_one::<Float>("displacement") {
+        Ok(d) => Arc::new(ConstantTexture(d)) as TextureRef<Float>,
+        Err(_) => params.get_one::<TextureRef<Float>>("displacement").ok()?,
+    };
+    let tessellation = match params.get_one::<i32>("levels") {
+        Ok(levels) => Tessellation::Levels(levels.max(0) as u32),
+        Err(_) => Tessellation::EdgeLength(params.get_one("edgelength").unwrap_or(1.0)),
+    };
+    Some((displacement, tessellation))
+}
+
 pub fn make_sphere(mut params: ParamSet, ctx: &Context) -> ParamResult<Sphere<Transform>> {
     let radius = params.get_one("radius").unwrap_or(1.0);
     let zmin = params.get_one("zmin").unwrap_or(-radius);
@@ -54,15 +102,129 @@ pub fn make_sphere(mut params: ParamSet, ctx: &Context) -> ParamResult<Sphere<Tr
     ))
 }
 
+pub fn make_disk(mut params: ParamSet, ctx: &Context) -> ParamResult<Disk<Transform>> {
+    let height = params.get_one("height").unwrap_or(0.0);
+    let radius = params.get_one("radius").unwrap_or(1.0);
+    let inner_radius = params.get_one("innerradius").unwrap_or(0.0);
+    let phimax = params.get_one("phimax").unwrap_or(360.0);
+    let o2w = params.current_transform()?;
+    let w2o = o2w.inverse();
+    let rev = params.reverse_orientation()?;
+    Ok(Disk::new(o2w, w2o, rev, height, radius, inner_radius, phimax))
+}
+
+pub fn make_cylinder(mut params: ParamSet, ctx: &Context) -> ParamResult<Cylinder<Transform>> {
+    let radius = params.get_one("radius").unwrap_or(1.0);
+    let zmin = params.get_one("zmin").unwrap_or(-1.0);
+    let zmax = params.get_one("zmax").unwrap_or(1.0);
+    let phimax = params.get_one("phimax").unwrap_or(360.0);
+    let o2w = params.current_transform()?;
+    let w2o = o2w.inverse();
+    let rev = params.reverse_orientation()?;
+    Ok(Cylinder::new(o2w, w2o, rev, radius, zmin, zmax, phimax))
+}
+
+pub fn make_cone(mut params: ParamSet, ctx: &Context) -> ParamResult<Cone<Transform>> {
+    let height = params.get_one("height").unwrap_or(1.0);
+    let radius = params.get_one("radius").unwrap_or(1.0);
+    let phimax = params.get_one("phimax").unwrap_or(360.0);
+    let o2w = params.current_transform()?;
+    let w2o = o2w.inverse();
+    let rev = params.reverse_orientation()?;
+    Ok(Cone::new(o2w, w2o, rev, height, radius, phimax))
+}
+
+pub fn make_paraboloid(mut params: ParamSet, ctx: &Context) -> ParamResult<Paraboloid<Transform>> {
+    let radius = params.get_one("radius").unwrap_or(1.0);
+    let zmin = params.get_one("zmin").unwrap_or(0.0);
+    let zmax = params.get_one("zmax").unwrap_or(1.0);
+    let phimax = params.get_one("phimax").unwrap_or(360.0);
+    let o2w = params.current_transform()?;
+    let w2o = o2w.inverse();
+    let rev = params.reverse_orientation()?;
+    Ok(Paraboloid::new(o2w, w2o, rev, radius, zmin, zmax, phimax))
+}
+
+pub fn make_hyperboloid(mut params: ParamSet, ctx: &Context) -> ParamResult<Hyperboloid<Transform>> {
+    let p1 = params.get_one("p1").unwrap_or(Point3f::new(0.0, 0.0, 0.0));
+    let p2 = params.get_one("p2").unwrap_or(Point3f::new(1.0, 1.0, 1.0));
+    if p1.z == p2.z {
+        return Err(ConstructError::ValueError("Hyperboloid endpoints must have different z values".to_string()));
+    }
+    let phimax = params.get_one("phimax").unwrap_or(360.0);
+    let o2w = params.current_transform()?;
+    let w2o = o2w.inverse();
+    let rev = params.reverse_orientation()?;
+    Ok(Hyperboloid::new(o2w, w2o, rev, p1, p2, phimax))
+}
+
+pub fn make_curves(mut params: ParamSet, ctx: &Context) -> ParamResult<Vec<Curve>> {
+    let o2w = params.current_transform()?;
+    let w2o = o2w.inverse();
+    let rev = params.reverse_orientation()?;
+
+    let cp: Vec<Point3f> = params.get_one("P")?;
+    let basis: String = params.get_one("basis").unwrap_or_else(|_| "bezier".to_string());
+    let degree = params.get_one::<i32>("degree").unwrap_or(3);
+    if basis != "bezier" || degree != 3 {
+        return Err(ConstructError::ValueError(format!("Unsupported curve basis {} of degree {}", basis, degree)));
+    }
+    if cp.len() < 4 || (cp.len() - 1) % 3 != 0 {
+        return Err(ConstructError::ValueError(
+            "Cubic Bezier curve needs 3 * n + 1 control points".to_string()
+        ));
+    }
+    let n_segments = (cp.len() - 1) / 3;
+
+    let type_name: String = params.get_one("type").unwrap_or_else(|_| "flat".to_string());
+    let curve_type = CurveType::from_name(&type_name)
+        .ok_or_else(|| ConstructError::ValueError(format!("Unknown curve type {}", type_name)))?;
+
+    let normals: Option<Vec<Normal3>> = params.get_one("N").ok();
+    let normals = match (curve_type, normals) {
+        (CurveType::Ribbon, Some(n)) if n.len() == n_segments + 1 => Some(n),
+        (CurveType::Ribbon, Some(_)) => {
+            return Err(ConstructError::ValueError(
+                "Ribbon curves need one normal at each segment endpoint".to_string()
+            ));
+        },
+        (CurveType::Ribbon, None) => {
+            return Err(ConstructError::ValueError("Ribbon curves need normals".to_string()));
+        },
+        (_, Some(_)) => {
+            tracing::warn!("Curve normals are only used with \"ribbon\" type curves");
+            None
+        },
+        (_, None) => None,
+    };
+
+    let width = params.get_one("width").unwrap_or(1.0);
+    let width0 = params.get_one("width0").unwrap_or(width);
+    let width1 = params.get_one("width1").unwrap_or(width);
+    let split_depth = params.get_one::<i32>("splitdepth").unwrap_or(3).max(0) as u32;
+
+    let curves = (0..n_segments)
+        .flat_map(|seg| {
+            let seg_cp = [cp[3 * seg], cp[3 * seg + 1], cp[3 * seg + 2], cp[3 * seg + 3]];
+            let w0 = lerp(seg as Float / n_segments as Float, width0, width1);
+            let w1 = lerp((seg + 1) as Float / n_segments as Float, width0, width1);
+            let seg_normals = normals.as_ref().map(|n| [n[seg], n[seg + 1]]);
+            let common = CurveCommon::new(o2w.clone(), w2o.clone(), rev, curve_type, seg_cp, w0, w1, seg_normals);
+            Curve::split(Arc::new(common), split_depth)
+        })
+        .collect();
+    Ok(curves)
+}
+
 pub fn make_triangle_mesh(mut params: ParamSet, ctx: &Context) -> ParamResult<TriangleMesh> {
     let tf = params.current_transform()?;
     let indices: Vec<i32> = params.get_one("indices")?;
-    let indices = indices.into_iter().map(|i| i as u32).collect();
-    let vertices = params.get_one("P")?;
-    let normals = params.get_one("N").ok();
+    let indices: Vec<u32> = indices.into_iter().map(|i| i as u32).collect();
+    let vertices: Vec<Point3f> = params.get_one("P")?;
+    let normals: Option<Vec<Normal3>> = params.get_one("N").ok();
     let tangents = params.get_one("S").ok();
     // TODO: handle float array
-    let tex_coords = params.get_one("uv")
+    let tex_coords: Option<Vec<Point2f>> = params.get_one("uv")
         .or_else(|_| params.get_one("st"))
         .ok()
         .or_else(|| {
@@ -79,6 +241,19 @@ pub fn make_triangle_mesh(mut params: ParamSet, ctx: &Context) -> ParamResult<Tr
         });
     let reverse_orientation = params.reverse_orientation()?;
 
+    if let Some((displacement, tessellation)) = make_displacement(&mut params) {
+        return Ok(displace_mesh(
+            &tf,
+            reverse_orientation,
+            &vertices,
+            &indices,
+            normals.as_deref(),
+            tex_coords.as_deref(),
+            &displacement,
+            tessellation
+        ));
+    }
+
     let mesh = TriangleMesh::new(
         tf,
         indices,
@@ -91,9 +266,167 @@ pub fn make_triangle_mesh(mut params: ParamSet, ctx: &Context) -> ParamResult<Tr
     Ok(mesh)
 }
 
+pub fn make_loop_subdiv(mut params: ParamSet, ctx: &Context) -> ParamResult<TriangleMesh> {
+    let tf = params.current_transform()?;
+    let rev = params.reverse_orientation()?;
+    let levels = params.get_one::<i32>("levels")
+        .or_else(|_| params.get_one::<i32>("nlevels"))
+        .unwrap_or(3)
+        .max(0) as u32;
+    let indices: Vec<i32> = params.get_one("indices")?;
+    let vertices: Vec<Point3f> = params.get_one("P")?;
+    if indices.len() % 3 != 0 {
+        return Err(ConstructError::ValueError("Number of indices for loopsubdiv must be a multiple of 3".to_string()));
+    }
+    if indices.iter().any(|&i| i < 0 || i as usize >= vertices.len()) {
+        return Err(ConstructError::ValueError("loopsubdiv has out of bounds vertex index".to_string()));
+    }
+    let indices: Vec<u32> = indices.into_iter().map(|i| i as u32).collect();
+    Ok(loop_subdivide(&tf, rev, levels, &vertices, &indices))
+}
+
+/// Takes heights either from an `nu` by `nv` grid in "Pz", or from the luminance of an image
+/// in "filename" scaled by "scale", with the top row of the image at v = 1.
+pub fn make_heightfield(mut params: ParamSet, ctx: &Context) -> ParamResult<TriangleMesh> {
+    let tf = params.current_transform()?;
+    let rev = params.reverse_orientation()?;
+    let (nu, nv, heights) = match params.get_one::<String>("filename") {
+        Ok(filename) => {
+            let path = ctx.resolve(&filename);
+            let (image, (width, height)) = load_image(&path).map_err(|e| {
+                ConstructError::ValueError(format!("Couldn't load heightfield image {}: {}", path.display(), e))
+            })?;
+            let scale = params.get_one("scale").unwrap_or(1.0);
+            let heights = (0..height).rev()
+                .flat_map(|row| image[row * width..(row + 1) * width].iter())
+                .map(|s| scale * s.luminance())
+                .collect::<Vec<Float>>();
+            (width, height, heights)
+        },
+        Err(_) => {
+            let nu = params.get_one::<i32>("nu")?;
+            let nv = params.get_one::<i32>("nv")?;
+            let heights: Vec<Float> = params.get_one("Pz")?;
+            (nu.max(0) as usize, nv.max(0) as usize, heights)
+        }
+    };
+    if nu < 2 || nv < 2 {
+        return Err(ConstructError::ValueError(format!("Heightfield needs at least 2x2 samples, got {}x{}", nu, nv)));
+    }
+    if heights.len() != nu * nv {
+        return Err(ConstructError::ValueError(format!(
+            "Heightfield has {} heights, expected {}x{}", heights.len(), nu, nv
+        )));
+    }
+    Ok(heightfield(&tf, rev, nu, nv, &heights))
+}
+
+pub fn make_bilinear_mesh(mut params: ParamSet, ctx: &Context) -> ParamResult<BilinearPatchMesh> {
+    let tf = params.current_transform()?;
+    let rev = params.reverse_orientation()?;
+    let vertices: Vec<Point3f> = params.get_one("P")?;
+    let indices: Vec<u32> = match params.get_one::<Vec<i32>>("indices") {
+        Ok(indices) => indices.into_iter().map(|i| i as u32).collect(),
+        // A single patch can be given by its four vertices alone
+        Err(_) if vertices.len() == 4 => vec![0, 1, 2, 3],
+        Err(e) => return Err(e.into()),
+    };
+    if indices.len() % 4 != 0 {
+        return Err(ConstructError::ValueError(format!(
+            "Bilinear mesh has {} indices, which is not a multiple of 4", indices.len()
+        )));
+    }
+    if let Some(&i) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+        return Err(ConstructError::ValueError(format!(
+            "Bilinear mesh index {} is out of bounds for {} vertices", i, vertices.len()
+        )));
+    }
+    let normals = params.get_one::<Vec<Normal3>>("N").ok()
+        .filter(|n| n.len() == vertices.len());
+    let tex_coords = params.get_one::<Vec<Point2f>>("uv")
+        .ok()
+        .or_else(|| {
+            params
+                .get_one::<Vec<Float>>("uv")
+                .ok()
+                .filter(|v| v.len() % 2 == 0)
+                .map(|uvs| {
+                    uvs.chunks_exact(2)
+                        .map(|uv| Point2f::new(uv[0], uv[1]))
+                        .collect()
+                })
+        })
+        .filter(|uv: &Vec<Point2f>| uv.len() == vertices.len());
+    Ok(BilinearPatchMesh::new(tf, indices, vertices, normals, tex_coords, rev))
+}
+
+/// Converts a numeric PLY property to floats, whatever type it was stored as.
+fn ply_scalars(data: Option<&plydough::PropertyData>) -> Option<Vec<Float>> {
+    use plydough::PropertyData as Ply;
+
+    let values = match data? {
+        Ply::Float(v) => v.to_vec(),
+        Ply::Double(v) => v.iter().map(|&x| x as Float).collect(),
+        Ply::Char(v) => v.iter().map(|&x| x as Float).collect(),
+        Ply::Uchar(v) => v.iter().map(|&x| x as Float).collect(),
+        Ply::Short(v) => v.iter().map(|&x| x as Float).collect(),
+        Ply::Ushort(v) => v.iter().map(|&x| x as Float).collect(),
+        Ply::Int(v) => v.iter().map(|&x| x as Float).collect(),
+        Ply::Uint(v) => v.iter().map(|&x| x as Float).collect(),
+        _ => return None,
+    };
+    Some(values)
+}
+
+/// Converts an integer PLY property to `i32`s.
+fn ply_ints(data: Option<&plydough::PropertyData>) -> Option<Vec<i32>> {
+    use plydough::PropertyData as Ply;
+
+    let values = match data? {
+        Ply::Char(v) => v.iter().map(|&x| x as i32).collect(),
+        Ply::Uchar(v) => v.iter().map(|&x| x as i32).collect(),
+        Ply::Short(v) => v.iter().map(|&x| x as i32).collect(),
+        Ply::Ushort(v) => v.iter().map(|&x| x as i32).collect(),
+        Ply::Int(v) => v.to_vec(),
+        Ply::Uint(v) => v.iter().map(|&x| x as i32).collect(),
+        _ => return None,
+    };
+    Some(values)
+}
+
+/// Splits polygonal faces into triangles, as fans around the first vertex of each face. This
+/// is only correct for convex polygons. Also returns the number of the face each triangle came
+/// from.
+fn triangulate_faces<F, I>(faces: F, n_vertices: usize) -> ParamResult<(Vec<u32>, Vec<u32>)>
+    where F: IntoIterator<Item=I>,
+          I: IntoIterator<Item=i64>
+{
+    let mut indices = Vec::new();
+    let mut triangle_faces = Vec::new();
+    for (face_num, face) in faces.into_iter().enumerate() {
+        let face: SmallVec<[u32; 4]> = face.into_iter()
+            .map(|i| {
+                if i < 0 || i as usize >= n_vertices {
+                    Err(ConstructError::ValueError(format!("Face has out of bounds vertex index {}", i)))
+                } else {
+                    Ok(i as u32)
+                }
+            })
+            .collect::<ParamResult<_>>()?;
+
+        if face.len() < 3 {
+            return Err(ConstructError::ValueError(format!("Face with {} vertices found", face.len())));
+        }
+        for i in 1..face.len() - 1 {
+            indices.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+            triangle_faces.push(face_num as u32);
+        }
+    }
+    Ok((indices, triangle_faces))
+}
+
 pub fn make_triangle_mesh_from_ply(mut params: ParamSet, ctx: &Context) -> ParamResult<TriangleMesh> {
-    use plydough::PropertyData::*;
-    use plydough::ElementData;
+    use plydough::PropertyData as Ply;
 
     let start = std::time::Instant::now();
     let filename: String = params.get_one("filename")?;
@@ -102,78 +435,106 @@ pub fn make_triangle_mesh_from_ply(mut params: ParamSet, ctx: &Context) -> Param
 
     let tf = params.current_transform()?;
     let rev = params.reverse_orientation()?;
-    let path = ctx.resolve(filename);
-    let bytes = std::fs::read(path).unwrap();
-    let ply_data = plydough::PlyData::parse_complete(&bytes).unwrap(); // TODO: errors...
+    let path = ctx.resolve(&filename);
+    let bytes = std::fs::read(&path).map_err(|e| {
+        ConstructError::ValueError(format!("Couldn't read ply file {}: {}", path.display(), e))
+    })?;
+    let ply_data = plydough::PlyData::parse_complete(&bytes).map_err(|e| {
+        ConstructError::ValueError(format!("Couldn't parse ply file {}: {:?}", path.display(), e))
+    })?;
 
+    let props = &ply_data.elements.get("vertex")
+        .ok_or_else(|| ConstructError::ValueError("Ply file is missing vertices".to_string()))?
+        .properties;
 
-    let (vertices, normals, tex_coords) = match ply_data.elements.get("vertex") {
-        Some(ElementData{ properties: props}) => {
-            let vertices = match (props.get("x"), props.get("y"), props.get("z")) {
-                (Some(Float(x)), Some(Float(y)), Some(Float(z))) => {
-                    x.iter().zip(y.iter()).zip(z.iter())
-                        .map(|((&x, &y), &z)| Point3f::new(x, y, z))
-                        .collect()
-                },
-                _ => panic!("Ply file is missing vertex coordinates")
-            };
-
-            let normals = match (props.get("nx"), props.get("ny"), props.get("nz")) {
-                (Some(Float(x)), Some(Float(y)), Some(Float(z))) => {
-                    x.iter().zip(y.iter()).zip(z.iter())
-                        .map(|((&x, &y), &z)| Normal3(Vec3f::new(x, y, z)))
-                        .collect::<Vec<_>>()
-                        .into()
-                },
-                _ => None
-            };
-
-            let tex_coords = match (props.get("u"), props.get("v")) {
-                (Some(Float(u)), Some(Float(v))) => {
-                    u.iter().zip(v.iter())
-                        .map(|(&u, &v)| Point2f::new(u, v))
-                        .collect::<Vec<_>>()
-                        .into()
-                },
-                _ => None
-            };
-            (vertices, normals, tex_coords)
+    let vertices: Vec<Point3f> = match (ply_scalars(props.get("x")), ply_scalars(props.get("y")), ply_scalars(props.get("z"))) {
+        (Some(x), Some(y), Some(z)) => {
+            x.iter().zip(y.iter()).zip(z.iter())
+                .map(|((&x, &y), &z)| Point3f::new(x, y, z))
+                .collect()
+        },
+        _ => return Err(ConstructError::ValueError("Ply file is missing vertex coordinates".to_string()))
+    };
+
+    let normals = match (ply_scalars(props.get("nx")), ply_scalars(props.get("ny")), ply_scalars(props.get("nz"))) {
+        (Some(x), Some(y), Some(z)) => {
+            Some(x.iter().zip(y.iter()).zip(z.iter())
+                .map(|((&x, &y), &z)| Normal3(Vec3f::new(x, y, z)))
+                .collect::<Vec<_>>())
+        },
+        _ => None
+    }.filter(|n| {
+        let matches = n.len() == vertices.len();
+        if !matches {
+            tracing::warn!("Ignoring ply normals, which don't match the number of vertices");
         }
+        matches
+    });
+
+    let tex_coord_names = [("u", "v"), ("s", "t"), ("texture_u", "texture_v"), ("texture_s", "texture_t")];
+    let tex_coords = tex_coord_names.iter()
+        .find_map(|(u, v)| Some((ply_scalars(props.get(*u))?, ply_scalars(props.get(*v))?)))
+        .map(|(u, v)| {
+            u.iter().zip(v.iter())
+                .map(|(&u, &v)| Point2f::new(u, v))
+                .collect::<Vec<_>>()
+        })
+        .filter(|uv| {
+            let matches = uv.len() == vertices.len();
+            if !matches {
+                tracing::warn!("Ignoring ply texture coordinates, which don't match the number of vertices");
+            }
+            matches
+        });
 
-        _ => panic!("Ply file is missing vertices")
+    let face_props = &ply_data.elements.get("face")
+        .ok_or_else(|| ConstructError::ValueError("Ply file is missing faces".to_string()))?
+        .properties;
+    let faces = face_props.get("vertex_indices")
+        .or_else(|| face_props.get("vertex_index"))
+        .ok_or_else(|| ConstructError::ValueError("Ply file is missing vertex indices".to_string()))?;
+
+    let n = vertices.len();
+    let (indices, triangle_faces) = match faces {
+        Ply::ListChar(v) => triangulate_faces(v.iter().map(|f| f.iter().map(|&i| i as i64)), n),
+        Ply::ListUchar(v) => triangulate_faces(v.iter().map(|f| f.iter().map(|&i| i as i64)), n),
+        Ply::ListShort(v) => triangulate_faces(v.iter().map(|f| f.iter().map(|&i| i as i64)), n),
+        Ply::ListUshort(v) => triangulate_faces(v.iter().map(|f| f.iter().map(|&i| i as i64)), n),
+        Ply::ListInt(v) => triangulate_faces(v.iter().map(|f| f.iter().map(|&i| i as i64)), n),
+        Ply::ListUint(v) => triangulate_faces(v.iter().map(|f| f.iter().map(|&i| i as i64)), n),
+        _ => Err(ConstructError::ValueError("Unsupported vertex indices type".to_string()))
+    }?;
+
+    let n_faces = triangle_faces.last().map_or(0, |&f| f as usize + 1);
+    let face_indices = match face_props.get("face_indices") {
+        Some(data) => {
+            let face_indices = ply_ints(Some(data))
+                .ok_or_else(|| ConstructError::ValueError("Unsupported face indices type".to_string()))?;
+            if face_indices.len() != n_faces {
+                return Err(ConstructError::ValueError(format!(
+                    "Ply file has {} face indices for {} faces", face_indices.len(), n_faces
+                )));
+            }
+            Some(triangle_faces.iter().map(|&f| face_indices[f as usize]).collect::<Vec<_>>())
+        },
+        None => None
     };
 
-    let indices = ply_data
-        .elements
-        .get("face")
-        .and_then(|el| el.properties.get("vertex_indices"))
-        .map(|verts| {
-            match verts {
-                ListInt(v) => {
-                    v.iter()
-                        .flat_map(|face| {
-                            if face.len() != 3 {
-                                panic!("Face with supported vertex count {} found", face.len())
-                            }
-                            face.iter().map(|i| *i as u32)
-                        })
-                        .collect()
-                },
-                ListUint(v) => {
-                    v.iter()
-                        .inspect(|face| {
-                            if face.len() != 3 {
-                                panic!("Face with supported vertex count {} found", face.len())
-                            }
-                        })
-                        .flatten()
-                        .copied()
-                        .collect()
-                }
-                _ => panic!("Unsupported vertex indices type")
-            }
-        })
-        .expect("Ply file is missing vertex indices");
+    if let Some((displacement, tessellation)) = make_displacement(&mut params) {
+        if face_indices.is_some() {
+            tracing::warn!("Ignoring ply face indices, which don't survive displacement");
+        }
+        return Ok(displace_mesh(
+            &tf,
+            rev,
+            &vertices,
+            &indices,
+            normals.as_deref(),
+            tex_coords.as_deref(),
+            &displacement,
+            tessellation
+        ));
+    }
 
     let mesh = TriangleMesh::new(
         tf,
@@ -183,12 +544,41 @@ pub fn make_triangle_mesh_from_ply(mut params: ParamSet, ctx: &Context) -> Param
         None,
         tex_coords,
         rev
-    );
+    ).with_face_indices(face_indices);
     let elapsed = start.elapsed().as_millis();
     tracing::debug!("Loaded in {} ms", elapsed);
     Ok(mesh)
 }
 
+pub fn make_obj_meshes(mut params: ParamSet, ctx: &Context) -> ParamResult<Vec<ObjMesh>> {
+    let filename: String = params.get_one("filename")?;
+    let span = tracing::debug_span!("load_obj_file", filename = %filename);
+    let _enter = span.enter();
+
+    let tf = params.current_transform()?;
+    let rev = params.reverse_orientation()?;
+    load_obj(ctx.resolve(&filename), &tf, rev)
+}
+
+/// Takes a "bumpmap" float texture or a "normalmap" image filename, shared by every material.
+pub fn make_shading_map(params: &mut ParamSet, ctx: &Context) -> ParamResult<Option<ShadingMap>> {
+    if let Ok(bump) = params.get_one::<TextureRef<Float>>("bumpmap") {
+        return Ok(Some(ShadingMap::Bump(bump)));
+    }
+    match params.get_one::<String>("normalmap") {
+        Ok(filename) => {
+            // Normal maps store directions rather than colors, so they're never gamma corrected.
+            let info = ImageTexInfo::new(ctx.resolve(&filename), ImageWrap::Repeat, 1.0, Some(false), true);
+            let mipmap = get_mipmap(info).map_err(|e| {
+                ConstructError::ValueError(format!("Couldn't load normal map {}: {}", filename, e))
+            })?;
+            let tex: TextureRef<Spectrum> = Arc::new(ImageTexture::new(UVMapping::default(), mipmap));
+            Ok(Some(ShadingMap::Normal(tex)))
+        },
+        Err(_) => Ok(None),
+    }
+}
+
 pub fn make_matte(mut params: ParamSet, ctx: &Context) -> ParamResult<MatteMaterial> {
     let diffuse = params.get_texture_or_default("Kd", Spectrum::uniform(0.5))?;
     let sigma = params.get_texture_or_default("sigma", 0.0)?;
@@ -237,6 +627,79 @@ pub fn make_plastic_material(mut params: ParamSet, ctx: &Context) -> ParamResult
     Ok(PlasticMaterial::new(kd, ks, roughness, remap))
 }
 
+pub fn make_substrate_material(mut params: ParamSet, ctx: &Context) -> ParamResult<SubstrateMaterial> {
+    let kd = params.get_texture_or_default("Kd", Spectrum::uniform(0.5))?;
+    let ks = params.get_texture_or_default("Ks", Spectrum::uniform(0.5))?;
+    let u_rough = params.get_texture_or_default("uroughness", 0.1)?;
+    let v_rough = params.get_texture_or_default("vroughness", 0.1)?;
+    let remap = params.get_one("remaproughness").unwrap_or(true);
+    Ok(SubstrateMaterial::new(kd, ks, u_rough, v_rough, remap))
+}
+
+pub fn make_uber_material(mut params: ParamSet, ctx: &Context) -> ParamResult<UberMaterial> {
+    let kd = params.get_texture_or_default("Kd", Spectrum::uniform(0.25))?;
+    let ks = params.get_texture_or_default("Ks", Spectrum::uniform(0.25))?;
+    let kr = params.get_texture_or_default("Kr", Spectrum::uniform(0.0))?;
+    let kt = params.get_texture_or_default("Kt", Spectrum::uniform(0.0))?;
+    let roughness = params.get_texture_or_default("roughness", 0.1)?;
+    let u_rough = params.get_texture_or_const("uroughness");
+    let v_rough = params.get_texture_or_const("vroughness");
+    let rough_tex = match (u_rough, v_rough) {
+        (Ok(u_rough), Ok(v_rough)) => {
+            RoughnessTex::Anisotropic { u_rough, v_rough }
+        },
+        _ => RoughnessTex::Isotropic(roughness)
+    };
+    let opacity = params.get_texture_or_default("opacity", Spectrum::uniform(1.0))?;
+    let eta = match params.get_texture_or_const("eta") {
+        Ok(eta) => eta,
+        Err(_) => params.get_texture_or_default("index", 1.5)?,
+    };
+    let remap = params.get_one("remaproughness").unwrap_or(true);
+    Ok(UberMaterial::new(kd, ks, kr, kt, rough_tex, opacity, eta, remap))
+}
+
+pub fn make_translucent_material(mut params: ParamSet, ctx: &Context) -> ParamResult<TranslucentMaterial> {
+    let kd = params.get_texture_or_default("Kd", Spectrum::uniform(0.25))?;
+    let ks = params.get_texture_or_default("Ks", Spectrum::uniform(0.25))?;
+    let reflect = params.get_texture_or_default("reflect", Spectrum::uniform(0.5))?;
+    let transmit = params.get_texture_or_default("transmit", Spectrum::uniform(0.5))?;
+    let roughness = params.get_texture_or_default("roughness", 0.1)?;
+    let remap = params.get_one("remaproughness").unwrap_or(true);
+    Ok(TranslucentMaterial::new(kd, ks, roughness, reflect, transmit, remap))
+}
+
+/// Takes the index of refraction from a "specular" amount if there is one, or "eta" otherwise.
+pub fn make_disney_material(mut params: ParamSet, ctx: &Context) -> ParamResult<DisneyMaterial> {
+    let disney = DisneyParams {
+        color: params.get_texture_or_default("color", Spectrum::uniform(0.5))?,
+        metallic: params.get_texture_or_default("metallic", 0.0)?,
+        eta: match params.get_texture_or_const("specular") {
+            Ok(specular) => Arc::new(SpecularEta(specular)) as TextureRef<Float>,
+            Err(_) => params.get_texture_or_default("eta", 1.5)?,
+        },
+        roughness: params.get_texture_or_default("roughness", 0.5)?,
+        specular_tint: params.get_texture_or_default("speculartint", 0.0)?,
+        anisotropic: params.get_texture_or_default("anisotropic", 0.0)?,
+        sheen: params.get_texture_or_default("sheen", 0.0)?,
+        sheen_tint: params.get_texture_or_default("sheentint", 0.5)?,
+        clearcoat: params.get_texture_or_default("clearcoat", 0.0)?,
+        clearcoat_gloss: params.get_texture_or_default("clearcoatgloss", 1.0)?,
+        spec_trans: params.get_texture_or_default("spectrans", 0.0)?,
+    };
+    if params.get_one::<bool>("thin").unwrap_or(false) {
+        tracing::warn!("Thin surfaces are not supported by the disney material");
+    }
+    Ok(DisneyMaterial::new(disney))
+}
+
+/// Takes the two materials to blend, which the scene names in "namedmaterial1" and
+/// "namedmaterial2".
+pub fn make_mix_material(mut params: ParamSet, ctx: &Context, m1: Arc<dyn Material>, m2: Arc<dyn Material>) -> ParamResult<MixMaterial> {
+    let amount = params.get_texture_or_default("amount", 0.5)?;
+    Ok(MixMaterial::new(m1, m2, amount))
+}
+
 pub fn make_diffuse_area_light(mut params: ParamSet, ctx: &Context) -> ParamResult<DiffuseAreaLightBuilder> {
     let emit = params.get_one("L").unwrap_or(Spectrum::uniform(1.0));
     let _two_sided = params.get_one("twosided").unwrap_or(false);
@@ -357,3 +820,104 @@ pub fn make_infinite_area_light(mut params: ParamSet, ctx: &Context) -> ParamRes
     );
     Ok(light)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_triangulate_faces() {
+        let faces = vec![vec![0, 1, 2], vec![0, 2, 3, 4], vec![4, 3, 2, 1, 0]];
+        let (indices, triangle_faces) = triangulate_faces(faces.iter().map(|f| f.iter().map(|&i| i as i64)), 5).unwrap();
+        assert_eq!(indices, vec![
+            0, 1, 2,
+            0, 2, 3,  0, 3, 4,
+            4, 3, 2,  4, 2, 1,  4, 1, 0,
+        ]);
+        assert_eq!(triangle_faces, vec![0, 1, 1, 2, 2, 2]);
+
+        let bad = vec![vec![0, 1, 5]];
+        assert!(triangulate_faces(bad.iter().map(|f| f.iter().map(|&i| i as i64)), 5).is_err());
+        let degenerate = vec![vec![0, 1]];
+        assert!(triangulate_faces(degenerate.iter().map(|f| f.iter().map(|&i| i as i64)), 5).is_err());
+    }
+
+    #[test]
+    fn test_make_triangle_mesh_from_ply() {
+        use std::path::Path;
+        use crate::Ray;
+        use crate::shapes::Shape;
+
+        // Two quads side by side, with double precision vertices, s/t texture coordinates and
+        // per-face indices.
+        let mut params = ParamSet::new();
+        params.put_one("filename".to_string(), "quads.ply".to_string());
+        params.put_one("object_to_world".to_string(), Transform::IDENTITY);
+        params.put_one("reverse_orientation".to_string(), false);
+        let ctx = Context::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("testscenes/ply"));
+        let mesh = Arc::new(make_triangle_mesh_from_ply(params, &ctx).unwrap());
+        assert_eq!(mesh.n_triangles, 4);
+
+        let triangles: Vec<_> = mesh.iter_triangles().collect();
+        let face_indices: Vec<_> = triangles.iter().map(|t| t.face_index()).collect();
+        assert_eq!(face_indices, vec![Some(7), Some(7), Some(9), Some(9)]);
+
+        for &(x, face_index, u) in &[(0.25, 7, 0.125), (1.5, 9, 0.75)] {
+            let ray = Ray::new(Point3f::new(x, 0.5, -1.0), Vec3f::new(0.0, 0.0, 1.0));
+            let (tri, si) = triangles.iter()
+                .find_map(|t| Some((t, t.intersect(&ray)?.1)))
+                .unwrap();
+            assert_eq!(tri.face_index(), Some(face_index));
+            assert!((si.uv.x - u).abs() < 1e-5);
+            assert!((si.uv.y - 0.5).abs() < 1e-5);
+        }
+    }
+    #[test]
+    fn test_make_disney_material() {
+        use std::path::PathBuf;
+        use bumpalo::Bump;
+        use cgmath::InnerSpace;
+        use crate::SurfaceInteraction;
+        use crate::interaction::DiffGeom;
+        use crate::material::TransportMode;
+        use crate::reflection::BxDFType;
+
+        let mut params = ParamSet::new();
+        params.put_one("color".to_string(), Spectrum::uniform(0.8));
+        params.put_one("specular".to_string(), 0.5 as Float);
+        params.put_one("sheen".to_string(), 0.5 as Float);
+        params.put_one("clearcoat".to_string(), 1.0 as Float);
+        params.put_one("clearcoatgloss".to_string(), 0.0 as Float);
+        let material = make_disney_material(params, &Context::new(PathBuf::new())).unwrap();
+
+        let mut si = SurfaceInteraction::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            vec3f!(0.0, 0.0, 0.0),
+            0.0,
+            Point2f::new(0.5, 0.5),
+            vec3f!(0.0, 0.0, 1.0),
+            Normal3::new(0.0, 0.0, 1.0),
+            DiffGeom {
+                dpdu: vec3f!(1.0, 0.0, 0.0),
+                dpdv: vec3f!(0.0, 1.0, 0.0),
+                dndu: Normal3::new(0.0, 0.0, 0.0),
+                dndv: Normal3::new(0.0, 0.0, 0.0),
+            }
+        );
+        let arena = Bump::new();
+        let bsdf = material.compute_scattering_functions(&mut si, &arena, TransportMode::Radiance, true);
+
+        // A specular amount of 0.5 is an index of refraction of 1.5.
+        assert!((bsdf.eta - 1.5).abs() < 1e-5);
+        // Diffuse, retro-reflection and sheen, then the specular and clearcoat highlights.
+        assert_eq!(bsdf.num_components(BxDFType::all()), 5);
+        assert_eq!(bsdf.num_components(BxDFType::REFLECTION | BxDFType::DIFFUSE), 3);
+        assert_eq!(bsdf.num_components(BxDFType::TRANSMISSION | BxDFType::GLOSSY), 0);
+
+        let wo = vec3f!(0.3, 0.1, 0.9).normalize();
+        for &wi in &[vec3f!(0.0, 0.0, 1.0), vec3f!(-0.3, -0.1, 0.9).normalize(), vec3f!(0.9, 0.0, 0.1).normalize()] {
+            let f = bsdf.f(wo, wi, BxDFType::all());
+            assert!((0..3).all(|i| f[i].is_finite() && f[i] > 0.0), "f was {:?} for wi {:?}", f, wi);
+        }
+    }
+}
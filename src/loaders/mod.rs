@@ -12,6 +12,7 @@ use std::fs::File;
 
 pub mod pbrt;
 pub mod constructors;
+pub mod obj;
 
 pub enum ParamVal {
     Int(SmallVec<[i32; 1]>),
@@ -105,6 +106,10 @@ macro_rules! impl_basic_conversions {
             }
         }
 
+        impl_basic_conversions!(@rest $param_variant, $into_ty, $ty_name);
+    };
+
+    (@rest $param_variant:ident, $into_ty:ty, $ty_name:expr) => {
         impl<'a> TryFrom<&'a ParamVal> for &'a $into_ty {
             type Error = TryFromParamErr<&'a ParamVal>;
 
@@ -152,11 +157,37 @@ impl_basic_conversions!(Vec2f, Vec2f, "vector2");
 impl_basic_conversions!(Point3f, Point3f, "point3");
 impl_basic_conversions!(Vec3f, Vec3f, "vector3");
 impl_basic_conversions!(Normal3, Normal3, "normal3");
-impl_basic_conversions!(Spectrum, Spectrum, "spectrum");
+impl_basic_conversions!(@rest Spectrum, Spectrum, "spectrum");
 impl_basic_conversions!(Bool, bool, "bool");
 impl_basic_conversions!(String, String, "string");
 
 
+impl TryFrom<ParamVal> for Spectrum {
+    type Error = TryFromParamErr<ParamVal>;
+
+    /// A spectrum can also be given as the name of a `.spd` file of (wavelength, value) pairs.
+    /// The parser doesn't keep the declared type of a parameter, so the file is only read here,
+    /// where a spectrum is expected, and string parameters are left alone. The scene builder
+    /// resolves the name against the scene directory when it makes the `ParamSet`.
+    fn try_from(value: ParamVal) -> Result<Self, Self::Error> {
+        match value {
+            ParamVal::Spectrum(v) if v.len() == 1 => {
+                Ok(v.into_iter().nth(0).unwrap())
+            },
+            ParamVal::String(ref v) if v.len() == 1 && v[0].ends_with(".spd") => {
+                match crate::spectrum::read_spd_file(&v[0]) {
+                    Ok(samples) => Ok(Spectrum::from_sampled(&samples)),
+                    Err(e) => {
+                        tracing::warn!("Couldn't read spectrum file {}: {}", v[0], e);
+                        Err(TryFromParamErr("spectrum", value))
+                    }
+                }
+            },
+            _ => Err(TryFromParamErr("spectrum", value))
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct ParamSet {
     params: HashMap<String, ParamVal>,
@@ -278,5 +309,20 @@ impl Context {
 mod tests {
     use super::*;
 
-
+    #[test]
+    fn test_spd_file_only_read_as_spectrum() {
+        let path = std::env::temp_dir().join("raytracer_test_constant.spd");
+        std::fs::write(&path, "# constant\n400 2.0\n700 2.0\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let mut params = ParamSet::new();
+        params.put_one("filename".to_string(), path.clone());
+        params.put_one("Kd".to_string(), path.clone());
+        assert_eq!(params.get_one::<String>("filename").unwrap(), path);
+        let kd: Spectrum = params.get_one("Kd").unwrap();
+        assert!((kd.to_xyz()[1] - 2.0).abs() < 1e-3);
+
+        params.put_one("Ks".to_string(), "missing.spd".to_string());
+        assert!(params.get_one::<Spectrum>("Ks").is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,216 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{Float, Normal3, Point2f, Point3f, Transform};
+use crate::imageio::{ImageTexInfo, get_mipmap};
+use crate::loaders::constructors::ConstructError;
+use crate::material::Material;
+use crate::material::glass::GlassMaterial;
+use crate::material::matte::MatteMaterial;
+use crate::material::plastic::PlasticMaterial;
+use crate::mipmap::ImageWrap;
+use crate::shapes::triangle::TriangleMesh;
+use crate::spectrum::Spectrum;
+use crate::texture::{ConstantTexture, TextureRef};
+use crate::texture::image::ImageTexture;
+use crate::texture::mapping::UVMapping;
+
+/// A mesh for a single group of an OBJ file, along with the material from its MTL file, if
+/// it has one.
+pub struct ObjMesh {
+    pub name: String,
+    pub mesh: TriangleMesh,
+    pub material: Option<Arc<dyn Material>>,
+}
+
+/// Loads every group in an OBJ file as a triangle mesh. Textures referenced by the MTL file
+/// are resolved relative to the OBJ file's directory.
+pub fn load_obj(
+    path: impl AsRef<Path>,
+    object_to_world: &Transform,
+    reverse_orientation: bool
+) -> Result<Vec<ObjMesh>, ConstructError> {
+    let path = path.as_ref();
+    let (models, materials) = tobj::load_obj(path).map_err(|e| {
+        ConstructError::ValueError(format!("Couldn't load obj file {}: {}", path.display(), e))
+    })?;
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let materials = materials.iter()
+        .map(|mtl| make_mtl_material(mtl, base_dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    models.into_iter()
+        .map(|model| {
+            let name = model.name;
+            let material_id = model.mesh.material_id;
+            let mesh = mesh_from_buffers(
+                *object_to_world,
+                &model.mesh.positions,
+                &model.mesh.normals,
+                &model.mesh.texcoords,
+                model.mesh.indices,
+                reverse_orientation
+            ).map_err(|e| match e {
+                ConstructError::ValueError(msg) => ConstructError::ValueError(format!("{} in group {}", msg, name)),
+                e => e,
+            })?;
+            let material = material_id.and_then(|id| materials.get(id).cloned());
+            Ok(ObjMesh { name, mesh, material })
+        })
+        .collect()
+}
+
+/// Builds a mesh from the flattened vertex buffers of an OBJ group, which share a single set
+/// of indices.
+fn mesh_from_buffers(
+    object_to_world: Transform,
+    positions: &[f32],
+    normals: &[f32],
+    texcoords: &[f32],
+    indices: Vec<u32>,
+    reverse_orientation: bool,
+) -> Result<TriangleMesh, ConstructError> {
+    let vertices: Vec<Point3f> = positions
+        .chunks_exact(3)
+        .map(|v| Point3f::new(v[0], v[1], v[2]))
+        .collect();
+    if indices.len() % 3 != 0 {
+        return Err(ConstructError::ValueError("Number of obj indices isn't a multiple of 3".to_string()));
+    }
+    if indices.iter().any(|&i| i as usize >= vertices.len()) {
+        return Err(ConstructError::ValueError("Obj face has out of bounds vertex index".to_string()));
+    }
+
+    let normals: Vec<Normal3> = normals
+        .chunks_exact(3)
+        .map(|n| Normal3::new(n[0], n[1], n[2]))
+        .collect();
+    let normals = Some(normals).filter(|n| n.len() == vertices.len());
+
+    let tex_coords: Vec<Point2f> = texcoords
+        .chunks_exact(2)
+        .map(|uv| Point2f::new(uv[0], uv[1]))
+        .collect();
+    let tex_coords = Some(tex_coords).filter(|uv| uv.len() == vertices.len());
+
+    Ok(TriangleMesh::new(
+        object_to_world,
+        indices,
+        vertices,
+        normals,
+        None,
+        tex_coords,
+        reverse_orientation
+    ))
+}
+
+/// Picks the closest material to an MTL description: glass for transparent or refractive
+/// illumination models, plastic when there is a specular color, and matte otherwise.
+fn make_mtl_material(mtl: &tobj::Material, base_dir: &Path) -> Result<Arc<dyn Material>, ConstructError> {
+    let kd: TextureRef<Spectrum> = if mtl.diffuse_texture.is_empty() {
+        Arc::new(ConstantTexture(Spectrum::new(mtl.diffuse)))
+    } else {
+        let info = ImageTexInfo::new(
+            base_dir.join(&mtl.diffuse_texture),
+            ImageWrap::Repeat,
+            1.0,
+            None,
+            true
+        );
+        let mipmap = get_mipmap(info).map_err(|e| {
+            ConstructError::ValueError(format!("Couldn't load texture {}: {}", mtl.diffuse_texture, e))
+        })?;
+        Arc::new(ImageTexture::new(UVMapping::default(), mipmap))
+    };
+
+    let refractive = matches!(mtl.illumination_model, Some(4) | Some(6) | Some(7) | Some(9));
+    let material: Arc<dyn Material> = if mtl.dissolve < 1.0 || refractive {
+        let eta = if mtl.optical_density > 0.0 { mtl.optical_density } else { 1.5 };
+        Arc::new(GlassMaterial::new(
+            Arc::new(ConstantTexture(Spectrum::uniform(1.0))),
+            Arc::new(ConstantTexture(Spectrum::uniform(1.0))),
+            Arc::new(ConstantTexture(0.0)),
+            Arc::new(ConstantTexture(0.0)),
+            Arc::new(ConstantTexture(eta)),
+            true
+        ))
+    } else if mtl.specular.iter().any(|&s| s > 0.0) {
+        // Convert the Phong exponent to a microfacet roughness (Walter et al. 2007)
+        let roughness: Float = (2.0 / (mtl.shininess.max(0.0) + 2.0)).sqrt();
+        Arc::new(PlasticMaterial::new(
+            kd,
+            Arc::new(ConstantTexture(Spectrum::new(mtl.specular))),
+            Arc::new(ConstantTexture(roughness)),
+            false
+        ))
+    } else {
+        Arc::new(MatteMaterial::new(kd, Arc::new(ConstantTexture(0.0))))
+    };
+    Ok(material)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bumpalo::Bump;
+    use crate::{Point2f, Vec3f};
+    use crate::interaction::{DiffGeom, SurfaceInteraction};
+    use crate::material::TransportMode;
+    use crate::reflection::BxDFType;
+
+    /// The union of the types of every lobe the material produces.
+    fn lobe_types(material: &dyn Material) -> BxDFType {
+        let mut si = SurfaceInteraction::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(0.0, 0.0, 0.0),
+            0.0,
+            Point2f::new(0.5, 0.5),
+            Vec3f::new(0.0, 0.0, 1.0),
+            Normal3::new(0.0, 0.0, 1.0),
+            DiffGeom {
+                dpdu: Vec3f::new(1.0, 0.0, 0.0),
+                dpdv: Vec3f::new(0.0, 1.0, 0.0),
+                dndu: Normal3::new(0.0, 0.0, 0.0),
+                dndv: Normal3::new(0.0, 0.0, 0.0),
+            }
+        );
+        let arena = Bump::new();
+        let bsdf = material.compute_scattering_functions(&mut si, &arena, TransportMode::Radiance, false);
+        bsdf.bxdfs().iter().fold(BxDFType::empty(), |t, bxdf| t | bxdf.get_type())
+    }
+
+    #[test]
+    fn test_mesh_from_buffers() {
+        let positions = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let normals = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+        let texcoords = [0.0, 0.0, 1.0, 0.0];
+        let mesh = mesh_from_buffers(
+            Transform::IDENTITY, &positions, &normals, &texcoords, vec![0, 1, 2], false
+        ).unwrap();
+        assert_eq!(mesh.n_triangles, 1);
+
+        let bad = mesh_from_buffers(Transform::IDENTITY, &positions, &[], &[], vec![0, 1, 3], false);
+        assert!(bad.is_err());
+    }
+
+    #[test]
+    fn test_load_obj() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testscenes/obj/groups.obj");
+        let meshes = load_obj(path, &Transform::IDENTITY, false).unwrap();
+        assert_eq!(meshes.len(), 3);
+
+        let names: Vec<_> = meshes.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["matte", "plastic", "glass"]);
+        let n_triangles: Vec<_> = meshes.iter().map(|m| m.mesh.n_triangles).collect();
+        assert_eq!(n_triangles, vec![2, 2, 1]);
+
+        let types: Vec<_> = meshes.iter()
+            .map(|m| lobe_types(m.material.as_deref().unwrap()))
+            .collect();
+        assert_eq!(types[0], BxDFType::REFLECTION | BxDFType::DIFFUSE);
+        assert_eq!(types[1], BxDFType::REFLECTION | BxDFType::DIFFUSE | BxDFType::GLOSSY);
+        assert!(types[2].contains(BxDFType::TRANSMISSION | BxDFType::SPECULAR));
+        assert!(!types[2].contains(BxDFType::DIFFUSE));
+    }
+}
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 use crate::material::Material;
-use crate::{Transform, Point3f, Vec3f, Point2f, Bounds2f, Point2i};
+use crate::material::bump::ShadingMapped;
+use crate::{Transform, Point3f, Vec3f, Point2f, Bounds2f, Bounds2i, Point2i};
 use crate::Float;
 use crate::light::diffuse::DiffuseAreaLightBuilder;
 use pbrt_parser as parser;
@@ -9,26 +10,43 @@ use crate::loaders::{ParamSet, ParamVal, ParamError, Context};
 use crate::spectrum::Spectrum;
 use std::collections::HashMap;
 use crate::texture::Texture;
-use crate::loaders::constructors::{make_sphere, make_matte, make_triangle_mesh, make_diffuse_area_light, ConstructError, make_checkerboard_spect, make_checkerboard_float, make_point_light, make_distant_light, make_imagemap_spect, make_infinite_area_light, make_triangle_mesh_from_ply, make_glass, make_metal_material, make_plastic_material, make_mirror_material, make_uv_spect};
+use crate::loaders::constructors::{make_sphere, make_disk, make_cylinder, make_cone, make_paraboloid, make_hyperboloid, make_curves, make_matte, make_triangle_mesh, make_diffuse_area_light, ConstructError, make_checkerboard_spect, make_checkerboard_float, make_point_light, make_distant_light, make_imagemap_spect, make_infinite_area_light, make_triangle_mesh_from_ply, make_loop_subdiv, make_heightfield, make_bilinear_mesh, make_obj_meshes, make_alpha_masks, AlphaMasks, make_glass, make_metal_material, make_plastic_material, make_substrate_material, make_uber_material, make_translucent_material, make_disney_material, make_mix_material, make_mirror_material, make_uv_spect, make_shading_map};
 use crate::light::{AreaLightBuilder, Light};
-use crate::primitive::{GeometricPrimitive, Primitive};
+use crate::primitive::{GeometricPrimitive, Primitive, TransformedPrimitive};
+use crate::shapes::Shape;
+use crate::shapes::alpha::AlphaMasked;
 use crate::shapes::triangle::TriangleMesh;
 
 use crate::texture::{SpectrumTexture, FloatTexture};
 use crate::scene::Scene;
-use crate::bvh::BVH;
+use crate::bvh::{BVH, SplitMethod};
 use crate::camera::{Camera, PerspectiveCamera};
 use crate::sampler::Sampler;
-use crate::filter::BoxFilter;
+use crate::filter::{Filter, BoxFilter, GaussianFilter, MitchellFilter, LanczosSincFilter, TriangleFilter};
 use crate::sampler::random::RandomSampler;
+use crate::sampler::stratified::StratifiedSampler;
+use crate::sampler::halton::HaltonSampler;
+use crate::sampler::sobol::SobolSampler;
+use crate::sampler::zerotwosequence::ZeroTwoSequenceSampler;
 use crate::film::Film;
+use crate::integrator::IntegratorRadiance;
+use crate::integrator::path::PathIntegrator;
+use crate::integrator::direct_lighting::{DirectLightingIntegrator, LightStrategy};
+use crate::integrator::whitted::WhittedIntegrator;
 use cgmath::Deg;
 use std::fmt::{Formatter, Error};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Transforms saved with `CoordinateSystem`, shared between the header and the world block.
+pub type NamedCoordinateSystems = HashMap<String, Transform>;
+
+/// How deeply `Include`s may nest, as a backstop for cycles that canonical paths can't catch.
+const MAX_INCLUDE_DEPTH: usize = 32;
 
 pub struct PbrtSceneBuilder {
     graphics_state: Vec<GraphicsState>,
     tf_state: Vec<Transform>,
+    named_coordinate_systems: NamedCoordinateSystems,
     float_textures: HashMap<String, Arc<dyn Texture<Output=Float>>>,
     spectrum_textures: HashMap<String, Arc<dyn Texture<Output=Spectrum>>>,
     named_materials: HashMap<String, Arc<dyn Material>>,
@@ -37,6 +55,17 @@ pub struct PbrtSceneBuilder {
     meshes: Vec<Arc<TriangleMesh>>,
     lights: Vec<Arc<dyn Light>>,
 
+    instances: HashMap<String, Arc<BVH>>,
+    /// The name of the object currently being defined between `ObjectBegin` and `ObjectEnd`,
+    /// along with the world primitives set aside while its shapes are collected in `primitives`.
+    current_instance: Option<(String, Vec<Box<dyn Primitive>>)>,
+
+    /// Files currently being evaluated through `Include`, used to detect include cycles.
+    include_stack: Vec<PathBuf>,
+
+    /// How the BVHs for the scene and object instances are built, from `Accelerator`.
+    split_method: SplitMethod,
+
     ctx: Context,
 }
 
@@ -57,6 +86,8 @@ pub enum PbrtEvalError {
         expected: String
     },
     UnknownName(String),
+    InstanceError(String),
+    IncludeError(String),
 }
 
 impl From<ParamError> for PbrtEvalError {
@@ -96,18 +127,32 @@ impl PbrtSceneBuilder {
         Self {
             graphics_state,
             tf_state,
+            named_coordinate_systems: Default::default(),
             float_textures: Default::default(),
             spectrum_textures: Default::default(),
             named_materials: Default::default(),
             primitives: vec![],
             meshes: vec![],
             lights: vec![],
+            instances: Default::default(),
+            current_instance: None,
+            include_stack: vec![],
+            split_method: SplitMethod::default(),
             ctx,
         }
     }
 
+    /// Creates a builder for the world block that follows `header`, carrying over the
+    /// coordinate systems it named (including `"camera"`) and the BVH split method.
+    pub fn from_header(base_path: PathBuf, header: &PbrtHeader) -> Self {
+        let mut builder = Self::new(base_path);
+        builder.named_coordinate_systems = header.named_coordinate_systems.clone();
+        builder.split_method = header.split_method;
+        builder
+    }
+
     pub fn create_scene(self) -> Scene {
-        let bvh = BVH::build(self.primitives);
+        let bvh = BVH::build_with_method(self.primitives, self.split_method);
         let lights = self.lights;
         let scene = Scene::new(bvh, lights, self.meshes);
         scene
@@ -124,14 +169,20 @@ impl PbrtSceneBuilder {
             parser::ParamVal::Vector3(v) => ParamVal::Vec3f(convert_vec(v).into()),
             parser::ParamVal::Normal3(v) => ParamVal::Normal3(convert_vec(v).into()),
             parser::ParamVal::Bool(v) => ParamVal::Bool(v.into()),
+            // Possibly the name of a spectrum file, which is only read where a spectrum is
+            // expected. Resolve it against the scene directory now while we know it.
+            parser::ParamVal::String(v) if v.len() == 1 && v[0].ends_with(".spd") => {
+                let path = self.ctx.resolve(&*v[0]);
+                ParamVal::String(vec![path.to_string_lossy().into_owned()].into())
+            },
             parser::ParamVal::String(v) => ParamVal::String(v.into_iter().map(|s| s.to_string()).collect::<Vec<_>>().into()),
             parser::ParamVal::Texture(s) => self.lookup_texture(&s[0])?, // TODO: no vec for textures
             parser::ParamVal::SpectrumRgb(v) => {
                 ParamVal::Spectrum(v.into_iter().map(|s| s.into()).collect::<Vec<Spectrum>>().into())
             },
-            parser::ParamVal::SpectrumXyz(_) => unimplemented!(),
-            parser::ParamVal::SpectrumSampled(_) => unimplemented!(),
-            parser::ParamVal::SpectrumBlackbody(_) => unimplemented!(),
+            parser::ParamVal::SpectrumXyz(v) => convert_xyz(v),
+            parser::ParamVal::SpectrumSampled(v) => convert_sampled(v),
+            parser::ParamVal::SpectrumBlackbody(v) => convert_blackbody(v),
         };
         Ok(value)
     }
@@ -193,25 +244,50 @@ impl PbrtSceneBuilder {
             WorldStmt::TransformEnd => {
                 self.tf_state.pop().unwrap();
             },
-            WorldStmt::ObjectBegin(_) => {
-                unimplemented!()
+            WorldStmt::ObjectBegin(name) => {
+                if let Some((current, _)) = &self.current_instance {
+                    return Err(PbrtEvalError::InstanceError(
+                        format!("ObjectBegin \"{}\" called inside of instance definition \"{}\"", name, current)
+                    ));
+                }
+                self.exec_stmt(WorldStmt::AttributeBegin)?;
+                let world_prims = std::mem::take(&mut self.primitives);
+                self.current_instance = Some((name.to_string(), world_prims));
             },
             WorldStmt::ObjectEnd => {
-                unimplemented!()
+                let (name, world_prims) = self.current_instance.take()
+                    .ok_or_else(|| PbrtEvalError::InstanceError("ObjectEnd called outside of instance definition".to_string()))?;
+                let instance_prims = std::mem::replace(&mut self.primitives, world_prims);
+                tracing::debug!("Building instance \"{}\" with {} primitives", name, instance_prims.len());
+                let bvh = BVH::build_with_method(instance_prims, self.split_method);
+                self.instances.insert(name, Arc::new(bvh));
+                self.exec_stmt(WorldStmt::AttributeEnd)?;
             },
             WorldStmt::ReverseOrientation => {
                 self.graphics_state_mut().rev_orientation = true;
             },
             WorldStmt::Transform(tf_stmt) => {
-                let ctm = self.tf_state.pop().unwrap();
-                let ctm = eval_transform_stmt(tf_stmt, &ctm)?;
-                self.tf_state.push(ctm)
+                let ctm = eval_transform_stmt(tf_stmt, self.tf_state.last().unwrap(), &mut self.named_coordinate_systems)?;
+                *self.tf_state.last_mut().unwrap() = ctm;
             },
             WorldStmt::Shape(name, params) => {
                 let params = self.make_param_set(params)?;
                 self.shape(name, params)?;
             },
-            WorldStmt::ObjectInstance(_) => {},
+            WorldStmt::ObjectInstance(name) => {
+                if self.current_instance.is_some() {
+                    return Err(PbrtEvalError::InstanceError(
+                        format!("ObjectInstance \"{}\" can't be called inside instance definition", name)
+                    ));
+                }
+                let instance = self.instances.get(name.as_ref())
+                    .ok_or_else(|| PbrtEvalError::UnknownName(name.to_string()))?;
+                if !instance.prims.is_empty() {
+                    let instance_to_world = *self.tf_state.last().unwrap();
+                    let prim = TransformedPrimitive::new(instance.clone(), instance_to_world);
+                    self.primitives.push(Box::new(prim));
+                }
+            },
             WorldStmt::LightSource(name, params) => {
                 let params = self.make_param_set(params)?;
                 self.light_source(name.as_ref(), params)?;
@@ -232,10 +308,8 @@ impl PbrtSceneBuilder {
                 self.named_materials.insert(name.to_string(), mat);
             },
             WorldStmt::NamedMaterial(name) => {
-                let mat = self.named_materials
-                    .get(name.as_ref())
-                    .ok_or_else(|| PbrtEvalError::MaterialError { expected: name.to_string() })?;
-                self.set_current_material(mat.clone())
+                let mat = self.named_material(name.as_ref())?;
+                self.set_current_material(mat)
             },
             WorldStmt::Texture(tex_stmt) => {
                 let params = self.make_param_set(tex_stmt.params)?;
@@ -247,71 +321,141 @@ impl PbrtSceneBuilder {
             WorldStmt::MediumInterface(_, _) => {
                 unimplemented!()
             },
-            WorldStmt::Include(_) => {
-                unimplemented!()
+            WorldStmt::Include(path) => {
+                let path = self.ctx.resolve(&*path);
+                self.include(&path)?;
             },
         };
         Ok(())
     }
 
-    fn shape(&mut self, name: Arc<str>, params: ParamSet) -> Result<(), PbrtEvalError> {
-        let graphics_state = self.graphics_state.last_mut().unwrap();
+    /// Evaluates the world statements of an included file as if they appeared in place of the
+    /// `Include`. The attribute and transform stacks must be balanced by the end of the file,
+    /// otherwise any state pushed inside it is discarded so it can't leak into the includer.
+    fn include(&mut self, path: &Path) -> Result<(), PbrtEvalError> {
+        let canonical = path.canonicalize()
+            .map_err(|e| PbrtEvalError::IncludeError(format!("{}: {}", path.display(), e)))?;
+        if self.include_stack.contains(&canonical) {
+            return Err(PbrtEvalError::IncludeError(format!("Include cycle detected at {}", path.display())));
+        }
+        if self.include_stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(PbrtEvalError::IncludeError(
+                format!("Includes nested more than {} deep at {}", MAX_INCLUDE_DEPTH, path.display())
+            ));
+        }
+
+        // Nested includes are left for `exec_stmt`, so every file is checked against the stack.
+        let text = std::fs::read_to_string(&canonical)
+            .map_err(|e| PbrtEvalError::IncludeError(format!("{}: {}", path.display(), e)))?;
+        let parsed = parser::PbrtParser::parse_string(&text)
+            .map_err(|e| PbrtEvalError::IncludeError(format!("{}: {}", path.display(), e)))?;
+        if !parsed.header.is_empty() {
+            tracing::warn!("Ignoring header statements in included file {}", path.display());
+        }
+        let stmts = parsed.world;
+
+        tracing::debug!("Including {}", path.display());
+        self.include_stack.push(canonical);
+        let graphics_depth = self.graphics_state.len();
+        let tf_depth = self.tf_state.len();
+
+        let result = stmts.into_iter().try_for_each(|stmt| self.exec_stmt(stmt));
+        self.include_stack.pop();
+        result?;
+
+        if self.graphics_state.len() < graphics_depth || self.tf_state.len() < tf_depth {
+            return Err(PbrtEvalError::IncludeError(
+                format!("{} ends more attribute or transform blocks than it begins", path.display())
+            ));
+        }
+        if self.graphics_state.len() > graphics_depth || self.tf_state.len() > tf_depth {
+            tracing::warn!("Unmatched AttributeBegin or TransformBegin at end of {}", path.display());
+            self.graphics_state.truncate(graphics_depth);
+            self.tf_state.truncate(tf_depth);
+        }
+        Ok(())
+    }
+
+    fn shape(&mut self, name: Arc<str>, mut params: ParamSet) -> Result<(), PbrtEvalError> {
+        let mut graphics_state = self.graphics_state.last().unwrap().clone();
+        let alpha_masks = make_alpha_masks(&mut params);
+        if self.current_instance.is_some() && graphics_state.area_light.take().is_some() {
+            tracing::warn!("Area lights not supported with object instancing");
+        }
         match name.as_ref() {
             "sphere" => {
                 let shape = make_sphere(params, &self.ctx)?;
-                let shape = Arc::new(shape);
-                let light = graphics_state.area_light.clone()
-                    .map(|builder| builder.create(shape.clone()));
-                let light = light.map(|l| Arc::new(l));
-                let prim = GeometricPrimitive {
-                    shape,
-                    material: graphics_state.material.clone(),
-                    light
-                };
-                self.primitives.push(Box::new(prim));
+                self.push_shape(shape, &graphics_state, &alpha_masks);
+            },
+
+            "disk" => {
+                let shape = make_disk(params, &self.ctx)?;
+                self.push_shape(shape, &graphics_state, &alpha_masks);
+            },
+
+            "cylinder" => {
+                let shape = make_cylinder(params, &self.ctx)?;
+                self.push_shape(shape, &graphics_state, &alpha_masks);
+            },
+
+            "cone" => {
+                let shape = make_cone(params, &self.ctx)?;
+                self.push_shape(shape, &graphics_state, &alpha_masks);
+            },
+
+            "paraboloid" => {
+                let shape = make_paraboloid(params, &self.ctx)?;
+                self.push_shape(shape, &graphics_state, &alpha_masks);
+            },
+
+            "hyperboloid" => {
+                let shape = make_hyperboloid(params, &self.ctx)?;
+                self.push_shape(shape, &graphics_state, &alpha_masks);
+            },
+
+            "curve" => {
+                for curve in make_curves(params, &self.ctx)? {
+                    self.push_shape(curve, &graphics_state, &alpha_masks);
+                }
             },
 
             "trianglemesh" => {
                 let mesh = make_triangle_mesh(params, &self.ctx)?;
-                let mesh = Arc::new(mesh);
-                self.meshes.push(mesh.clone());
-                self.primitives.extend(mesh.iter_triangles()
-                    .map(|shape| {
-                        let shape = Arc::new(shape);
-                        let light = graphics_state.area_light.clone()
-                            .map(|builder| builder.create(shape.clone()));
-                        let light = light.map(|l| Arc::new(l));
-                        let material = graphics_state.material.clone();
-                        let prim = GeometricPrimitive {
-                            shape,
-                            material,
-                            light
-                        };
-                        Box::new(prim) as Box<dyn Primitive>
-                    })
-                );
+                self.push_mesh(mesh, &graphics_state, &alpha_masks);
             },
 
             "plymesh" => {
                 let mesh = make_triangle_mesh_from_ply(params, &self.ctx)?;
-                let mesh = Arc::new(mesh);
-                self.meshes.push(mesh.clone());
-                self.primitives.extend(mesh.iter_triangles()
-                    .map(|shape| {
-                        let shape = Arc::new(shape);
-                        let light = graphics_state.area_light.clone()
-                            .map(|builder| builder.create(shape.clone()));
-                        let light = light.map(|l| Arc::new(l));
-                        let material = graphics_state.material.clone();
-                        let prim = GeometricPrimitive {
-                            shape,
-                            material,
-                            light
-                        };
-                        Box::new(prim) as Box<dyn Primitive>
-                    })
-                );
-            }
+                self.push_mesh(mesh, &graphics_state, &alpha_masks);
+            },
+
+            "loopsubdiv" => {
+                let mesh = make_loop_subdiv(params, &self.ctx)?;
+                self.push_mesh(mesh, &graphics_state, &alpha_masks);
+            },
+
+            "heightfield" => {
+                let mesh = make_heightfield(params, &self.ctx)?;
+                self.push_mesh(mesh, &graphics_state, &alpha_masks);
+            },
+
+            "bilinearmesh" => {
+                let mesh = Arc::new(make_bilinear_mesh(params, &self.ctx)?);
+                for patch in mesh.iter_patches() {
+                    self.push_shape(patch, &graphics_state, &alpha_masks);
+                }
+            },
+
+            "objmesh" => {
+                for obj_mesh in make_obj_meshes(params, &self.ctx)? {
+                    // Materials from the MTL file take precedence over the current material
+                    let mut mesh_state = graphics_state.clone();
+                    if obj_mesh.material.is_some() {
+                        mesh_state.material = obj_mesh.material;
+                    }
+                    self.push_mesh(obj_mesh.mesh, &mesh_state, &alpha_masks);
+                }
+            },
 
             _ => {
                 return Err(PbrtEvalError::UnknownName(name.to_string()));
@@ -320,7 +464,62 @@ impl PbrtSceneBuilder {
         Ok(())
     }
 
-    fn material(&mut self, name: &str, params: ParamSet) -> Result<Arc<dyn Material>, PbrtEvalError> {
+    /// Adds a primitive for a single shape, with the current material and area light, and the
+    /// shape's alpha masks if it has any.
+    fn push_shape<S: Shape + 'static>(&mut self, shape: S, graphics_state: &GraphicsState, alpha_masks: &AlphaMasks) {
+        match alpha_masks {
+            (None, None) => self.push_primitive(shape, graphics_state),
+            (alpha, shadow_alpha) => {
+                let shape = AlphaMasked::new(shape, alpha.clone(), shadow_alpha.clone());
+                self.push_primitive(shape, graphics_state)
+            }
+        }
+    }
+
+    fn push_primitive<S: Shape + 'static>(&mut self, shape: S, graphics_state: &GraphicsState) {
+        let shape = Arc::new(shape);
+        let light = graphics_state.area_light.clone()
+            .map(|builder| builder.create(shape.clone()));
+        let light = light.map(|l| Arc::new(l));
+        let prim = GeometricPrimitive {
+            shape,
+            material: graphics_state.material.clone(),
+            light
+        };
+        self.primitives.push(Box::new(prim));
+    }
+
+    /// Adds a primitive for each triangle of a mesh, with the current material and area light.
+    fn push_mesh(&mut self, mesh: TriangleMesh, graphics_state: &GraphicsState, alpha_masks: &AlphaMasks) {
+        let (alpha, shadow_alpha) = alpha_masks.clone();
+        let mesh = Arc::new(mesh.with_alpha_masks(alpha, shadow_alpha));
+        self.meshes.push(mesh.clone());
+        self.primitives.extend(mesh.iter_triangles()
+            .map(|shape| {
+                let shape = Arc::new(shape);
+                let light = graphics_state.area_light.clone()
+                    .map(|builder| builder.create(shape.clone()));
+                let light = light.map(|l| Arc::new(l));
+                let material = graphics_state.material.clone();
+                let prim = GeometricPrimitive {
+                    shape,
+                    material,
+                    light
+                };
+                Box::new(prim) as Box<dyn Primitive>
+            })
+        );
+    }
+
+    fn named_material(&self, name: &str) -> Result<Arc<dyn Material>, PbrtEvalError> {
+        self.named_materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| PbrtEvalError::MaterialError { expected: name.to_string() })
+    }
+
+    fn material(&mut self, name: &str, mut params: ParamSet) -> Result<Arc<dyn Material>, PbrtEvalError> {
+        let shading_map = make_shading_map(&mut params, &self.ctx)?;
         let material: Arc<dyn Material> = match name {
             "matte" => {
                 Arc::new(make_matte(params, &self.ctx)?)
@@ -337,11 +536,31 @@ impl PbrtSceneBuilder {
             "plastic" => {
                 Arc::new(make_plastic_material(params, &self.ctx)?)
             }
+            "substrate" => {
+                Arc::new(make_substrate_material(params, &self.ctx)?)
+            }
+            "uber" => {
+                Arc::new(make_uber_material(params, &self.ctx)?)
+            }
+            "translucent" => {
+                Arc::new(make_translucent_material(params, &self.ctx)?)
+            }
+            "disney" => {
+                Arc::new(make_disney_material(params, &self.ctx)?)
+            }
+            "mix" => {
+                let m1 = self.named_material(&params.get_one::<String>("namedmaterial1")?)?;
+                let m2 = self.named_material(&params.get_one::<String>("namedmaterial2")?)?;
+                Arc::new(make_mix_material(params, &self.ctx, m1, m2)?)
+            }
             _ => {
                 return Err(PbrtEvalError::UnknownName(name.to_string()))
             }
         };
-        Ok(material)
+        Ok(match shading_map {
+            Some(map) => Arc::new(ShadingMapped::new(map, material)),
+            None => material,
+        })
     }
 
     fn set_current_material(&mut self, mat: Arc<dyn Material>) {
@@ -410,6 +629,10 @@ pub struct PbrtHeader {
     camera_tf: Transform,
     sampler_params: ParamSet,
     pub film_params: ParamSet,
+    filter_params: ParamSet,
+    integrator_params: ParamSet,
+    named_coordinate_systems: NamedCoordinateSystems,
+    split_method: SplitMethod,
 }
 
 impl PbrtHeader {
@@ -419,7 +642,11 @@ impl PbrtHeader {
             camera_params: ParamSet::new(),
             camera_tf: Transform::identity(),
             sampler_params: Default::default(),
-            film_params: Default::default()
+            film_params: Default::default(),
+            filter_params: Default::default(),
+            integrator_params: Default::default(),
+            named_coordinate_systems: Default::default(),
+            split_method: SplitMethod::default(),
         }
     }
 
@@ -465,7 +692,14 @@ impl PbrtHeader {
         }
     }
 
-    pub fn make_sampler(&mut self, override_samples: Option<usize>) -> Result<RandomSampler, PbrtEvalError> {
+    /// The pixels of the full image, which the global samplers spread their sequences over.
+    fn sample_bounds(&self) -> Bounds2i {
+        let xres = *self.film_params.get_one_ref("xresolution").unwrap_or(&640);
+        let yres = *self.film_params.get_one_ref("yresolution").unwrap_or(&480);
+        Bounds2i::with_bounds(Point2i::new(0, 0), Point2i::new(xres, yres))
+    }
+
+    pub fn make_sampler(&mut self, override_samples: Option<usize>) -> Result<Box<dyn Sampler>, PbrtEvalError> {
         let name: String = self.sampler_params.get_one("name")?;
         let spp = override_samples.unwrap_or_else(|| {
             self.sampler_params.get_one("pixelsamples").unwrap_or(16) as usize
@@ -473,18 +707,91 @@ impl PbrtHeader {
         match name.as_ref() {
             "random" => {
                 let sampler = RandomSampler::new_with_seed(spp, 0);
-                Ok(sampler)
+                Ok(Box::new(sampler))
+            },
+            "stratified" => {
+                let jitter = self.sampler_params.get_one("jitter").unwrap_or(true);
+                let (x_samples, y_samples) = match override_samples {
+                    // Split the requested sample count into as square a grid as possible,
+                    // rounding up rather than dropping samples.
+                    Some(spp) => {
+                        let x = (spp as Float).sqrt().ceil().max(1.0) as usize;
+                        let y = ((spp + x - 1) / x).max(1);
+                        if x * y != spp {
+                            tracing::warn!("Stratified sampler rounding {} samples per pixel up to {}", spp, x * y);
+                        }
+                        (x, y)
+                    },
+                    None => (
+                        self.sampler_params.get_one("xsamples").unwrap_or(4) as usize,
+                        self.sampler_params.get_one("ysamples").unwrap_or(4) as usize,
+                    )
+                };
+                let n_dimensions = self.sampler_params.get_one("dimensions").unwrap_or(4) as usize;
+                let sampler = StratifiedSampler::new_with_seed(x_samples, y_samples, jitter, n_dimensions, 0);
+                Ok(Box::new(sampler))
+            },
+            "halton" => {
+                let sample_at_center = self.sampler_params.get_one("samplepixelcenter").unwrap_or(false);
+                Ok(Box::new(HaltonSampler::new(spp, self.sample_bounds(), sample_at_center)))
+            },
+            "sobol" => {
+                if !spp.is_power_of_two() {
+                    tracing::warn!("Sobol sampler rounding {} samples per pixel up to {}", spp, spp.next_power_of_two());
+                }
+                Ok(Box::new(SobolSampler::new(spp, self.sample_bounds())))
+            },
+            "02sequence" | "lowdiscrepancy" => {
+                if !spp.is_power_of_two() {
+                    tracing::warn!("(0, 2)-sequence sampler rounding {} samples per pixel up to {}", spp, spp.next_power_of_two());
+                }
+                let n_dimensions = self.sampler_params.get_one("dimensions").unwrap_or(4) as usize;
+                Ok(Box::new(ZeroTwoSequenceSampler::new_with_seed(spp, n_dimensions, 0)))
             },
             name @ _ => {
                 tracing::warn!("Unsupported sampler {}, falling back to random", name);
                 let sampler = RandomSampler::new_with_seed(spp, 0);
-                Ok(sampler)
+                Ok(Box::new(sampler))
             }
             // _ => Err(PbrtEvalError::UnknownName(name))
         }
     }
 
-    pub fn make_film(&mut self) -> Result<Film<BoxFilter>, PbrtEvalError> {
+    /// Creates the pixel reconstruction filter given by the `PixelFilter` statement, defaulting
+    /// to a box filter if there wasn't one.
+    pub fn make_filter(&mut self) -> Result<Box<dyn Filter>, PbrtEvalError> {
+        let name: String = self.filter_params.get_one("name").unwrap_or_else(|_| "box".to_string());
+        let default_width = match name.as_ref() {
+            "box" => 0.5,
+            "gaussian" | "mitchell" | "triangle" => 2.0,
+            "sinc" => 4.0,
+            _ => return Err(PbrtEvalError::UnknownName(name)),
+        };
+        let xwidth = self.filter_params.get_one("xwidth").unwrap_or(default_width);
+        let ywidth = self.filter_params.get_one("ywidth").unwrap_or(default_width);
+        let radius = Vec2f::new(xwidth, ywidth);
+
+        let filter: Box<dyn Filter> = match name.as_ref() {
+            "box" => Box::new(BoxFilter::new(radius)),
+            "gaussian" => {
+                let alpha = self.filter_params.get_one("alpha").unwrap_or(2.0);
+                Box::new(GaussianFilter::new(radius, alpha))
+            },
+            "mitchell" => {
+                let b = self.filter_params.get_one("B").unwrap_or(1.0 / 3.0);
+                let c = self.filter_params.get_one("C").unwrap_or(1.0 / 3.0);
+                Box::new(MitchellFilter::new(radius, b, c))
+            },
+            "sinc" => {
+                let tau = self.filter_params.get_one("tau").unwrap_or(3.0);
+                Box::new(LanczosSincFilter::new(radius, tau))
+            },
+            _ => Box::new(TriangleFilter::new(radius)),
+        };
+        Ok(filter)
+    }
+
+    pub fn make_film(&mut self) -> Result<Film<Box<dyn Filter>>, PbrtEvalError> {
         let xres = *self.film_params.get_one_ref("xresolution").unwrap_or(&640);
         let yres = *self.film_params.get_one_ref("yresolution").unwrap_or(&480);
 
@@ -494,7 +801,7 @@ impl PbrtHeader {
             Point2f::new(cropwindow[1], cropwindow[3])
         );
 
-        let filter = BoxFilter::default();
+        let filter = self.make_filter()?;
         let film = Film::new(
             Point2i::new(xres, yres),
             cropwindow,
@@ -504,16 +811,43 @@ impl PbrtHeader {
         Ok(film)
     }
 
+    /// Creates the radiance integrator given by the `Integrator` statement, defaulting to a
+    /// path tracer if there wasn't one.
+    pub fn make_integrator(&mut self) -> Result<Box<dyn IntegratorRadiance>, PbrtEvalError> {
+        let name: String = self.integrator_params.get_one("name").unwrap_or_else(|_| "path".to_string());
+        let max_depth = self.integrator_params.get_one::<i32>("maxdepth").unwrap_or(5).max(0) as u16;
+        match name.as_ref() {
+            "path" => {
+                let rr_threshold = self.integrator_params.get_one("rrthreshold").unwrap_or(1.0);
+                Ok(Box::new(PathIntegrator::new(max_depth, rr_threshold)))
+            },
+            "directlighting" => {
+                let strategy: String = self.integrator_params.get_one("strategy").unwrap_or_else(|_| "all".to_string());
+                let strategy = match strategy.as_ref() {
+                    "all" => LightStrategy::UniformSampleAll,
+                    "one" => LightStrategy::UniformSampleOne,
+                    _ => return Err(PbrtEvalError::UnknownName(strategy)),
+                };
+                Ok(Box::new(DirectLightingIntegrator::new(strategy, max_depth)))
+            },
+            "whitted" => {
+                Ok(Box::new(WhittedIntegrator { max_depth }))
+            },
+            _ => Err(PbrtEvalError::UnknownName(name)),
+        }
+    }
+
     pub fn exec_stmt(&mut self, stmt: parser::HeaderStmt) -> Result<(), PbrtEvalError> {
         match stmt {
             HeaderStmt::Transform(tf_stmt) => {
-                self.tf = eval_transform_stmt(tf_stmt, &self.tf)?;
+                self.tf = eval_transform_stmt(tf_stmt, &self.tf, &mut self.named_coordinate_systems)?;
             },
             HeaderStmt::Camera(name, params) => {
                 let mut params = Self::make_param_set(params);
                 params.put_one("name".to_string(), vec![name]);
                 self.camera_params = params;
                 self.camera_tf = self.tf;
+                self.named_coordinate_systems.insert("camera".to_string(), self.camera_tf.inverse());
             },
             HeaderStmt::Sampler(name, params) => {
                 let mut params = Self::make_param_set(params);
@@ -525,9 +859,26 @@ impl PbrtHeader {
                 params.put_one("name".to_string(), vec![name]);
                 self.film_params = params;
             },
-            HeaderStmt::Filter(_, _) => {},
-            HeaderStmt::Integrator(_, _) => {},
-            HeaderStmt::Accelerator(_, _) => {},
+            HeaderStmt::Filter(name, params) => {
+                let mut params = Self::make_param_set(params);
+                params.put_one("name".to_string(), vec![name]);
+                self.filter_params = params;
+            },
+            HeaderStmt::Integrator(name, params) => {
+                let mut params = Self::make_param_set(params);
+                params.put_one("name".to_string(), vec![name]);
+                self.integrator_params = params;
+            },
+            HeaderStmt::Accelerator(name, params) => {
+                if &*name != "bvh" {
+                    tracing::warn!("Unsupported accelerator {}, using bvh", name);
+                }
+                let mut params = Self::make_param_set(params);
+                if let Ok(method) = params.get_one::<String>("splitmethod") {
+                    self.split_method = SplitMethod::from_name(&method)
+                        .ok_or_else(|| PbrtEvalError::UnknownName(method))?;
+                }
+            },
         };
         Ok(())
     }
@@ -559,14 +910,18 @@ impl PbrtHeader {
             parser::ParamVal::SpectrumRgb(v) => {
                 ParamVal::Spectrum(v.into_iter().map(|s| s.into()).collect::<Vec<Spectrum>>().into())
             },
-            parser::ParamVal::SpectrumXyz(_) => unimplemented!(),
-            parser::ParamVal::SpectrumSampled(_) => unimplemented!(),
-            parser::ParamVal::SpectrumBlackbody(_) => unimplemented!(),
+            parser::ParamVal::SpectrumXyz(v) => convert_xyz(v),
+            parser::ParamVal::SpectrumSampled(v) => convert_sampled(v),
+            parser::ParamVal::SpectrumBlackbody(v) => convert_blackbody(v),
         }
     }
 }
 
-fn eval_transform_stmt(stmt: parser::TransformStmt, current_tf: &Transform) -> Result<Transform, PbrtEvalError> {
+fn eval_transform_stmt(
+    stmt: parser::TransformStmt,
+    current_tf: &Transform,
+    named_coordinate_systems: &mut NamedCoordinateSystems,
+) -> Result<Transform, PbrtEvalError> {
     let tf = match stmt {
         parser::TransformStmt::Identity => {
             Transform::identity()
@@ -588,11 +943,15 @@ fn eval_transform_stmt(stmt: parser::TransformStmt, current_tf: &Transform) -> R
             let up = Vec3f::new(m[6], m[7], m[8]);
             *current_tf * Transform::look_at(eye, look_at, up)
         },
-        parser::TransformStmt::CoordinateSystem(_) => {
-            unimplemented!()
+        parser::TransformStmt::CoordinateSystem(name) => {
+            named_coordinate_systems.insert(name.to_string(), *current_tf);
+            *current_tf
         },
-        parser::TransformStmt::CoordSysTransform(_) => {
-            unimplemented!()
+        parser::TransformStmt::CoordSysTransform(name) => {
+            match named_coordinate_systems.get(&*name) {
+                Some(tf) => *tf,
+                None => return Err(PbrtEvalError::UnknownName(name.to_string())),
+            }
         },
         parser::TransformStmt::Transform(m) => {
             Transform::from_flat(*m)
@@ -606,6 +965,132 @@ fn eval_transform_stmt(stmt: parser::TransformStmt, current_tf: &Transform) -> R
 
 
 
+fn convert_xyz(v: Vec<[Float; 3]>) -> ParamVal {
+    ParamVal::Spectrum(v.into_iter().map(Spectrum::from_xyz).collect::<Vec<Spectrum>>().into())
+}
+
+/// A sampled spectrum is a single distribution given by (wavelength, value) pairs.
+fn convert_sampled(v: Vec<[Float; 2]>) -> ParamVal {
+    let samples = v.into_iter().map(|[lambda, val]| (lambda, val)).collect::<Vec<_>>();
+    ParamVal::Spectrum(vec![Spectrum::from_sampled(&samples)].into())
+}
+
+/// Each (temperature, scale) pair gives one normalized blackbody spectrum.
+fn convert_blackbody(v: Vec<[Float; 2]>) -> ParamVal {
+    let spectra = v.into_iter()
+        .map(|[temp, scale]| Spectrum::from_blackbody(temp, scale))
+        .collect::<Vec<Spectrum>>();
+    ParamVal::Spectrum(spectra.into())
+}
+
 fn convert_vec<T, U: From<T>>(v: Vec<T>) -> Vec<U> {
     v.into_iter().map(Into::into).collect()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn include_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testscenes/include")
+    }
+
+    #[test]
+    fn test_include_relative_to_base_path() {
+        let mut builder = PbrtSceneBuilder::new(include_dir());
+        builder.exec_stmt(WorldStmt::Include("parts/sphere.pbrt".into())).unwrap();
+        assert_eq!(builder.primitives.len(), 1);
+        assert!(builder.include_stack.is_empty());
+        // State pushed inside the included file doesn't leak out of it.
+        assert_eq!(builder.graphics_state.len(), 1);
+        assert_eq!(builder.tf_state.len(), 1);
+    }
+
+    #[test]
+    fn test_nested_include() {
+        let mut builder = PbrtSceneBuilder::new(include_dir());
+        // The file includes "parts/sphere.pbrt", which is also relative to the base path.
+        builder.exec_stmt(WorldStmt::Include("parts/nested.pbrt".into())).unwrap();
+        assert_eq!(builder.primitives.len(), 1);
+        assert!(builder.include_stack.is_empty());
+        assert_eq!(builder.graphics_state.len(), 1);
+        assert_eq!(builder.tf_state.len(), 1);
+    }
+
+    fn assert_include_cycle(file: &str) {
+        let mut builder = PbrtSceneBuilder::new(include_dir());
+        match builder.exec_stmt(WorldStmt::Include(file.into())) {
+            Err(PbrtEvalError::IncludeError(msg)) => assert!(msg.contains("cycle"), "unexpected error: {}", msg),
+            other => panic!("expected an include cycle error, got {:?}", other.err()),
+        }
+        assert!(builder.include_stack.is_empty());
+    }
+
+    #[test]
+    fn test_include_self() {
+        assert_include_cycle("self.pbrt");
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        assert_include_cycle("cycle_a.pbrt");
+    }
+
+    #[test]
+    fn test_spd_relative_to_base_path() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testscenes/spd");
+        let builder = PbrtSceneBuilder::new(dir);
+        let val = builder.convert_param_val(parser::ParamVal::String(vec!["constant.spd".into()])).unwrap();
+        let mut params = ParamSet::new();
+        params.put_one("Kd".to_string(), val);
+        let kd: Spectrum = params.get_one("Kd").unwrap();
+        assert!((kd.to_xyz()[1] - 2.0).abs() < 1e-3);
+    }
+
+    fn assert_same_transform(a: &Transform, b: &Transform) {
+        for &p in &[Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, -2.0, 3.0)] {
+            approx::assert_abs_diff_eq!(a.transform(p), b.transform(p), epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_named_coordinate_system() {
+        let mut named = NamedCoordinateSystems::new();
+        let tf = eval_transform_stmt(TransformStmt::Translate([1.0, 2.0, 3.0].into()), &Transform::identity(), &mut named).unwrap();
+        eval_transform_stmt(TransformStmt::CoordinateSystem("saved".into()), &tf, &mut named).unwrap();
+        let scaled = eval_transform_stmt(TransformStmt::Scale([2.0, 2.0, 2.0].into()), &tf, &mut named).unwrap();
+
+        let restored = eval_transform_stmt(TransformStmt::CoordSysTransform("saved".into()), &scaled, &mut named).unwrap();
+        assert_same_transform(&restored, &tf);
+    }
+
+    #[test]
+    fn test_camera_coordinate_system() {
+        let mut header = PbrtHeader::new();
+        header.exec_stmt(HeaderStmt::Transform(TransformStmt::Translate([0.0, 0.0, 5.0].into()))).unwrap();
+        header.exec_stmt(HeaderStmt::Camera("perspective".into(), vec![])).unwrap();
+        header.exec_stmt(HeaderStmt::Transform(TransformStmt::Identity)).unwrap();
+
+        header.exec_stmt(HeaderStmt::Transform(TransformStmt::CoordSysTransform("camera".into()))).unwrap();
+        assert_same_transform(&header.tf, &header.camera_tf.inverse());
+
+        // The world block sees the same coordinate systems.
+        let mut builder = PbrtSceneBuilder::from_header(include_dir(), &header);
+        builder.exec_stmt(WorldStmt::Transform(TransformStmt::CoordSysTransform("camera".into()))).unwrap();
+        assert_same_transform(builder.tf_state.last().unwrap(), &header.camera_tf.inverse());
+    }
+
+    #[test]
+    fn test_unknown_coordinate_system() {
+        let mut named = NamedCoordinateSystems::new();
+        let result = eval_transform_stmt(TransformStmt::CoordSysTransform("missing".into()), &Transform::identity(), &mut named);
+        assert!(matches!(result, Err(PbrtEvalError::UnknownName(name)) if name == "missing"));
+    }
+
+    #[test]
+    fn test_missing_include() {
+        let mut builder = PbrtSceneBuilder::new(include_dir());
+        let result = builder.exec_stmt(WorldStmt::Include("parts/missing.pbrt".into()));
+        assert!(matches!(result, Err(PbrtEvalError::IncludeError(_))));
+    }
+}
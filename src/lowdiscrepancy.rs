@@ -0,0 +1,494 @@
+//! Building blocks for the low-discrepancy samplers: radical inverses for the Halton sequence
+//! and generator matrices for the Sobol' sequence.
+
+use crate::{Float, Point2f, Point2i};
+use crate::math::ONE_MINUS_EPSILON;
+use once_cell::sync::Lazy;
+use rand::Rng;
+
+pub const PRIME_TABLE_SIZE: usize = 1000;
+
+/// The first `PRIME_TABLE_SIZE` primes, used as the bases of the Halton sequence.
+pub static PRIMES: Lazy<Vec<u64>> = Lazy::new(|| {
+    let mut primes: Vec<u64> = Vec::with_capacity(PRIME_TABLE_SIZE);
+    let mut candidate = 2;
+    while primes.len() < PRIME_TABLE_SIZE {
+        if primes.iter().take_while(|&&p| p * p <= candidate).all(|&p| candidate % p != 0) {
+            primes.push(candidate);
+        }
+        candidate += 1;
+    }
+    primes
+});
+
+/// For each prime in `PRIMES`, the sum of all of the primes before it. This is the offset of
+/// that base's digit permutation in the table from `compute_radical_inverse_permutations`.
+pub static PRIME_SUMS: Lazy<Vec<u64>> = Lazy::new(|| {
+    PRIMES.iter()
+        .scan(0, |sum, &p| {
+            let offset = *sum;
+            *sum += p;
+            Some(offset)
+        })
+        .collect()
+});
+
+/// Reflects the digits of `a` in the base given by the `base_index`th prime about the decimal
+/// point, giving the `a`th value of the van der Corput sequence in that base.
+pub fn radical_inverse(base_index: usize, a: u64) -> Float {
+    let base = PRIMES[base_index];
+    if base == 2 {
+        return ((a.reverse_bits() as f64 * 0.5f64.powi(64)) as Float).min(ONE_MINUS_EPSILON);
+    }
+
+    let inv_base = 1.0 / base as f64;
+    let mut reversed_digits: u64 = 0;
+    let mut inv_base_n = 1.0;
+    let mut a = a;
+    while a != 0 {
+        let next = a / base;
+        let digit = a - next * base;
+        reversed_digits = reversed_digits * base + digit;
+        inv_base_n *= inv_base;
+        a = next;
+    }
+    ((reversed_digits as f64 * inv_base_n) as Float).min(ONE_MINUS_EPSILON)
+}
+
+/// Given the reversed digits of a radical inverse as an integer, recovers the original index.
+pub fn inverse_radical_inverse(base: u64, inverse: u64, n_digits: u32) -> u64 {
+    let mut inverse = inverse;
+    let mut index = 0;
+    for _ in 0..n_digits {
+        let digit = inverse % base;
+        inverse /= base;
+        index = index * base + digit;
+    }
+    index
+}
+
+/// Like `radical_inverse`, but each digit is first mapped through `perm`, a permutation of the
+/// digits of the base. This breaks up the correlation between higher Halton dimensions.
+pub fn scrambled_radical_inverse(base_index: usize, a: u64, perm: &[u16]) -> Float {
+    let base = PRIMES[base_index];
+    let inv_base = 1.0 / base as f64;
+    let mut reversed_digits: u64 = 0;
+    let mut inv_base_n = 1.0;
+    let mut a = a;
+    while a != 0 {
+        let next = a / base;
+        let digit = a - next * base;
+        reversed_digits = reversed_digits * base + perm[digit as usize] as u64;
+        inv_base_n *= inv_base;
+        a = next;
+    }
+
+    // The infinite tail of zero digits is permuted too, which sums to a geometric series.
+    let tail = inv_base * perm[0] as f64 / (1.0 - inv_base);
+    ((inv_base_n * (reversed_digits as f64 + tail)) as Float).min(ONE_MINUS_EPSILON)
+}
+
+/// Generates a random digit permutation for each of the prime bases, stored one after another.
+pub fn compute_radical_inverse_permutations(rng: &mut impl Rng) -> Vec<u16> {
+    let mut perms = Vec::with_capacity(PRIMES.iter().sum::<u64>() as usize);
+    for &base in PRIMES.iter() {
+        let start = perms.len();
+        perms.extend(0..base as u16);
+        crate::sampling::shuffle(&mut perms[start..], rng);
+    }
+    perms
+}
+
+fn extended_gcd(a: u64, b: u64) -> (i64, i64) {
+    if b == 0 {
+        return (1, 0);
+    }
+    let d = (a / b) as i64;
+    let (xp, yp) = extended_gcd(b, a % b);
+    (yp, xp - d * yp)
+}
+
+/// Finds `x` such that `a * x` is congruent to 1 mod `n`.
+pub fn multiplicative_inverse(a: u64, n: u64) -> u64 {
+    let (x, _) = extended_gcd(a, n);
+    x.rem_euclid(n as i64) as u64
+}
+
+/// Generator matrix of the first Sobol' dimension, which is the base 2 van der Corput sequence.
+pub const VAN_DER_CORPUT_MATRIX: [u32; 32] = [
+    0x80000000, 0x40000000, 0x20000000, 0x10000000, 0x08000000, 0x04000000, 0x02000000, 0x01000000,
+    0x00800000, 0x00400000, 0x00200000, 0x00100000, 0x00080000, 0x00040000, 0x00020000, 0x00010000,
+    0x00008000, 0x00004000, 0x00002000, 0x00001000, 0x00000800, 0x00000400, 0x00000200, 0x00000100,
+    0x00000080, 0x00000040, 0x00000020, 0x00000010, 0x00000008, 0x00000004, 0x00000002, 0x00000001,
+];
+
+/// Generator matrix of the second Sobol' dimension. Together with `VAN_DER_CORPUT_MATRIX` this
+/// generates a (0, 2)-sequence.
+pub const SOBOL_MATRIX_1: [u32; 32] = [
+    0x80000000, 0xc0000000, 0xa0000000, 0xf0000000, 0x88000000, 0xcc000000, 0xaa000000, 0xff000000,
+    0x80800000, 0xc0c00000, 0xa0a00000, 0xf0f00000, 0x88880000, 0xcccc0000, 0xaaaa0000, 0xffff0000,
+    0x80008000, 0xc000c000, 0xa000a000, 0xf000f000, 0x88008800, 0xcc00cc00, 0xaa00aa00, 0xff00ff00,
+    0x80808080, 0xc0c0c0c0, 0xa0a0a0a0, 0xf0f0f0f0, 0x88888888, 0xcccccccc, 0xaaaaaaaa, 0xffffffff,
+];
+
+fn to_unit_float(v: u32) -> Float {
+    (v as Float * 2.0f32.powi(-32)).min(ONE_MINUS_EPSILON)
+}
+
+/// The number of columns of each Sobol' generator matrix, which limits sample indices to this
+/// many bits.
+pub const SOBOL_MATRIX_SIZE: usize = 52;
+
+/// Initial direction numbers `m_1..m_s` for the Sobol' dimensions after the first, from Joe
+/// and Kuo, "Constructing Sobol' sequences with better two-dimensional projections"
+/// (new-joe-kuo-6.21201). Entry `i` goes with the `i`th primitive polynomial.
+const JOE_KUO_DIRECTIONS: [&[u32]; 36] = [
+    &[1], &[1, 3], &[1, 3, 1], &[1, 1, 1], &[1, 1, 3, 3], &[1, 3, 5, 13],
+    &[1, 1, 5, 5, 17], &[1, 1, 5, 5, 5], &[1, 1, 7, 11, 19], &[1, 1, 5, 1, 1], &[1, 1, 1, 3, 11],
+    &[1, 3, 5, 5, 31], &[1, 3, 3, 9, 7, 49], &[1, 1, 1, 15, 21, 21], &[1, 3, 1, 13, 27, 49],
+    &[1, 1, 1, 15, 7, 5], &[1, 3, 1, 15, 13, 25], &[1, 1, 5, 5, 19, 61],
+    &[1, 3, 7, 11, 23, 15, 103], &[1, 3, 7, 13, 13, 15, 69], &[1, 1, 3, 13, 7, 35, 63],
+    &[1, 3, 5, 9, 1, 25, 53], &[1, 3, 1, 13, 9, 35, 107], &[1, 3, 1, 5, 27, 61, 31],
+    &[1, 1, 5, 11, 19, 41, 61], &[1, 3, 5, 3, 3, 13, 69], &[1, 1, 7, 13, 1, 19, 1],
+    &[1, 3, 7, 5, 13, 19, 59], &[1, 1, 3, 9, 25, 29, 41], &[1, 3, 5, 13, 23, 1, 55],
+    &[1, 3, 7, 3, 13, 59, 17], &[1, 3, 1, 3, 5, 53, 69], &[1, 1, 5, 5, 23, 33, 13],
+    &[1, 1, 7, 7, 1, 61, 123], &[1, 1, 7, 9, 13, 61, 49], &[1, 3, 3, 5, 3, 55, 33],
+];
+
+/// The number of Sobol' dimensions with tabulated direction numbers, counting the first.
+/// Samplers have to fall back to something else for any further dimensions.
+pub const NUM_SOBOL_DIMENSIONS: usize = 1 + JOE_KUO_DIRECTIONS.len();
+
+/// Generator matrices for the first `NUM_SOBOL_DIMENSIONS` dimensions of the Sobol' sequence,
+/// keeping the top 32 bits of each column. The first dimension is the van der Corput sequence
+/// and each later one comes from the next primitive polynomial over GF(2).
+pub static SOBOL_MATRICES_32: Lazy<Vec<[u32; SOBOL_MATRIX_SIZE]>> = Lazy::new(|| {
+    let mut matrices = Vec::with_capacity(NUM_SOBOL_DIMENSIONS);
+    let mut van_der_corput = [0; SOBOL_MATRIX_SIZE];
+    van_der_corput[..32].copy_from_slice(&VAN_DER_CORPUT_MATRIX);
+    matrices.push(van_der_corput);
+
+    for ((degree, a), m) in primitive_polynomials().zip(JOE_KUO_DIRECTIONS.iter()) {
+        matrices.push(sobol_generator_matrix(degree, a, m));
+    }
+    matrices
+});
+
+/// Builds the generator matrix for the primitive polynomial of the given `degree` whose inner
+/// coefficients are the bits of `a`, starting from the direction numbers `m`.
+fn sobol_generator_matrix(degree: u32, a: u32, m: &[u32]) -> [u32; SOBOL_MATRIX_SIZE] {
+    let s = degree as usize;
+    // Direction numbers as fixed point values with SOBOL_MATRIX_SIZE bits.
+    let mut v = [0u64; SOBOL_MATRIX_SIZE];
+    for i in 0..s {
+        v[i] = (m[i] as u64) << (SOBOL_MATRIX_SIZE - 1 - i);
+    }
+    for i in s..SOBOL_MATRIX_SIZE {
+        v[i] = v[i - s] ^ (v[i - s] >> s);
+        for k in 1..s {
+            if (a >> (s - 1 - k)) & 1 != 0 {
+                v[i] ^= v[i - k];
+            }
+        }
+    }
+
+    let mut c = [0; SOBOL_MATRIX_SIZE];
+    for (c, v) in c.iter_mut().zip(v.iter()) {
+        *c = (v >> (SOBOL_MATRIX_SIZE - 32)) as u32;
+    }
+    c
+}
+
+/// Multiplies two polynomials over GF(2), modulo `poly` of the given degree.
+fn gf2_mul_mod(a: u32, b: u32, poly: u32, degree: u32) -> u32 {
+    let (mut a, mut b, mut r) = (a, b, 0);
+    while b != 0 {
+        if b & 1 != 0 {
+            r ^= a;
+        }
+        b >>= 1;
+        a <<= 1;
+        if a & (1 << degree) != 0 {
+            a ^= poly;
+        }
+    }
+    r
+}
+
+/// Computes `x^n` modulo `poly`.
+fn gf2_pow_x(n: u32, poly: u32, degree: u32) -> u32 {
+    let mut base = if degree == 1 { 2 ^ poly } else { 2 };
+    let (mut n, mut r) = (n, 1);
+    while n != 0 {
+        if n & 1 != 0 {
+            r = gf2_mul_mod(r, base, poly, degree);
+        }
+        base = gf2_mul_mod(base, base, poly, degree);
+        n >>= 1;
+    }
+    r
+}
+
+/// Whether `x^degree + a_1 x^(degree - 1) + ... + a_(degree - 1) x + 1`, with the inner
+/// coefficients given by the bits of `a`, is primitive: `x` must have order `2^degree - 1`.
+fn is_primitive(degree: u32, a: u32) -> bool {
+    let poly = (1 << degree) | (a << 1) | 1;
+    let order = (1u32 << degree) - 1;
+    if gf2_pow_x(order, poly, degree) != 1 {
+        return false;
+    }
+
+    let mut n = order;
+    let mut q = 2;
+    while q * q <= n {
+        if n % q == 0 {
+            if gf2_pow_x(order / q, poly, degree) == 1 {
+                return false;
+            }
+            while n % q == 0 {
+                n /= q;
+            }
+        }
+        q += 1;
+    }
+    n == 1 || gf2_pow_x(order / n, poly, degree) != 1
+}
+
+/// The primitive polynomials over GF(2) as `(degree, a)` pairs, in the order of increasing
+/// degree and then `a` that the Sobol' dimensions use.
+fn primitive_polynomials() -> impl Iterator<Item=(u32, u32)> {
+    (1..).flat_map(|degree| {
+        (0..1 << (degree - 1))
+            .filter(move |&a| is_primitive(degree, a))
+            .map(move |a| (degree, a))
+    })
+}
+
+/// The `a`th sample of the given Sobol' dimension, randomized by xor-ing with `scramble`. Xor
+/// scrambling preserves the stratification of the sequence.
+pub fn sobol_sample(a: u64, dimension: usize, scramble: u32) -> Float {
+    let c = &SOBOL_MATRICES_32[dimension];
+    let mut v = scramble;
+    let mut a = a;
+    let mut i = 0;
+    while a != 0 && i < SOBOL_MATRIX_SIZE {
+        if a & 1 != 0 {
+            v ^= c[i];
+        }
+        a >>= 1;
+        i += 1;
+    }
+    to_unit_float(v)
+}
+
+/// The largest image resolution, as a power of two, that `sobol_interval_to_index` supports.
+/// The pixel takes up twice this many bits of the sample index.
+const MAX_SOBOL_LOG2_RESOLUTION: u32 = 24;
+
+/// The top `m` bits of the first two Sobol' dimensions for index bit `j`, packed as `x << m | y`.
+fn sobol_interval_column(m: u32, j: usize) -> u64 {
+    let top_bits = |v: u32| (v >> (32 - m)) as u64;
+    (top_bits(SOBOL_MATRICES_32[0][j]) << m) | top_bits(SOBOL_MATRICES_32[1][j])
+}
+
+/// For each resolution `2^m`, the inverse of the map from the low `2m` bits of a sample index
+/// to the pixel its first two dimensions fall in. Entry `k` is the combination of index bits
+/// that sets only bit `k` of the pixel, packed as `x << m | y`.
+static SOBOL_INTERVAL_INVERSES: Lazy<Vec<Vec<u64>>> = Lazy::new(|| {
+    (1..=MAX_SOBOL_LOG2_RESOLUTION).map(|m| {
+        let n = 2 * m as usize;
+        // Gauss-Jordan elimination over GF(2), tracking which index bits make up each row.
+        let mut rows: Vec<(u64, u64)> = (0..n).map(|j| (sobol_interval_column(m, j), 1u64 << j)).collect();
+        for bit in 0..n {
+            let pivot = (bit..n).find(|&i| rows[i].0 & (1 << bit) != 0)
+                .expect("The first two Sobol' dimensions should form a (0, 2)-sequence");
+            rows.swap(bit, pivot);
+            let (pixel, index) = rows[bit];
+            for (i, row) in rows.iter_mut().enumerate() {
+                if i != bit && row.0 & (1 << bit) != 0 {
+                    row.0 ^= pixel;
+                    row.1 ^= index;
+                }
+            }
+        }
+        rows.into_iter().map(|(_, index)| index).collect()
+    }).collect()
+});
+
+/// Finds the index of the `frame`th sample of the Sobol' sequence whose first two dimensions,
+/// scaled up to a `2^log2_resolution` square image, fall in pixel `p` (Grünschloß et al.,
+/// "Enumerating Quasi-Monte Carlo Point Sequences in Elementary Intervals").
+pub fn sobol_interval_to_index(log2_resolution: u32, frame: u64, p: Point2i) -> u64 {
+    let m = log2_resolution;
+    if m == 0 {
+        return frame;
+    }
+    assert!(m <= MAX_SOBOL_LOG2_RESOLUTION, "Image too large for the Sobol' sampler");
+
+    // The frame gives the high bits of the index, which also shift the pixel the low bits
+    // have to select.
+    let n = 2 * m as usize;
+    let mut pixel = ((p.x as u64) << m) | p.y as u64;
+    let mut high = frame;
+    for j in n..SOBOL_MATRIX_SIZE {
+        if high == 0 {
+            break;
+        }
+        if high & 1 != 0 {
+            pixel ^= sobol_interval_column(m, j);
+        }
+        high >>= 1;
+    }
+
+    let low = SOBOL_INTERVAL_INVERSES[m as usize - 1].iter()
+        .enumerate()
+        .filter(|&(k, _)| pixel & (1 << k) != 0)
+        .fold(0, |low, (_, &index)| low ^ index);
+    (frame << n) | low
+}
+
+/// Fills `samples` with the first `samples.len()` points of the sequence generated by `c`,
+/// visiting them in Gray code order so each point only needs a single xor.
+pub fn gray_code_sample_1d(c: &[u32; 32], scramble: u32, samples: &mut [Float]) {
+    let mut v = scramble;
+    for (i, sample) in samples.iter_mut().enumerate() {
+        *sample = to_unit_float(v);
+        v ^= c[(i + 1).trailing_zeros() as usize];
+    }
+}
+
+pub fn gray_code_sample_2d(c0: &[u32; 32], c1: &[u32; 32], scramble: (u32, u32), samples: &mut [Point2f]) {
+    let mut v = scramble;
+    for (i, sample) in samples.iter_mut().enumerate() {
+        *sample = Point2f::new(to_unit_float(v.0), to_unit_float(v.1));
+        let bit = (i + 1).trailing_zeros() as usize;
+        v.0 ^= c0[bit];
+        v.1 ^= c1[bit];
+    }
+}
+
+/// Shuffles `samples` as blocks of `block_len` consecutive values, keeping each block intact.
+fn shuffle_blocks<T>(samples: &mut [T], block_len: usize, rng: &mut impl Rng) {
+    let count = samples.len() / block_len;
+    for i in 0..count {
+        let other = rng.gen_range(i, count);
+        for k in 0..block_len {
+            samples.swap(i * block_len + k, other * block_len + k);
+        }
+    }
+}
+
+/// Generates `n_pixel_samples` groups of `n_per_pixel_sample` scrambled van der Corput samples.
+/// Each group is stratified on its own, and the groups are shuffled so that the samples used
+/// for different dimensions aren't correlated.
+pub fn van_der_corput(n_per_pixel_sample: usize, samples: &mut [Float], rng: &mut impl Rng) {
+    gray_code_sample_1d(&VAN_DER_CORPUT_MATRIX, rng.gen(), samples);
+    for group in samples.chunks_mut(n_per_pixel_sample) {
+        crate::sampling::shuffle(group, rng);
+    }
+    shuffle_blocks(samples, n_per_pixel_sample, rng);
+}
+
+/// The 2D counterpart of `van_der_corput`, using the first two Sobol' dimensions.
+pub fn sobol_2d(n_per_pixel_sample: usize, samples: &mut [Point2f], rng: &mut impl Rng) {
+    gray_code_sample_2d(&VAN_DER_CORPUT_MATRIX, &SOBOL_MATRIX_1, (rng.gen(), rng.gen()), samples);
+    for group in samples.chunks_mut(n_per_pixel_sample) {
+        crate::sampling::shuffle(group, rng);
+    }
+    shuffle_blocks(samples, n_per_pixel_sample, rng);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_radical_inverse() {
+        assert_abs_diff_eq!(radical_inverse(0, 1), 0.5);
+        assert_abs_diff_eq!(radical_inverse(0, 3), 0.75);
+        assert_abs_diff_eq!(radical_inverse(0, 6), 0.375);
+        assert_abs_diff_eq!(radical_inverse(1, 1), 1.0 / 3.0);
+        assert_abs_diff_eq!(radical_inverse(1, 5), 7.0 / 9.0);
+
+        // 5 in base 3 is 12, reversed is 21.
+        assert_eq!(inverse_radical_inverse(3, 7, 2), 5);
+    }
+
+    #[test]
+    fn test_scrambled_radical_inverse_identity_permutation() {
+        let identity: Vec<u16> = (0..3).collect();
+        for a in 0..20 {
+            assert_abs_diff_eq!(scrambled_radical_inverse(1, a, &identity), radical_inverse(1, a), epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_multiplicative_inverse() {
+        assert_eq!(multiplicative_inverse(3, 7), 5);
+        assert_eq!(multiplicative_inverse(27, 16), 3);
+    }
+
+    #[test]
+    fn test_sobol_2d_is_stratified() {
+        // The first 2^m points of a (0, 2)-sequence have one point in each of the 2^m
+        // elementary intervals of any shape.
+        let mut samples = vec![Point2f::new(0.0, 0.0); 16];
+        gray_code_sample_2d(&VAN_DER_CORPUT_MATRIX, &SOBOL_MATRIX_1, (0, 0), &mut samples);
+        for &(nx, ny) in &[(16, 1), (8, 2), (4, 4), (2, 8), (1, 16)] {
+            let mut cells: Vec<usize> = samples.iter()
+                .map(|p| (p.y * ny as Float) as usize * nx + (p.x * nx as Float) as usize)
+                .collect();
+            cells.sort();
+            assert_eq!(cells, (0..16).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_sobol_matrices() {
+        assert_eq!(SOBOL_MATRICES_32.len(), NUM_SOBOL_DIMENSIONS);
+        assert_eq!(&SOBOL_MATRICES_32[0][..32], &VAN_DER_CORPUT_MATRIX[..]);
+        assert_eq!(&SOBOL_MATRICES_32[1][..32], &SOBOL_MATRIX_1[..]);
+        assert_eq!(
+            &SOBOL_MATRICES_32[2][..8],
+            &[0x80000000, 0xc0000000, 0x60000000, 0x90000000, 0xe8000000, 0x5c000000, 0x8e000000, 0xc5000000]
+        );
+
+        // Every dimension is a (0, 1)-sequence on its own.
+        for dim in &[2, 10, NUM_SOBOL_DIMENSIONS - 1] {
+            let mut strata: Vec<usize> = (0..64).map(|a| (sobol_sample(a, *dim, 0) * 64.0) as usize).collect();
+            strata.sort();
+            assert_eq!(strata, (0..64).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_primitive_polynomials() {
+        let polys: Vec<(u32, u32)> = primitive_polynomials().take(12).collect();
+        assert_eq!(polys, vec![
+            (1, 0), (2, 1), (3, 1), (3, 2), (4, 1), (4, 4),
+            (5, 2), (5, 4), (5, 7), (5, 11), (5, 13), (5, 14),
+        ]);
+        // The number of primitive polynomials of degree 7 is phi(127) / 7
+        let n_degree_7 = primitive_polynomials()
+            .take_while(|&(d, _)| d <= 7)
+            .filter(|&(d, _)| d == 7)
+            .count();
+        assert_eq!(n_degree_7, 18);
+    }
+
+    #[test]
+    fn test_sobol_interval_to_index() {
+        let m = 3;
+        for &(x, y) in &[(0, 0), (5, 2), (7, 7)] {
+            let p = Point2i::new(x, y);
+            for frame in 0..20 {
+                let index = sobol_interval_to_index(m, frame, p);
+                assert_eq!(index >> (2 * m), frame);
+                let px = (sobol_sample(index, 0, 0) * 8.0) as i32;
+                let py = (sobol_sample(index, 1, 0) * 8.0) as i32;
+                assert_eq!((px, py), (x, y));
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::sync::Arc;
+
+use bumpalo::Bump;
+use cgmath::InnerSpace;
+
+use crate::{Float, Normal3, Vec2f, Vec3f, SurfaceInteraction};
+use crate::interaction::DiffGeom;
+use crate::material::{Material, TransportMode};
+use crate::reflection::bsdf::Bsdf;
+use crate::spectrum::Spectrum;
+use crate::texture::{Texture, TextureRef};
+
+/// A texture that perturbs the shading frame of a surface before a material computes its
+/// BSDF, adding detail to the shading without changing the geometry.
+#[derive(Clone)]
+pub enum ShadingMap {
+    /// A scalar displacement along the shading normal.
+    Bump(TextureRef<Float>),
+
+    /// Normals in the tangent space of the shading frame, encoded as RGB in [0, 1].
+    Normal(TextureRef<Spectrum>),
+}
+
+impl ShadingMap {
+    pub fn apply(&self, si: &mut SurfaceInteraction) {
+        match self {
+            ShadingMap::Bump(displacement) => bump(displacement, si),
+            ShadingMap::Normal(normal_map) => normal_map_shading(normal_map, si),
+        }
+    }
+}
+
+/// Applies a shading map to the interaction before handing it to any other material.
+pub struct ShadingMapped {
+    map: ShadingMap,
+    material: Arc<dyn Material>,
+}
+
+impl ShadingMapped {
+    pub fn new(map: ShadingMap, material: Arc<dyn Material>) -> Self {
+        ShadingMapped { map, material }
+    }
+}
+
+impl Material for ShadingMapped {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+        self.map.apply(si);
+        self.material.compute_scattering_functions(si, arena, mode, allow_multiple_lobes)
+    }
+}
+
+/// Perturbs the shading frame using finite differences of a displacement texture along the
+/// shading tangents.
+pub fn bump(displacement: &TextureRef<Float>, si: &mut SurfaceInteraction) {
+    let shading = si.shading_geom;
+    let shading_n = si.shading_n;
+    let mut si_eval = si.clone();
+
+    // Shift by half the texture footprint, or a small fixed distance without differentials.
+    let mut du = 0.5 * (si.tex_diffs.dudx.abs() + si.tex_diffs.dudy.abs());
+    if du == 0.0 { du = 0.0005; }
+    si_eval.hit.p = si.hit.p + du * shading.dpdu;
+    si_eval.uv = si.uv + Vec2f::new(du, 0.0);
+    si_eval.hit.n = Normal3((shading.dpdu.cross(shading.dpdv) + du * shading.dndu.0).normalize());
+    let u_displace = displacement.evaluate(&si_eval);
+
+    let mut dv = 0.5 * (si.tex_diffs.dvdx.abs() + si.tex_diffs.dvdy.abs());
+    if dv == 0.0 { dv = 0.0005; }
+    si_eval.hit.p = si.hit.p + dv * shading.dpdv;
+    si_eval.uv = si.uv + Vec2f::new(0.0, dv);
+    si_eval.hit.n = Normal3((shading.dpdu.cross(shading.dpdv) + dv * shading.dndv.0).normalize());
+    let v_displace = displacement.evaluate(&si_eval);
+
+    let displace = displacement.evaluate(si);
+
+    let dpdu = shading.dpdu + (u_displace - displace) / du * shading_n.0 + displace * shading.dndu.0;
+    let dpdv = shading.dpdv + (v_displace - displace) / dv * shading_n.0 + displace * shading.dndv.0;
+    si.set_shading_geometry(DiffGeom { dpdu, dpdv, ..shading }, false);
+}
+
+/// Replaces the shading normal with one looked up from a tangent space normal map, where the
+/// tangent frame is made up of the shading `dpdu` and normal.
+pub fn normal_map_shading(normal_map: &TextureRef<Spectrum>, si: &mut SurfaceInteraction) {
+    let shading = si.shading_geom;
+    let rgb = normal_map.evaluate(si);
+    let ns_local = vec3f!(2.0 * rgb[0] - 1.0, 2.0 * rgb[1] - 1.0, 2.0 * rgb[2] - 1.0).normalize();
+
+    let x = shading.dpdu.normalize();
+    let z = si.shading_n.0;
+    let y = z.cross(x);
+    let ns: Vec3f = ns_local.x * x + ns_local.y * y + ns_local.z * z;
+
+    let u_len = shading.dpdu.magnitude();
+    let v_len = shading.dpdv.magnitude();
+    let dpdu = (shading.dpdu - shading.dpdu.dot(ns) * ns).normalize() * u_len;
+    let dpdv = ns.cross(dpdu).normalize() * v_len;
+    si.set_shading_geometry(DiffGeom { dpdu, dpdv, ..shading }, false);
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::{Point2f, Point3f};
+    use crate::texture::ConstantTexture;
+    use crate::material::matte::MatteMaterial;
+
+    fn flat_interaction() -> SurfaceInteraction<'static> {
+        SurfaceInteraction::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            vec3f!(0.0, 0.0, 0.0),
+            0.0,
+            Point2f::new(0.5, 0.5),
+            vec3f!(0.0, 0.0, 1.0),
+            Normal3::new(0.0, 0.0, 1.0),
+            DiffGeom {
+                dpdu: vec3f!(1.0, 0.0, 0.0),
+                dpdv: vec3f!(0.0, 1.0, 0.0),
+                dndu: Normal3::new(0.0, 0.0, 0.0),
+                dndv: Normal3::new(0.0, 0.0, 0.0),
+            }
+        )
+    }
+
+    #[test]
+    fn test_constant_bump_keeps_flat_normal() {
+        let mut si = flat_interaction();
+        bump(&(Arc::new(ConstantTexture(0.3)) as TextureRef<Float>), &mut si);
+        assert_abs_diff_eq!(si.shading_n.0, vec3f!(0.0, 0.0, 1.0), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_normal_map_tilts_normal() {
+        let mut si = flat_interaction();
+        // Tilted 45 degrees towards +u in tangent space.
+        let n = 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        let rgb = Spectrum::new([0.5 + n, 0.5, 0.5 + n]);
+        normal_map_shading(&(Arc::new(ConstantTexture(rgb)) as TextureRef<Spectrum>), &mut si);
+
+        let expected = vec3f!(1.0, 0.0, 1.0).normalize();
+        assert_abs_diff_eq!(si.shading_n.0, expected, epsilon = 1e-5);
+        // The geometric normal is left alone.
+        assert_abs_diff_eq!(si.hit.n.0, vec3f!(0.0, 0.0, 1.0), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_shading_mapped_applies_map_first() {
+        let n = 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        let rgb = Spectrum::new([0.5 + n, 0.5, 0.5 + n]);
+        let map = ShadingMap::Normal(Arc::new(ConstantTexture(rgb)));
+        let material = ShadingMapped::new(map, Arc::new(MatteMaterial::constant(Spectrum::uniform(0.5))));
+
+        let mut si = flat_interaction();
+        let arena = Bump::new();
+        let bsdf = material.compute_scattering_functions(&mut si, &arena, TransportMode::Radiance, false);
+
+        let expected = vec3f!(1.0, 0.0, 1.0).normalize();
+        assert_abs_diff_eq!(si.shading_n.0, expected, epsilon = 1e-5);
+        // The BSDF's shading frame comes from the perturbed normal.
+        assert_abs_diff_eq!(bsdf.world_to_local(expected), vec3f!(0.0, 0.0, 1.0), epsilon = 1e-5);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::texture::{Texture, TextureRef};
+use crate::spectrum::Spectrum;
+use crate::{Float, SurfaceInteraction};
+use crate::material::{Material, TransportMode};
+use crate::math::lerp;
+use bumpalo::Bump;
+use crate::reflection::bsdf::Bsdf;
+use crate::reflection::{MicrofacetReflection, MicrofacetTransmission};
+use crate::reflection::disney::{DisneyDiffuse, DisneyRetro, DisneySheen, DisneyClearcoat};
+use crate::fresnel::{DisneyFresnel, schlick_r0_from_eta};
+use crate::reflection::microfacet::TrowbridgeReitzDistribution;
+
+/// The textures of the Disney principled BSDF. Apart from `color`, they are all weights or
+/// amounts between zero and one, except `eta`, the index of refraction.
+pub struct DisneyParams {
+    pub color: TextureRef<Spectrum>,
+    pub metallic: TextureRef<Float>,
+    pub eta: TextureRef<Float>,
+    pub roughness: TextureRef<Float>,
+    pub specular_tint: TextureRef<Float>,
+    pub anisotropic: TextureRef<Float>,
+    pub sheen: TextureRef<Float>,
+    pub sheen_tint: TextureRef<Float>,
+    pub clearcoat: TextureRef<Float>,
+    pub clearcoat_gloss: TextureRef<Float>,
+    pub spec_trans: TextureRef<Float>,
+}
+
+/// The index of refraction for a Disney "specular" amount, which is 0.5 for an index of 1.5.
+pub struct SpecularEta(pub TextureRef<Float>);
+
+impl Texture for SpecularEta {
+    type Output = Float;
+
+    fn evaluate(&self, si: &SurfaceInteraction) -> Float {
+        let specular = self.0.evaluate(si).clamp(0.0, 1.0);
+        2.0 / (1.0 - Float::sqrt(0.08 * specular)) - 1.0
+    }
+}
+
+/// Disney's principled BSDF, which covers most materials with a small set of artist-friendly
+/// parameters. Only solid surfaces are supported, not the thin surface variant.
+pub struct DisneyMaterial {
+    params: DisneyParams,
+}
+
+impl DisneyMaterial {
+    pub fn new(params: DisneyParams) -> Self {
+        DisneyMaterial { params }
+    }
+}
+
+impl Material for DisneyMaterial {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+        let p = &self.params;
+        let c = p.color.evaluate(si).clamp_positive();
+        let metallic = p.metallic.evaluate(si);
+        let eta = p.eta.evaluate(si);
+        let spec_trans = p.spec_trans.evaluate(si);
+        let roughness = p.roughness.evaluate(si);
+        let diffuse_weight = (1.0 - metallic) * (1.0 - spec_trans);
+
+        // The base color normalized by its luminance, for tinting the other lobes by its hue
+        let lum = c.luminance();
+        let c_tint = if lum > 0.0 { c / lum } else { Spectrum::uniform(1.0) };
+        let tint = |amount: Float| Spectrum::lerp(amount, Spectrum::uniform(1.0), c_tint);
+
+        let mut bsdf = Bsdf::new(si, eta);
+
+        if diffuse_weight > 0.0 {
+            bsdf.add(arena.alloc(DisneyDiffuse { r: diffuse_weight * c }));
+            bsdf.add(arena.alloc(DisneyRetro { r: diffuse_weight * c, roughness }));
+
+            let sheen = p.sheen.evaluate(si);
+            if sheen > 0.0 {
+                let c_sheen = tint(p.sheen_tint.evaluate(si));
+                bsdf.add(arena.alloc(DisneySheen { r: diffuse_weight * sheen * c_sheen }));
+            }
+        }
+
+        let aspect = Float::sqrt(1.0 - p.anisotropic.evaluate(si) * 0.9);
+        let alpha_x = Float::max(0.001, sq!(roughness) / aspect);
+        let alpha_y = Float::max(0.001, sq!(roughness) * aspect);
+
+        // Specular reflection goes from a tinted dielectric to the base color as the surface
+        // gets more metallic.
+        let c_spec0 = Spectrum::lerp(metallic, schlick_r0_from_eta(eta) * tint(p.specular_tint.evaluate(si)), c);
+        let fresnel = DisneyFresnel { r0: c_spec0, metallic, eta };
+        let distribution = TrowbridgeReitzDistribution::new(alpha_x, alpha_y);
+        bsdf.add(arena.alloc(MicrofacetReflection::new(Spectrum::uniform(1.0), distribution, fresnel)));
+
+        let clearcoat = p.clearcoat.evaluate(si);
+        if clearcoat > 0.0 {
+            let gloss = lerp(p.clearcoat_gloss.evaluate(si), 0.1, 0.001);
+            bsdf.add(arena.alloc(DisneyClearcoat::new(clearcoat, gloss)));
+        }
+
+        if spec_trans > 0.0 {
+            // Scale by the square root of the color, since light is tinted both entering and
+            // leaving the surface.
+            let t = spec_trans * c.sqrt();
+            let distribution = TrowbridgeReitzDistribution::new(alpha_x, alpha_y);
+            bsdf.add(arena.alloc(MicrofacetTransmission::new(t, distribution, 1.0, eta, mode)));
+        }
+        bsdf
+    }
+}
@@ -49,7 +49,7 @@ impl GlassMaterial {
 }
 
 impl Material for GlassMaterial {
-    fn compute_scattering_functions<'a>(&self, si: &SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
         let eta = self.eta.evaluate(si);
         let r = self.reflectance.evaluate(si).clamp_positive();
         let t = self.transmittance.evaluate(si).clamp_positive();
@@ -14,7 +14,6 @@ use cgmath::Deg;
 pub struct MatteMaterial {
     diffuse: Arc<dyn Texture<Output = Spectrum>>,
     sigma: TextureRef<Float>
-    // TODO sigma, bump map
 }
 
 impl MatteMaterial {
@@ -33,7 +32,7 @@ impl MatteMaterial {
 }
 
 impl Material for MatteMaterial {
-    fn compute_scattering_functions<'a>(&self, si: &SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
         let mut bsdf = Bsdf::new(si, 1.0);
 
         let r = self.diffuse.evaluate(si).clamp_positive();
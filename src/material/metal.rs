@@ -35,7 +35,7 @@ impl MetalMaterial {
 }
 
 impl Material for MetalMaterial {
-    fn compute_scattering_functions<'a>(&self, si: &SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
         let (u_rough, v_rough) = match &self.roughness {
             RoughnessTex::Anisotropic { u_rough, v_rough} => {
                 (u_rough.evaluate(si), v_rough.evaluate(si))
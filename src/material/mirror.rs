@@ -19,7 +19,7 @@ impl MirrorMaterial {
 }
 
 impl Material for MirrorMaterial {
-    fn compute_scattering_functions<'a>(&self, si: &SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
         let mut bsdf = Bsdf::new(si, 1.0);
         let r = self.reflectance.evaluate(si).clamp_positive();
         if !r.is_black() {
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use bumpalo::Bump;
+
+use crate::interaction::SurfaceInteraction;
+use crate::material::{Material, TransportMode};
+use crate::reflection::bsdf::Bsdf;
+use crate::reflection::ScaledBxDF;
+use crate::spectrum::Spectrum;
+use crate::Float;
+use crate::texture::TextureRef;
+
+/// Blends the BSDFs of two materials by the `amount` texture. An amount of zero gives just
+/// the first material and an amount of one gives just the second.
+pub struct MixMaterial {
+    m1: Arc<dyn Material>,
+    m2: Arc<dyn Material>,
+    amount: TextureRef<Float>,
+}
+
+impl MixMaterial {
+    pub fn new(m1: Arc<dyn Material>, m2: Arc<dyn Material>, amount: TextureRef<Float>) -> Self {
+        MixMaterial { m1, m2, amount }
+    }
+}
+
+impl Material for MixMaterial {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+        let amount = self.amount.evaluate(si).clamp(0.0, 1.0);
+        let s1 = Spectrum::uniform(1.0 - amount);
+        let s2 = Spectrum::uniform(amount);
+
+        // Each material gets its own copy of the interaction, since it may change the
+        // shading geometry.
+        let mut si2 = si.clone();
+        let bsdf1 = self.m1.compute_scattering_functions(si, arena, mode, allow_multiple_lobes);
+        let bsdf2 = self.m2.compute_scattering_functions(&mut si2, arena, mode, allow_multiple_lobes);
+
+        let mut bsdf = Bsdf::new(si, bsdf1.eta);
+        for &bxdf in bsdf1.bxdfs() {
+            bsdf.add(arena.alloc(ScaledBxDF::new(bxdf, s1)));
+        }
+        for &bxdf in bsdf2.bxdfs() {
+            bsdf.add(arena.alloc(ScaledBxDF::new(bxdf, s2)));
+        }
+        bsdf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::{Point2f, Point3f, Normal3};
+    use crate::interaction::DiffGeom;
+    use crate::material::matte::MatteMaterial;
+    use crate::material::metal::RoughnessTex;
+    use crate::material::uber::UberMaterial;
+    use crate::reflection::BxDFType;
+    use crate::texture::ConstantTexture;
+
+    fn matte(r: Float) -> Arc<dyn Material> {
+        Arc::new(MatteMaterial::constant(Spectrum::uniform(r)))
+    }
+
+    /// An uber material with all five of its lobes.
+    fn full_uber() -> Arc<dyn Material> {
+        let spect = |r: Float| Arc::new(ConstantTexture(Spectrum::uniform(r))) as TextureRef<Spectrum>;
+        Arc::new(UberMaterial::new(
+            spect(0.25),
+            spect(0.25),
+            spect(0.25),
+            spect(0.25),
+            RoughnessTex::Isotropic(Arc::new(ConstantTexture(0.1))),
+            spect(0.5),
+            Arc::new(ConstantTexture(1.5)),
+            true
+        ))
+    }
+
+    fn flat_interaction() -> SurfaceInteraction<'static> {
+        SurfaceInteraction::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            vec3f!(0.0, 0.0, 0.0),
+            0.0,
+            Point2f::new(0.5, 0.5),
+            vec3f!(0.0, 0.0, 1.0),
+            Normal3::new(0.0, 0.0, 1.0),
+            DiffGeom {
+                dpdu: vec3f!(1.0, 0.0, 0.0),
+                dpdv: vec3f!(0.0, 1.0, 0.0),
+                dndu: Normal3::new(0.0, 0.0, 0.0),
+                dndv: Normal3::new(0.0, 0.0, 0.0),
+            }
+        )
+    }
+
+    #[test]
+    fn test_mix_blends_lobes() {
+        let mut si = flat_interaction();
+        let mix = MixMaterial::new(matte(0.2), matte(1.0), Arc::new(ConstantTexture(0.25)));
+        let arena = Bump::new();
+        let bsdf = mix.compute_scattering_functions(&mut si, &arena, TransportMode::Radiance, false);
+
+        let wo = vec3f!(0.0, 0.0, 1.0);
+        let f = bsdf.f(wo, wo, BxDFType::all());
+        let expected = (0.75 * 0.2 + 0.25 * 1.0) * std::f32::consts::FRAC_1_PI;
+        assert_abs_diff_eq!(f[0], expected, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_mix_of_full_materials() {
+        // Two uber materials have more lobes between them than a BSDF holds inline, and a
+        // nested mix has more again.
+        let mix = Arc::new(MixMaterial::new(full_uber(), full_uber(), Arc::new(ConstantTexture(0.5))));
+        let nested = MixMaterial::new(mix.clone(), full_uber(), Arc::new(ConstantTexture(0.5)));
+        let arena = Bump::new();
+
+        let bsdf = mix.compute_scattering_functions(&mut flat_interaction(), &arena, TransportMode::Radiance, false);
+        assert_eq!(bsdf.num_components(BxDFType::all()), 10);
+        let bsdf = nested.compute_scattering_functions(&mut flat_interaction(), &arena, TransportMode::Radiance, false);
+        assert_eq!(bsdf.num_components(BxDFType::all()), 15);
+    }
+}
@@ -7,6 +7,12 @@ pub mod mirror;
 pub mod glass;
 pub mod metal;
 pub mod plastic;
+pub mod substrate;
+pub mod uber;
+pub mod mix;
+pub mod translucent;
+pub mod disney;
+pub mod bump;
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum TransportMode {
@@ -17,7 +23,7 @@ pub enum TransportMode {
 pub trait Material: Sync + Send {
     fn compute_scattering_functions<'a>(
         &self,
-        si: &SurfaceInteraction,
+        si: &mut SurfaceInteraction,
         arena: &'a Bump,
         mode: TransportMode,
         allow_multiple_lobes: bool
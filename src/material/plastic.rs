@@ -22,7 +22,7 @@ impl PlasticMaterial {
 }
 
 impl Material for PlasticMaterial {
-    fn compute_scattering_functions<'a>(&self, si: &SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
         let mut bsdf = Bsdf::new(si, 1.0);
         let kd = self.kd.evaluate(si);
         if !kd.is_black() {
@@ -0,0 +1,47 @@
+use crate::texture::TextureRef;
+use crate::spectrum::Spectrum;
+use crate::{Float, SurfaceInteraction};
+use crate::material::{Material, TransportMode};
+use bumpalo::Bump;
+use crate::reflection::bsdf::Bsdf;
+use crate::reflection::FresnelBlend;
+use crate::reflection::microfacet::TrowbridgeReitzDistribution;
+
+/// A diffuse base under a glossy coating, such as varnished wood.
+pub struct SubstrateMaterial {
+    kd: TextureRef<Spectrum>,
+    ks: TextureRef<Spectrum>,
+    u_rough: TextureRef<Float>,
+    v_rough: TextureRef<Float>,
+    remap_roughness: bool,
+}
+
+impl SubstrateMaterial {
+    pub fn new(
+        kd: TextureRef<Spectrum>,
+        ks: TextureRef<Spectrum>,
+        u_rough: TextureRef<Float>,
+        v_rough: TextureRef<Float>,
+        remap_roughness: bool
+    ) -> Self {
+        SubstrateMaterial { kd, ks, u_rough, v_rough, remap_roughness }
+    }
+}
+
+impl Material for SubstrateMaterial {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+        let mut bsdf = Bsdf::new(si, 1.0);
+        let kd = self.kd.evaluate(si);
+        let ks = self.ks.evaluate(si);
+        if !kd.is_black() || !ks.is_black() {
+            let (mut u_rough, mut v_rough) = (self.u_rough.evaluate(si), self.v_rough.evaluate(si));
+            if self.remap_roughness {
+                u_rough = TrowbridgeReitzDistribution::roughness_to_alpha(u_rough);
+                v_rough = TrowbridgeReitzDistribution::roughness_to_alpha(v_rough);
+            }
+            let distribution = TrowbridgeReitzDistribution::new(u_rough, v_rough);
+            bsdf.add(arena.alloc(FresnelBlend::new(kd, ks, distribution)));
+        }
+        bsdf
+    }
+}
@@ -0,0 +1,75 @@
+use crate::texture::TextureRef;
+use crate::spectrum::Spectrum;
+use crate::{Float, SurfaceInteraction};
+use crate::material::{Material, TransportMode};
+use bumpalo::Bump;
+use crate::reflection::bsdf::Bsdf;
+use crate::reflection::{LambertianReflection, LambertianTransmission, MicrofacetReflection, MicrofacetTransmission};
+use crate::fresnel::FresnelDielectric;
+use crate::reflection::microfacet::TrowbridgeReitzDistribution;
+
+/// A thin surface that scatters light to both of its sides, such as leaves or paper. The
+/// diffuse and glossy lobes are split between reflection and transmission by the `reflect` and
+/// `transmit` textures.
+pub struct TranslucentMaterial {
+    kd: TextureRef<Spectrum>,
+    ks: TextureRef<Spectrum>,
+    roughness: TextureRef<Float>,
+    reflect: TextureRef<Spectrum>,
+    transmit: TextureRef<Spectrum>,
+    remap_roughness: bool,
+}
+
+impl TranslucentMaterial {
+    pub fn new(
+        kd: TextureRef<Spectrum>,
+        ks: TextureRef<Spectrum>,
+        roughness: TextureRef<Float>,
+        reflect: TextureRef<Spectrum>,
+        transmit: TextureRef<Spectrum>,
+        remap_roughness: bool
+    ) -> Self {
+        TranslucentMaterial { kd, ks, roughness, reflect, transmit, remap_roughness }
+    }
+}
+
+impl Material for TranslucentMaterial {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+        let eta = 1.5;
+        let mut bsdf = Bsdf::new(si, eta);
+
+        let r = self.reflect.evaluate(si).clamp_positive();
+        let t = self.transmit.evaluate(si).clamp_positive();
+        if r.is_black() && t.is_black() {
+            return bsdf;
+        }
+
+        let kd = self.kd.evaluate(si).clamp_positive();
+        if !kd.is_black() {
+            if !r.is_black() {
+                bsdf.add(arena.alloc(LambertianReflection { r: r * kd }));
+            }
+            if !t.is_black() {
+                bsdf.add(arena.alloc(LambertianTransmission { t: t * kd }));
+            }
+        }
+
+        let ks = self.ks.evaluate(si).clamp_positive();
+        if !ks.is_black() {
+            let mut rough = self.roughness.evaluate(si);
+            if self.remap_roughness {
+                rough = TrowbridgeReitzDistribution::roughness_to_alpha(rough);
+            }
+            if !r.is_black() {
+                let distribution = TrowbridgeReitzDistribution::new(rough, rough);
+                let fresnel = FresnelDielectric::new(1.0, eta);
+                bsdf.add(arena.alloc(MicrofacetReflection::new(r * ks, distribution, fresnel)));
+            }
+            if !t.is_black() {
+                let distribution = TrowbridgeReitzDistribution::new(rough, rough);
+                bsdf.add(arena.alloc(MicrofacetTransmission::new(t * ks, distribution, 1.0, eta, mode)));
+            }
+        }
+        bsdf
+    }
+}
@@ -0,0 +1,89 @@
+use crate::texture::TextureRef;
+use crate::spectrum::Spectrum;
+use crate::{Float, SurfaceInteraction};
+use crate::material::{Material, TransportMode};
+use crate::material::metal::RoughnessTex;
+use bumpalo::Bump;
+use crate::reflection::bsdf::Bsdf;
+use crate::reflection::{LambertianReflection, MicrofacetReflection, SpecularReflection, SpecularTransmission};
+use crate::fresnel::FresnelDielectric;
+use crate::reflection::microfacet::TrowbridgeReitzDistribution;
+
+/// A combination of diffuse, glossy and specular lobes, as used by many exported scenes.
+/// Light that isn't stopped by the surface's opacity passes straight through it.
+pub struct UberMaterial {
+    kd: TextureRef<Spectrum>,
+    ks: TextureRef<Spectrum>,
+    kr: TextureRef<Spectrum>,
+    kt: TextureRef<Spectrum>,
+    roughness: RoughnessTex,
+    opacity: TextureRef<Spectrum>,
+    eta: TextureRef<Float>,
+    remap_roughness: bool,
+}
+
+impl UberMaterial {
+    pub fn new(
+        kd: TextureRef<Spectrum>,
+        ks: TextureRef<Spectrum>,
+        kr: TextureRef<Spectrum>,
+        kt: TextureRef<Spectrum>,
+        roughness: RoughnessTex,
+        opacity: TextureRef<Spectrum>,
+        eta: TextureRef<Float>,
+        remap_roughness: bool
+    ) -> Self {
+        UberMaterial { kd, ks, kr, kt, roughness, opacity, eta, remap_roughness }
+    }
+}
+
+impl Material for UberMaterial {
+    fn compute_scattering_functions<'a>(&self, si: &mut SurfaceInteraction, arena: &'a Bump, mode: TransportMode, allow_multiple_lobes: bool) -> Bsdf<'a> {
+        let eta = self.eta.evaluate(si);
+        let opacity = self.opacity.evaluate(si).clamp_positive();
+        let pass_through = (Spectrum::uniform(1.0) - opacity).clamp_positive();
+
+        // Light passing through a partially opaque surface isn't refracted, so the relative
+        // index of refraction only applies when it's fully opaque.
+        let mut bsdf = if pass_through.is_black() { Bsdf::new(si, eta) } else { Bsdf::new(si, 1.0) };
+        if !pass_through.is_black() {
+            bsdf.add(arena.alloc(SpecularTransmission::new(pass_through, 1.0, 1.0, mode)));
+        }
+
+        let kd = opacity * self.kd.evaluate(si).clamp_positive();
+        if !kd.is_black() {
+            bsdf.add(arena.alloc(LambertianReflection { r: kd }));
+        }
+
+        let ks = opacity * self.ks.evaluate(si).clamp_positive();
+        if !ks.is_black() {
+            let fresnel = FresnelDielectric::new(1.0, eta);
+            let (u_rough, v_rough) = match &self.roughness {
+                RoughnessTex::Anisotropic { u_rough, v_rough } => {
+                    (u_rough.evaluate(si), v_rough.evaluate(si))
+                },
+                RoughnessTex::Isotropic(rough) => {
+                    let r = rough.evaluate(si);
+                    (r, r)
+                }
+            };
+            let (u_rough, v_rough) = if self.remap_roughness {
+                (TrowbridgeReitzDistribution::roughness_to_alpha(u_rough), TrowbridgeReitzDistribution::roughness_to_alpha(v_rough))
+            } else { (u_rough, v_rough) };
+            let distribution = TrowbridgeReitzDistribution::new(u_rough, v_rough);
+            bsdf.add(arena.alloc(MicrofacetReflection::new(ks, distribution, fresnel)));
+        }
+
+        let kr = opacity * self.kr.evaluate(si).clamp_positive();
+        if !kr.is_black() {
+            let fresnel = FresnelDielectric::new(1.0, eta);
+            bsdf.add(arena.alloc(SpecularReflection::new(kr, fresnel)));
+        }
+
+        let kt = opacity * self.kt.evaluate(si).clamp_positive();
+        if !kt.is_black() {
+            bsdf.add(arena.alloc(SpecularTransmission::new(kt, 1.0, eta, mode)));
+        }
+        bsdf
+    }
+}
@@ -7,8 +7,21 @@ use crate::spectrum::Spectrum;
 pub const INFINITY: Float = std::f32::INFINITY;
 pub const NEG_INFINITY: Float = std::f32::NEG_INFINITY;
 
+/// The largest float less than one, used to keep sample values in [0, 1).
+pub const ONE_MINUS_EPSILON: Float = 0.99999994;
+
 pub type Float = f32;
 
+/// A fast 64-bit hash finalizer, used to decorrelate seeds derived from nearby values.
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
 pub fn lerp(t: Float, v1: Float, v2: Float) -> Float {
     (1.0 - t) * v1 + t * v2
 }
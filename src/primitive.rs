@@ -7,8 +7,10 @@ use crate::shapes::Shape;
 use crate::light::{AreaLight, Light};
 use crate::spectrum::Spectrum;
 use crate::light::diffuse::DiffuseAreaLight;
+use crate::bvh::BVH;
+use crate::Transform;
 
-pub trait Primitive: Sync {
+pub trait Primitive: Sync + Send {
     fn world_bound(&self) -> Bounds3f;
 
     fn intersect(&self, ray: &mut Ray) -> Option<SurfaceInteraction>;
@@ -68,4 +70,97 @@ impl<S: 'static +  Shape> Primitive for GeometricPrimitive<S> {
     fn light_arc_cloned(&self) -> Option<Arc<dyn Light>> {
         self.light.as_ref().map(|l| l.clone() as Arc<dyn Light>)
     }
-}
\ No newline at end of file
+}
+
+/// A primitive placed in the world by an instancing transform. Many `TransformedPrimitive`s
+/// can share the same aggregate, which is built once in its own coordinate space.
+pub struct TransformedPrimitive {
+    primitive: Arc<BVH>,
+    primitive_to_world: Transform,
+    world_to_primitive: Transform,
+}
+
+impl TransformedPrimitive {
+    pub fn new(primitive: Arc<BVH>, primitive_to_world: Transform) -> Self {
+        Self {
+            primitive,
+            primitive_to_world,
+            world_to_primitive: primitive_to_world.inverse(),
+        }
+    }
+}
+
+impl Primitive for TransformedPrimitive {
+    fn world_bound(&self) -> Bounds3f {
+        self.primitive_to_world.transform(self.primitive.bounds)
+    }
+
+    fn intersect(&self, ray: &mut Ray) -> Option<SurfaceInteraction> {
+        let mut prim_ray = self.world_to_primitive.transform(*ray);
+        let intersect = self.primitive.intersect(&mut prim_ray)?;
+
+        ray.t_max = prim_ray.t_max;
+        Some(self.primitive_to_world.transform(intersect))
+    }
+
+    fn intersect_test(&self, ray: &Ray) -> bool {
+        let prim_ray = self.world_to_primitive.transform(*ray);
+        self.primitive.intersect_test(&prim_ray)
+    }
+
+    // The interaction returned by `intersect` refers to the instanced primitive that was hit,
+    // so these are never queried on the instance itself.
+    fn material(&self) -> Option<&dyn Material> {
+        None
+    }
+
+    fn area_light(&self) -> Option<&dyn AreaLight> {
+        None
+    }
+
+    fn light_arc_cloned(&self) -> Option<Arc<dyn Light>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::sphere::Sphere;
+    use crate::{Point3f, Vec3f};
+    use cgmath::assert_abs_diff_eq;
+
+    fn unit_sphere_instance() -> Arc<BVH> {
+        let sphere = Sphere::whole(Transform::IDENTITY, Transform::IDENTITY, 1.0);
+        let prim = GeometricPrimitive { shape: Arc::new(sphere), material: None, light: None };
+        Arc::new(BVH::build(vec![Box::new(prim) as Box<dyn Primitive>]))
+    }
+
+    #[test]
+    fn test_transformed_primitive_intersect() {
+        let instance = unit_sphere_instance();
+        let prims: Vec<Box<dyn Primitive>> = vec![
+            Box::new(TransformedPrimitive::new(instance.clone(), Transform::translate(Vec3f::new(5.0, 0.0, 0.0)))),
+            Box::new(TransformedPrimitive::new(instance, Transform::translate(Vec3f::new(-5.0, 0.0, 0.0)))),
+        ];
+
+        for &x in &[5.0, -5.0] {
+            let mut ray = Ray::new(Point3f::new(x, 0.0, -10.0), Vec3f::new(0.0, 0.0, 1.0));
+            let isect = prims.iter().fold(None, |isect, prim| prim.intersect(&mut ray).or(isect));
+            let isect = isect.expect("ray should hit instance");
+            assert_abs_diff_eq!(isect.hit.p, Point3f::new(x, 0.0, -1.0), epsilon = 1e-4);
+            assert_abs_diff_eq!(ray.t_max, 9.0, epsilon = 1e-4);
+        }
+
+        let ray = Ray::new(Point3f::new(0.0, 0.0, -10.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(prims.iter().all(|prim| !prim.intersect_test(&ray)));
+    }
+
+    #[test]
+    fn test_transformed_primitive_bound() {
+        let prim = TransformedPrimitive::new(unit_sphere_instance(), Transform::translate(Vec3f::new(0.0, 3.0, 0.0)));
+        let bound = prim.world_bound();
+        assert_abs_diff_eq!(bound.min, Point3f::new(-1.0, 2.0, -1.0), epsilon = 1e-4);
+        assert_abs_diff_eq!(bound.max, Point3f::new(1.0, 4.0, 1.0), epsilon = 1e-4);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{Float, Normal3, Vec3f, Point2f};
-use arrayvec::ArrayVec;
+use smallvec::SmallVec;
 use crate::reflection::{BxDF, BxDFType, ScatterSample};
 use crate::interaction::SurfaceInteraction;
 use cgmath::InnerSpace;
@@ -23,7 +23,9 @@ pub struct Bsdf<'a> {
     ts: Vec3f,
 
     // TODO: store flags alongside to avoid dynamic dispatch
-    bxdfs: ArrayVec<[&'a dyn BxDF; 8]>
+    /// Most materials have only a few lobes, but a mix of materials has all of the lobes of
+    /// both, so this can spill onto the heap rather than having a hard limit.
+    bxdfs: SmallVec<[&'a dyn BxDF; 8]>
 }
 
 impl<'a> Bsdf<'a> {
@@ -33,7 +35,7 @@ impl<'a> Bsdf<'a> {
         let ng = si.hit.n;
         let ss = si.shading_geom.dpdu.normalize();
         let ts = ns.cross(ss).normalize();
-        let bxdfs = ArrayVec::new();
+        let bxdfs = SmallVec::new();
 
         Self {
             eta,
@@ -49,6 +51,10 @@ impl<'a> Bsdf<'a> {
         self.bxdfs.push(bxdf);
     }
 
+    pub fn bxdfs(&self) -> &[&'a dyn BxDF] {
+        self.bxdfs.as_slice()
+    }
+
     pub fn num_components(&self, flags: BxDFType) -> usize {
         self.bxdfs.as_slice().iter().filter(|bxdf| bxdf.matches_flags(flags)).count()
     }
@@ -0,0 +1,168 @@
+use cgmath::InnerSpace;
+
+use crate::{Vec3f, Point2f, Float, abs_dot};
+use crate::consts::FRAC_1_PI;
+use crate::fresnel::{fr_schlick, schlick_weight};
+use crate::reflection::{BxDF, BxDFType, DefaultSampleF, ScatterSample, abs_cos_theta, reflect, same_hemisphere};
+use crate::reflection::microfacet::{GTR1Distribution, MicrofacetDistribution};
+use crate::spectrum::Spectrum;
+
+/// The diffuse lobe of the Disney BSDF, which darkens towards grazing angles.
+#[derive(Debug)]
+pub struct DisneyDiffuse {
+    pub r: Spectrum,
+}
+
+impl DefaultSampleF for DisneyDiffuse {
+    fn get_type(&self) -> BxDFType {
+        BxDFType::REFLECTION | BxDFType::DIFFUSE
+    }
+
+    fn f(&self, wo: Vec3f, wi: Vec3f) -> Spectrum {
+        let fo = schlick_weight(abs_cos_theta(wo));
+        let fi = schlick_weight(abs_cos_theta(wi));
+        self.r * FRAC_1_PI * (1.0 - fo / 2.0) * (1.0 - fi / 2.0)
+    }
+}
+
+/// Retro-reflection from rough diffuse surfaces, which brightens them at grazing angles.
+#[derive(Debug)]
+pub struct DisneyRetro {
+    pub r: Spectrum,
+    pub roughness: Float,
+}
+
+impl DefaultSampleF for DisneyRetro {
+    fn get_type(&self) -> BxDFType {
+        BxDFType::REFLECTION | BxDFType::DIFFUSE
+    }
+
+    fn f(&self, wo: Vec3f, wi: Vec3f) -> Spectrum {
+        let wh = wi + wo;
+        if wh == Vec3f::new(0.0, 0.0, 0.0) {
+            return Spectrum::uniform(0.0);
+        }
+        let wh = wh.normalize();
+        let cos_theta_d = wi.dot(wh);
+
+        let fo = schlick_weight(abs_cos_theta(wo));
+        let fi = schlick_weight(abs_cos_theta(wi));
+        let rr = 2.0 * self.roughness * cos_theta_d * cos_theta_d;
+        self.r * FRAC_1_PI * rr * (fo + fi + fo * fi * (rr - 1.0))
+    }
+}
+
+/// A grazing retro-reflection for cloth-like surfaces.
+#[derive(Debug)]
+pub struct DisneySheen {
+    pub r: Spectrum,
+}
+
+impl DefaultSampleF for DisneySheen {
+    fn get_type(&self) -> BxDFType {
+        BxDFType::REFLECTION | BxDFType::DIFFUSE
+    }
+
+    fn f(&self, wo: Vec3f, wi: Vec3f) -> Spectrum {
+        let wh = wi + wo;
+        if wh == Vec3f::new(0.0, 0.0, 0.0) {
+            return Spectrum::uniform(0.0);
+        }
+        let wh = wh.normalize();
+        self.r * schlick_weight(wi.dot(wh))
+    }
+}
+
+/// A second, colorless specular layer on top of the other lobes, with a GTR1 distribution of
+/// microfacets.
+pub struct DisneyClearcoat {
+    pub weight: Float,
+    pub distribution: GTR1Distribution,
+}
+
+impl DisneyClearcoat {
+    /// `gloss` is the alpha of the GTR1 distribution, where smaller values are glossier.
+    pub fn new(weight: Float, gloss: Float) -> Self {
+        DisneyClearcoat { weight, distribution: GTR1Distribution::new(gloss) }
+    }
+}
+
+impl BxDF for DisneyClearcoat {
+    fn get_type(&self) -> BxDFType {
+        BxDFType::REFLECTION | BxDFType::GLOSSY
+    }
+
+    fn f(&self, wo: Vec3f, wi: Vec3f) -> Spectrum {
+        let cos_theta_o = abs_cos_theta(wo);
+        let cos_theta_i = abs_cos_theta(wi);
+        let wh = wi + wo;
+        if cos_theta_i == 0.0 || cos_theta_o == 0.0 || wh == Vec3f::new(0.0, 0.0, 0.0) {
+            return Spectrum::uniform(0.0);
+        }
+        let wh = wh.normalize();
+
+        // The coating has a fixed index of refraction of 1.5, so a reflectance of 0.04 at
+        // normal incidence, and contributes a quarter of its weight at most.
+        let fr = fr_schlick(Spectrum::uniform(0.04), wo.dot(wh));
+        let d = self.distribution.d(wh);
+        let g = self.distribution.g(wo, wi);
+        fr * (0.25 * self.weight * d * g / (4.0 * cos_theta_i * cos_theta_o))
+    }
+
+    fn sample_f(&self, wo: Vec3f, sample: Point2f) -> Option<ScatterSample> {
+        if wo.z == 0.0 {
+            return None;
+        }
+        let wh = self.distribution.sample_wh(wo, sample);
+        let wi = reflect(wo, wh);
+        if !same_hemisphere(wo, wi) {
+            return None;
+        }
+        ScatterSample {
+            f: self.f(wo, wi),
+            wi,
+            pdf: self.pdf(wo, wi),
+            sampled_type: self.get_type()
+        }.into()
+    }
+
+    fn pdf(&self, wo: Vec3f, wi: Vec3f) -> Float {
+        if !same_hemisphere(wo, wi) {
+            return 0.0
+        }
+        let wh = wi + wo;
+        if wh == Vec3f::new(0.0, 0.0, 0.0) {
+            return 0.0;
+        }
+        let wh = wh.normalize();
+        self.distribution.pdf(wo, wh) / (4.0 * abs_dot(wo, wh))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::PI;
+
+    #[test]
+    fn test_clearcoat_at_normal_incidence() {
+        // Straight on, the Fresnel term is just the reflectance at normal incidence and there's
+        // no masking, leaving the GTR1 density of the normal.
+        let (weight, alpha) = (0.8, 0.05);
+        let clearcoat = DisneyClearcoat::new(weight, alpha);
+        let n = Vec3f::new(0.0, 0.0, 1.0);
+        let alpha2 = alpha * alpha;
+        let d = (alpha2 - 1.0) / (PI * alpha2.ln() * alpha2);
+        let expected = 0.04 * 0.25 * weight * d / 4.0;
+        let f = BxDF::f(&clearcoat, n, n)[0];
+        assert!((f - expected).abs() < 1e-4 * expected, "f was {}, expected {}", f, expected);
+    }
+
+    #[test]
+    fn test_disney_diffuse_at_normal_incidence() {
+        // There's no grazing falloff straight on.
+        let diffuse = DisneyDiffuse { r: Spectrum::uniform(1.0) };
+        let n = Vec3f::new(0.0, 0.0, 1.0);
+        assert!((BxDF::f(&diffuse, n, n)[0] - FRAC_1_PI).abs() < 1e-6);
+    }
+}
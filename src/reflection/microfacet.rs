@@ -1,6 +1,6 @@
 use crate::{Vec3f, Float, Point2f, spherical_direction};
 use crate::reflection::{tan2_theta, cos2_theta, cos2_phi, sin2_phi, tan_theta, same_hemisphere, abs_cos_theta};
-use crate::consts::{PI, FRAC_PI_2};
+use crate::consts::{PI, FRAC_PI_2, FRAC_1_PI};
 
 pub trait MicrofacetDistribution {
     /// Find the differential area of microfacets oriented with the given normal vector `w`
@@ -184,4 +184,77 @@ impl MicrofacetDistribution for TrowbridgeReitzDistribution {
             -wh
         }
     }
-}
\ No newline at end of file
+}
+
+/// The generalized Trowbridge-Reitz distribution with an exponent of one, which has the long
+/// tails of the Disney clearcoat highlight.
+pub struct GTR1Distribution {
+    alpha: Float,
+}
+
+impl GTR1Distribution {
+    pub fn new(alpha: Float) -> Self {
+        GTR1Distribution { alpha }
+    }
+}
+
+impl MicrofacetDistribution for GTR1Distribution {
+    fn d(&self, wh: Vec3f) -> Float {
+        // The distribution is uniform in the limit as alpha goes to one.
+        if self.alpha >= 1.0 {
+            return FRAC_1_PI;
+        }
+        let alpha2 = sq!(self.alpha);
+        (alpha2 - 1.0) / (PI * alpha2.ln() * (1.0 + (alpha2 - 1.0) * cos2_theta(wh)))
+    }
+
+    fn lambda(&self, w: Vec3f) -> Float {
+        // GTR1 has no closed form masking function, so this uses GGX with a fixed roughness
+        // of 0.25, as in the Disney model.
+        let abs_tan_theta = tan_theta(w).abs();
+        if abs_tan_theta.is_infinite() {
+            return 0.0
+        }
+        let alpha2_tan2_theta = sq!(0.25 * abs_tan_theta);
+        (-1.0 + Float::sqrt(1.0 + alpha2_tan2_theta)) / 2.0
+    }
+
+    fn sample_wh(&self, wo: Vec3f, u: Point2f) -> Vec3f {
+        let cos_theta = if self.alpha >= 1.0 {
+            Float::sqrt(1.0 - u[0])
+        } else {
+            let alpha2 = sq!(self.alpha);
+            Float::sqrt(Float::max(0.0, (1.0 - alpha2.powf(1.0 - u[0])) / (1.0 - alpha2)))
+        };
+        let sin_theta = Float::sqrt(Float::max(0.0, 1.0 - sq!(cos_theta)));
+        let wh = spherical_direction(sin_theta, cos_theta, 2.0 * PI * u[1]);
+        if same_hemisphere(wo, wh) {
+            wh
+        } else {
+            -wh
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gtr1_normalized() {
+        // The projected area of the microfacets should be one.
+        for &alpha in &[0.1, 0.5, 1.0] {
+            let dist = GTR1Distribution::new(alpha);
+            let n = 100_000;
+            let d_theta = FRAC_PI_2 / n as Float;
+            let integral: Float = (0..n)
+                .map(|i| {
+                    let theta = (i as Float + 0.5) * d_theta;
+                    let wh = spherical_direction(theta.sin(), theta.cos(), 0.0);
+                    dist.d(wh) * theta.cos() * theta.sin() * d_theta
+                })
+                .sum::<Float>() * 2.0 * PI;
+            assert!((integral - 1.0).abs() < 1e-2, "integral was {} for alpha {}", integral, alpha);
+        }
+    }
+}
@@ -7,9 +7,11 @@ use cgmath::{InnerSpace, Rad};
 use crate::sampling::cosine_sample_hemisphere;
 use std::fmt::Debug;
 use crate::reflection::microfacet::MicrofacetDistribution;
+use crate::math::ONE_MINUS_EPSILON;
 
 pub mod bsdf;
 pub mod microfacet;
+pub mod disney;
 
 bitflags! {
     pub struct BxDFType: u8 {
@@ -161,6 +163,44 @@ impl DefaultSampleF for LambertianReflection {
     }
 }
 
+/// Diffuse transmission to the other side of the surface, as through thin leaves or paper.
+/// Light isn't refracted, so unlike `SpecularTransmission` it is the same for radiance and
+/// importance transport.
+#[derive(Debug)]
+pub struct LambertianTransmission {
+    pub t: Spectrum,
+}
+
+impl BxDF for LambertianTransmission {
+    fn get_type(&self) -> BxDFType {
+        BxDFType::TRANSMISSION | BxDFType::DIFFUSE
+    }
+
+    fn f(&self, _wo: Vec3f, _wi: Vec3f) -> Spectrum {
+        self.t * std::f32::consts::FRAC_1_PI
+    }
+
+    fn sample_f(&self, wo: Vec3f, sample: Point2f) -> Option<ScatterSample> {
+        let mut wi = cosine_sample_hemisphere(sample);
+        // sample the hemisphere opposite wo
+        if wo.z > 0.0 { wi.z *= -1.0; }
+        Some(ScatterSample {
+            f: self.f(wo, wi),
+            wi,
+            pdf: self.pdf(wo, wi),
+            sampled_type: self.get_type()
+        })
+    }
+
+    fn pdf(&self, wo: Vec3f, wi: Vec3f) -> Float {
+        if same_hemisphere(wo, wi) {
+            0.0
+        } else {
+            abs_cos_theta(wi) * std::f32::consts::FRAC_1_PI
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SpecularReflection<F: Fresnel> {
     r: Spectrum,
@@ -439,6 +479,113 @@ impl<D: MicrofacetDistribution> BxDF for MicrofacetTransmission<D> {
 }
 
 
+/// Scales the values of another BxDF, leaving its sampling unchanged. Used to blend the
+/// lobes of two materials.
+pub struct ScaledBxDF<'a> {
+    pub bxdf: &'a dyn BxDF,
+    pub scale: Spectrum,
+}
+
+impl<'a> ScaledBxDF<'a> {
+    pub fn new(bxdf: &'a dyn BxDF, scale: Spectrum) -> Self {
+        ScaledBxDF { bxdf, scale }
+    }
+}
+
+impl<'a> BxDF for ScaledBxDF<'a> {
+    fn get_type(&self) -> BxDFType {
+        self.bxdf.get_type()
+    }
+
+    fn f(&self, wo: Vec3f, wi: Vec3f) -> Spectrum {
+        self.scale * self.bxdf.f(wo, wi)
+    }
+
+    fn sample_f(&self, wo: Vec3f, sample: Point2f) -> Option<ScatterSample> {
+        let sample = self.bxdf.sample_f(wo, sample)?;
+        Some(ScatterSample { f: self.scale * sample.f, ..sample })
+    }
+
+    fn pdf(&self, wo: Vec3f, wi: Vec3f) -> Float {
+        self.bxdf.pdf(wo, wi)
+    }
+}
+
+/// The Ashikhmin-Shirley model of a diffuse base under a glossy coating, where less light
+/// reaches the base at grazing angles, where the coating reflects more.
+#[derive(Debug)]
+pub struct FresnelBlend<D: MicrofacetDistribution> {
+    pub rd: Spectrum,
+    pub rs: Spectrum,
+    pub distribution: D,
+}
+
+impl<D: MicrofacetDistribution> FresnelBlend<D> {
+    pub fn new(rd: Spectrum, rs: Spectrum, distribution: D) -> Self {
+        FresnelBlend { rd, rs, distribution }
+    }
+
+    /// Schlick's approximation to the Fresnel reflectance of the coating.
+    fn schlick_fresnel(&self, cos_theta: Float) -> Spectrum {
+        self.rs + (1.0 - cos_theta).powi(5) * (Spectrum::uniform(1.0) - self.rs)
+    }
+}
+
+impl<D: MicrofacetDistribution> BxDF for FresnelBlend<D> {
+    fn get_type(&self) -> BxDFType {
+        BxDFType::REFLECTION | BxDFType::GLOSSY
+    }
+
+    fn f(&self, wo: Vec3f, wi: Vec3f) -> Spectrum {
+        let pow5 = |v: Float| v.powi(5);
+        let diffuse = (28.0 / (23.0 * std::f32::consts::PI)) * self.rd * (Spectrum::uniform(1.0) - self.rs)
+            * (1.0 - pow5(1.0 - 0.5 * abs_cos_theta(wi)))
+            * (1.0 - pow5(1.0 - 0.5 * abs_cos_theta(wo)));
+        let wh = wi + wo;
+        if wh == Vec3f::new(0.0, 0.0, 0.0) {
+            return Spectrum::uniform(0.0);
+        }
+        let wh = wh.normalize();
+        let specular = self.schlick_fresnel(wi.dot(wh))
+            * (self.distribution.d(wh) / (4.0 * abs_dot(wi, wh) * Float::max(abs_cos_theta(wi), abs_cos_theta(wo))));
+        diffuse + specular
+    }
+
+    fn sample_f(&self, wo: Vec3f, sample: Point2f) -> Option<ScatterSample> {
+        // Use the first dimension of the sample to choose between the diffuse and glossy lobes,
+        // then remap it to [0, 1) to sample the chosen lobe.
+        let wi = if sample[0] < 0.5 {
+            let u = Point2f::new(Float::min(2.0 * sample[0], ONE_MINUS_EPSILON), sample[1]);
+            let mut wi = cosine_sample_hemisphere(u);
+            if wo.z < 0.0 { wi.z *= -1.0; }
+            wi
+        } else {
+            let u = Point2f::new(Float::min(2.0 * (sample[0] - 0.5), ONE_MINUS_EPSILON), sample[1]);
+            let wh = self.distribution.sample_wh(wo, u);
+            let wi = reflect(wo, wh);
+            if !same_hemisphere(wo, wi) {
+                return None;
+            }
+            wi
+        };
+        ScatterSample {
+            f: self.f(wo, wi),
+            wi,
+            pdf: self.pdf(wo, wi),
+            sampled_type: self.get_type()
+        }.into()
+    }
+
+    fn pdf(&self, wo: Vec3f, wi: Vec3f) -> Float {
+        if !same_hemisphere(wo, wi) {
+            return 0.0
+        }
+        let wh = (wo + wi).normalize();
+        let pdf_wh = self.distribution.pdf(wo, wh);
+        0.5 * (abs_cos_theta(wi) * std::f32::consts::FRAC_1_PI + pdf_wh / (4.0 * wo.dot(wh)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -454,5 +601,46 @@ mod tests {
     fn test_specular_reflection() {
 
     }
+
+    #[test]
+    fn test_lambertian_transmission_samples_other_side() {
+        let bxdf = LambertianTransmission { t: Spectrum::uniform(0.5) };
+        for &wo in &[Vec3f::new(0.0, 0.6, 0.8), Vec3f::new(0.0, 0.6, -0.8)] {
+            let sample = bxdf.sample_f(wo, Point2f::new(0.3, 0.8)).unwrap();
+            assert!(!same_hemisphere(wo, sample.wi));
+            assert!(sample.pdf > 0.0);
+            assert!(bxdf.pdf(wo, -sample.wi) == 0.0);
+        }
+    }
+
+    #[test]
+    fn test_fresnel_blend_pdf_integral() {
+        use crate::reflection::microfacet::TrowbridgeReitzDistribution;
+        use crate::consts::{PI, FRAC_PI_2};
+        use crate::spherical_direction;
+
+        let alpha = 0.3;
+        let bxdf = FresnelBlend::new(
+            Spectrum::uniform(0.5),
+            Spectrum::uniform(0.04),
+            TrowbridgeReitzDistribution::new(alpha, alpha)
+        );
+
+        // Straight on, the pdf only depends on the angle of wi. Half of the samples are
+        // diffuse, and the glossy ones are lost when the half vector is more than 45 degrees
+        // from the normal, which happens with probability alpha^2 / (1 + alpha^2).
+        let wo = Vec3f::new(0.0, 0.0, 1.0);
+        let n = 100_000;
+        let d_theta = FRAC_PI_2 / n as Float;
+        let integral: Float = (0..n)
+            .map(|i| {
+                let theta = (i as Float + 0.5) * d_theta;
+                let wi = spherical_direction(theta.sin(), theta.cos(), 0.0);
+                bxdf.pdf(wo, wi) * theta.sin() * d_theta
+            })
+            .sum::<Float>() * 2.0 * PI;
+        let expected = 0.5 + 0.5 / (1.0 + alpha * alpha);
+        assert!((integral - expected).abs() < 2e-3, "integral was {}, expected {}", integral, expected);
+    }
 }
 
@@ -0,0 +1,257 @@
+use std::sync::Arc;
+use crate::{Point2i, Point2f, Float, Bounds2i};
+use rand_xoshiro::Xoshiro256Plus;
+use rand::SeedableRng;
+use crate::sampler::{Sampler, SamplerState, SampleArrayId};
+use crate::lowdiscrepancy::{radical_inverse, scrambled_radical_inverse, inverse_radical_inverse, multiplicative_inverse, compute_radical_inverse_permutations, PRIME_SUMS, PRIMES, PRIME_TABLE_SIZE};
+
+/// The Halton sequence is scaled so that its first two dimensions cover at most this many
+/// pixels in each direction before repeating.
+const MAX_RESOLUTION: i32 = 128;
+
+/// The first dimension used for sample arrays. Dimensions before this are reserved for the
+/// camera sample (film position, lens position and time).
+const ARRAY_START_DIM: usize = 5;
+
+/// A global sampler using the Halton sequence over the whole image. Each pixel gets the
+/// samples of the sequence whose first two dimensions fall inside it, so a pixel sample is
+/// fully determined by the pixel and its sample number. Dimensions after the first two are
+/// scrambled with random digit permutations.
+#[derive(Clone)]
+pub struct HaltonSampler {
+    state: SamplerState,
+    permutations: Arc<Vec<u16>>,
+    sample_at_pixel_center: bool,
+
+    base_scales: [u64; 2],
+    base_exponents: [u32; 2],
+    sample_stride: u64,
+    mult_inverse: [u64; 2],
+    offset_for_current_pixel: u64,
+
+    dimension: usize,
+    interval_sample_index: u64,
+    array_end_dim: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(samples_per_pixel: usize, sample_bounds: Bounds2i, sample_at_pixel_center: bool) -> Self {
+        let mut rng = Xoshiro256Plus::seed_from_u64(0);
+        let permutations = Arc::new(compute_radical_inverse_permutations(&mut rng));
+
+        // Find the smallest power of each base that covers the image resolution.
+        let res = sample_bounds.diagonal();
+        let mut base_scales = [1; 2];
+        let mut base_exponents = [0; 2];
+        for i in 0..2 {
+            let base = PRIMES[i];
+            while base_scales[i] < res[i].min(MAX_RESOLUTION) as u64 {
+                base_scales[i] *= base;
+                base_exponents[i] += 1;
+            }
+        }
+
+        let sample_stride = base_scales[0] * base_scales[1];
+        let mult_inverse = [
+            multiplicative_inverse(base_scales[1], base_scales[0]),
+            multiplicative_inverse(base_scales[0], base_scales[1]),
+        ];
+
+        Self {
+            state: SamplerState::new(samples_per_pixel),
+            permutations,
+            sample_at_pixel_center,
+            base_scales,
+            base_exponents,
+            sample_stride,
+            mult_inverse,
+            offset_for_current_pixel: 0,
+            dimension: 0,
+            interval_sample_index: 0,
+            array_end_dim: ARRAY_START_DIM,
+        }
+    }
+
+    /// Finds the index of the first sample of the sequence falling in `pixel`, by solving for
+    /// the index whose digits in the first two bases reverse to the pixel coordinates.
+    fn compute_pixel_offset(&self, pixel: Point2i) -> u64 {
+        if self.sample_stride == 1 {
+            return 0;
+        }
+
+        let pm = [
+            pixel.x.rem_euclid(MAX_RESOLUTION) as u64,
+            pixel.y.rem_euclid(MAX_RESOLUTION) as u64,
+        ];
+        let mut offset = 0;
+        for i in 0..2 {
+            let dim_offset = inverse_radical_inverse(PRIMES[i], pm[i], self.base_exponents[i]);
+            offset += dim_offset * (self.sample_stride / self.base_scales[i]) * self.mult_inverse[i];
+        }
+        offset % self.sample_stride
+    }
+
+    /// The global index of the `sample_num`th sample in the current pixel.
+    fn index_for_sample(&self, sample_num: u64) -> u64 {
+        self.offset_for_current_pixel + sample_num * self.sample_stride
+    }
+
+    fn permutation_for_dimension(&self, dim: usize) -> &[u16] {
+        let start = PRIME_SUMS[dim] as usize;
+        &self.permutations[start..start + PRIMES[dim] as usize]
+    }
+
+    fn sample_dimension(&self, index: u64, dim: usize) -> Float {
+        if self.sample_at_pixel_center && dim < 2 {
+            return 0.5;
+        }
+        match dim {
+            0 => radical_inverse(0, index >> self.base_exponents[0]),
+            1 => radical_inverse(1, index / self.base_scales[1]),
+            _ => scrambled_radical_inverse(dim, index, self.permutation_for_dimension(dim)),
+        }
+    }
+
+    fn next_dimension(&mut self, n_dims: usize) -> usize {
+        // Skip over the dimensions used for sample arrays, and start over past the end of the
+        // prime table.
+        if self.dimension + n_dims > ARRAY_START_DIM && self.dimension < self.array_end_dim {
+            self.dimension = self.array_end_dim;
+        }
+        if self.dimension + n_dims > PRIME_TABLE_SIZE {
+            self.dimension = self.array_end_dim;
+        }
+        let dim = self.dimension;
+        self.dimension += n_dims;
+        dim
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel(&mut self, pixel: Point2i) {
+        self.state.start_pixel(pixel);
+        self.offset_for_current_pixel = self.compute_pixel_offset(pixel);
+        self.dimension = 0;
+        self.interval_sample_index = self.index_for_sample(0);
+
+        let mut arrays_1d = std::mem::take(&mut self.state.sample_array_1d);
+        let mut arrays_2d = std::mem::take(&mut self.state.sample_array_2d);
+        self.array_end_dim = ARRAY_START_DIM + arrays_1d.len() + 2 * arrays_2d.len();
+
+        // The values for all pixel samples of an array come from consecutive sample indices
+        // in a single dimension.
+        for (i, array) in arrays_1d.iter_mut().enumerate() {
+            let dim = ARRAY_START_DIM + i;
+            for (j, x) in array.iter_mut().enumerate() {
+                *x = self.sample_dimension(self.index_for_sample(j as u64), dim);
+            }
+        }
+
+        let start_dim_2d = ARRAY_START_DIM + arrays_1d.len();
+        for (i, array) in arrays_2d.iter_mut().enumerate() {
+            let dim = start_dim_2d + 2 * i;
+            for (j, p) in array.iter_mut().enumerate() {
+                let index = self.index_for_sample(j as u64);
+                *p = Point2f::new(self.sample_dimension(index, dim), self.sample_dimension(index, dim + 1));
+            }
+        }
+
+        self.state.sample_array_1d = arrays_1d;
+        self.state.sample_array_2d = arrays_2d;
+    }
+
+    fn start_next_sample(&mut self) -> bool {
+        let more = self.state.start_next_sample();
+        self.dimension = 0;
+        self.interval_sample_index = self.index_for_sample(self.state.current_sample_index() as u64);
+        more
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let dim = self.next_dimension(1);
+        self.sample_dimension(self.interval_sample_index, dim)
+    }
+
+    fn get_2d(&mut self) -> Point2f {
+        let dim = self.next_dimension(2);
+        Point2f::new(
+            self.sample_dimension(self.interval_sample_index, dim),
+            self.sample_dimension(self.interval_sample_index, dim + 1),
+        )
+    }
+
+    fn request_1d_array(&mut self, len: usize) -> SampleArrayId {
+        self.state.request_1d_array(len)
+    }
+
+    fn request_2d_array(&mut self, len: usize) -> SampleArrayId {
+        self.state.request_2d_array(len)
+    }
+
+    fn get_1d_array(&self, id: SampleArrayId) -> &[Float] {
+        self.state.get_1d_array(id)
+    }
+
+    fn get_2d_array(&self, id: SampleArrayId) -> &[Point2f] {
+        self.state.get_2d_array(id)
+    }
+
+    fn clone_with_seed(&self, _seed: u64) -> Self where Self: Sized {
+        // The sequence is deterministic, so every clone generates the same samples.
+        self.clone()
+    }
+
+    fn boxed_clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        Box::new(self.clone_with_seed(seed))
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        self.state.samples_per_pixel
+    }
+
+    fn set_sample_number(&mut self, sample_num: u64) -> bool {
+        self.dimension = 0;
+        self.interval_sample_index = self.index_for_sample(sample_num);
+        self.state.set_sample_number(sample_num)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_sampler() -> HaltonSampler {
+        HaltonSampler::new(16, Bounds2i::with_bounds(Point2i::new(0, 0), Point2i::new(20, 10)), false)
+    }
+
+    #[test]
+    fn test_halton_film_samples_stay_in_pixel() {
+        // The offset found for each pixel should make the sequence's first two dimensions,
+        // scaled to the image, land in that pixel.
+        let sampler = make_sampler();
+        for &(x, y) in &[(0, 0), (3, 7), (19, 9)] {
+            let offset = sampler.compute_pixel_offset(Point2i::new(x, y));
+            for n in 0..16 {
+                let index = offset + n * sampler.sample_stride;
+                let px = radical_inverse(0, index) * sampler.base_scales[0] as Float;
+                let py = radical_inverse(1, index) * sampler.base_scales[1] as Float;
+                assert_eq!((px as i32, py as i32), (x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_halton_set_sample_number() {
+        let mut sampler = make_sampler();
+        sampler.start_pixel(Point2i::new(5, 5));
+        let mut samples = vec![];
+        while sampler.start_next_sample() {
+            samples.push((sampler.get_2d(), sampler.get_2d(), sampler.get_1d()));
+        }
+
+        for &n in &[7, 0, 15] {
+            sampler.set_sample_number(n as u64);
+            assert_eq!(samples[n], (sampler.get_2d(), sampler.get_2d(), sampler.get_1d()));
+        }
+    }
+}
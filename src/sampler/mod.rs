@@ -2,10 +2,13 @@ use cgmath::EuclideanSpace;
 
 use crate::{Float, Point2f, Point2i};
 use crate::camera::CameraSample;
-use std::cell::Cell;
-use std::sync::Arc;
+use crate::math::{mix_bits, ONE_MINUS_EPSILON};
 
 pub mod random;
+pub mod stratified;
+pub mod halton;
+pub mod sobol;
+pub mod zerotwosequence;
 
 #[derive(Copy, Clone)]
 pub struct SampleArrayId {
@@ -38,6 +41,9 @@ pub trait Sampler: Send {
 
     fn clone_with_seed(&self, seed: u64) -> Self where Self: Sized;
 
+    /// Object-safe version of `clone_with_seed`, used when the sampler is chosen at runtime.
+    fn boxed_clone_with_seed(&self, seed: u64) -> Box<dyn Sampler>;
+
     fn samples_per_pixel(&self) -> usize;
 
     fn get_camera_sample(&mut self, p_raster: Point2i) -> CameraSample {
@@ -53,24 +59,74 @@ pub trait Sampler: Send {
     fn set_sample_number(&mut self, sample_num: u64) -> bool;
 }
 
+impl Sampler for Box<dyn Sampler> {
+    fn start_pixel(&mut self, pixel: Point2i) {
+        (**self).start_pixel(pixel)
+    }
+
+    fn start_next_sample(&mut self) -> bool {
+        (**self).start_next_sample()
+    }
+
+    fn get_1d(&mut self) -> Float {
+        (**self).get_1d()
+    }
+
+    fn get_2d(&mut self) -> Point2f {
+        (**self).get_2d()
+    }
+
+    fn request_1d_array(&mut self, len: usize) -> SampleArrayId {
+        (**self).request_1d_array(len)
+    }
+
+    fn request_2d_array(&mut self, len: usize) -> SampleArrayId {
+        (**self).request_2d_array(len)
+    }
+
+    fn get_1d_array(&self, id: SampleArrayId) -> &[Float] {
+        (**self).get_1d_array(id)
+    }
+
+    fn get_2d_array(&self, id: SampleArrayId) -> &[Point2f] {
+        (**self).get_2d_array(id)
+    }
+
+    fn round_count(&self, n: usize) -> usize {
+        (**self).round_count(n)
+    }
+
+    fn clone_with_seed(&self, seed: u64) -> Self {
+        (**self).boxed_clone_with_seed(seed)
+    }
+
+    fn boxed_clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        (**self).boxed_clone_with_seed(seed)
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        (**self).samples_per_pixel()
+    }
+
+    fn get_camera_sample(&mut self, p_raster: Point2i) -> CameraSample {
+        (**self).get_camera_sample(p_raster)
+    }
+
+    fn set_sample_number(&mut self, sample_num: u64) -> bool {
+        (**self).set_sample_number(sample_num)
+    }
+}
+
 #[derive(Clone)]
 pub struct SamplerState {
     samples_per_pixel: usize,
     current_pixel: Point2i,
     current_pixel_sample_num: usize,
 
-//    sample_array_1d: Vec<Array2<Float>>,
-//    sample_array_2d: Vec<Array2<Point2f>>,
-
-    // Store a vector of grouped samples. For each group, store an array of samples of the
-    // requested size for
-//    sample_array_1d: Vec<Vec<Float>>,
-//    sample_array_2d: Vec<Vec<Point2f>>,
-//    samples_1d_array_sizes: Vec<usize>,
-//    samples_2d_array_sizes: Vec<usize>,
-//    array_1d_offset: Cell<usize>,
-//    array_2d_offset: Cell<usize>,
-
+    // For each requested array, the samples for every pixel sample are stored contiguously,
+    // i.e. the array for pixel sample `i` is at `[i * len..(i + 1) * len]`.
+    sample_array_1d: Vec<Vec<Float>>,
+    sample_array_2d: Vec<Vec<Point2f>>,
 }
 
 impl SamplerState {
@@ -79,68 +135,156 @@ impl SamplerState {
             samples_per_pixel,
             current_pixel: Point2i::new(0, 0),
             current_pixel_sample_num: 0,
-//            sample_array_1d: vec![],
-//            sample_array_2d: vec![],
+            sample_array_1d: vec![],
+            sample_array_2d: vec![],
         }
     }
 
     pub fn start_pixel(&mut self, p: Point2i) {
         self.current_pixel = p;
         self.current_pixel_sample_num = 0;
-//        self.array_1d_offset = 0.into();
-//        self.array_2d_offset = 0.into();
     }
 
     pub fn start_next_sample(&mut self) -> bool {
-//        self.array_1d_offset = 0.into();
-//        self.array_2d_offset = 0.into();
         self.current_pixel_sample_num += 1;
         self.current_pixel_sample_num <= self.samples_per_pixel
     }
 
+    pub fn set_sample_number(&mut self, sample_num: u64) -> bool {
+        self.current_pixel_sample_num = sample_num as usize + 1;
+        self.current_pixel_sample_num <= self.samples_per_pixel
+    }
+
+    /// The index of the pixel sample currently being generated. `start_next_sample` is called
+    /// before the first sample is taken, so this is one less than the sample counter.
+    pub fn current_sample_index(&self) -> usize {
+        self.current_pixel_sample_num.saturating_sub(1)
+    }
+
+    pub fn current_pixel(&self) -> Point2i {
+        self.current_pixel
+    }
+
     pub fn request_1d_array(&mut self, len: usize) -> SampleArrayId {
-//        let id = SampleArrayId {
-//            idx: self.sample_array_1d.len(),
-//            len
-//        };
-//        self.sample_array_1d.push(Array2::zeros((self.samples_per_pixel, len)));
-//        id
-//        self.sample_array_1d.push(vec!(0.0; len * self.samples_per_pixel as usize))
-        unimplemented!()
+        let id = SampleArrayId {
+            idx: self.sample_array_1d.len(),
+            len,
+        };
+        self.sample_array_1d.push(vec![0.0; len * self.samples_per_pixel]);
+        id
     }
 
     pub fn request_2d_array(&mut self, len: usize) -> SampleArrayId {
-//        let id = SampleArrayId {
-//            idx: self.sample_array_2d.len(),
-//            len
-//        };
-//        self.sample_array_2d.push(Array2::from_elem((self.samples_per_pixel, len), Point2f::origin()));
-//        id
-        unimplemented!()
+        let id = SampleArrayId {
+            idx: self.sample_array_2d.len(),
+            len,
+        };
+        self.sample_array_2d.push(vec![Point2f::origin(); len * self.samples_per_pixel]);
+        id
     }
 
     pub fn get_1d_array(&self, id: SampleArrayId) -> &[Float] {
-        unimplemented!()
-//        let sample_array = &self.sample_array_1d[id.idx];
-//        let arr = sample_array.row(self.current_pixel_sample_num);
-//        arr.as_slice().unwrap()
-
-//        let range = (self.current_pixel_sample_num * len .. (self.current_pixel_sample_num + 1) * len);
-//        let array = &self.sample_array_1d[self.array_1d_offset.get()][range];
-//        self.array_1d_offset.replace(self.array_1d_offset.get() + 1);
-//        array
+        let start = self.current_sample_index() * id.len;
+        &self.sample_array_1d[id.idx][start..start + id.len]
     }
 
     pub fn get_2d_array(&self, id: SampleArrayId) -> &[Point2f] {
-        unimplemented!()
-//        let sample_array = &self.sample_array_2d[id.idx];
-//        let arr = sample_array.row(self.current_pixel_sample_num);
-//        arr.as_slice().unwrap()
+        let start = self.current_sample_index() * id.len;
+        &self.sample_array_2d[id.idx][start..start + id.len]
+    }
 
-//        let range = (self.current_pixel_sample_num * len .. (self.current_pixel_sample_num + 1) * len);
-//        let array = &self.sample_array_2d[self.array_2d_offset.get()][range];
-//        self.array_2d_offset.replace(self.array_2d_offset.get() + 1);
-//        array
+    /// Iterates over the requested 1D arrays, each split into one chunk per pixel sample.
+    pub fn sample_arrays_1d_mut(&mut self) -> impl Iterator<Item=std::slice::ChunksMut<'_, Float>> + '_ {
+        let spp = self.samples_per_pixel;
+        self.sample_array_1d.iter_mut()
+            .map(move |arr| {
+                let len = (arr.len() / spp).max(1);
+                arr.chunks_mut(len)
+            })
+    }
+
+    /// Iterates over the requested 2D arrays, each split into one chunk per pixel sample.
+    pub fn sample_arrays_2d_mut(&mut self) -> impl Iterator<Item=std::slice::ChunksMut<'_, Point2f>> + '_ {
+        let spp = self.samples_per_pixel;
+        self.sample_array_2d.iter_mut()
+            .map(move |arr| {
+                let len = (arr.len() / spp).max(1);
+                arr.chunks_mut(len)
+            })
+    }
+}
+
+/// Per-dimension sample storage for samplers that generate all of the samples for a pixel
+/// up front in `start_pixel`, such as the stratified sampler. Dimensions past the number
+/// that were generated fall back to uniform values hashed from the pixel, the sample index,
+/// the dimension and the seed, so a sample can be regenerated with `set_sample_number`.
+#[derive(Clone)]
+pub struct PixelSamples {
+    samples_1d: Vec<Vec<Float>>,
+    samples_2d: Vec<Vec<Point2f>>,
+    current_1d_dim: usize,
+    current_2d_dim: usize,
+    pixel_hash: u64,
+    extra_dim: u64,
+}
+
+impl PixelSamples {
+    pub fn new(samples_per_pixel: usize, n_sampled_dimensions: usize) -> Self {
+        Self {
+            samples_1d: vec![vec![0.0; samples_per_pixel]; n_sampled_dimensions],
+            samples_2d: vec![vec![Point2f::origin(); samples_per_pixel]; n_sampled_dimensions],
+            current_1d_dim: 0,
+            current_2d_dim: 0,
+            pixel_hash: 0,
+            extra_dim: 0,
+        }
+    }
+
+    pub fn start_pixel(&mut self, pixel: Point2i, seed: u64) {
+        let pixel_bits = ((pixel.x as u32 as u64) << 32) | pixel.y as u32 as u64;
+        self.pixel_hash = mix_bits(pixel_bits ^ mix_bits(seed));
+        self.start_sample();
+    }
+
+    /// Resets the dimension counters; called whenever a new pixel sample is started.
+    pub fn start_sample(&mut self) {
+        self.current_1d_dim = 0;
+        self.current_2d_dim = 0;
+        self.extra_dim = 0;
+    }
+
+    pub fn samples_1d_mut(&mut self) -> impl Iterator<Item=&mut Vec<Float>> + '_ {
+        self.samples_1d.iter_mut()
+    }
+
+    pub fn samples_2d_mut(&mut self) -> impl Iterator<Item=&mut Vec<Point2f>> + '_ {
+        self.samples_2d.iter_mut()
+    }
+
+    pub fn get_1d(&mut self, sample_idx: usize) -> Float {
+        match self.samples_1d.get(self.current_1d_dim) {
+            Some(samples) => {
+                self.current_1d_dim += 1;
+                samples[sample_idx]
+            },
+            None => self.extra_sample(sample_idx),
+        }
+    }
+
+    pub fn get_2d(&mut self, sample_idx: usize) -> Point2f {
+        match self.samples_2d.get(self.current_2d_dim) {
+            Some(samples) => {
+                self.current_2d_dim += 1;
+                samples[sample_idx]
+            },
+            None => Point2f::new(self.extra_sample(sample_idx), self.extra_sample(sample_idx)),
+        }
+    }
+
+    fn extra_sample(&mut self, sample_idx: usize) -> Float {
+        let hash = mix_bits(self.pixel_hash ^ mix_bits(((sample_idx as u64) << 32) | self.extra_dim));
+        self.extra_dim += 1;
+        ((hash >> 40) as Float * 2.0f32.powi(-24)).min(ONE_MINUS_EPSILON)
     }
 }
 
@@ -149,55 +293,28 @@ mod tests {
     use super::*;
     use crate::sampler::random::RandomSampler;
 
-//    #[test]
-//    fn test_get_sample_arrays() {
-//        let mut sampler = RandomSampler::new_with_seed(2, 0);
-//
-//        let id1 = sampler.request_1d_array(10);
-//        let id2 = sampler.request_1d_array(7);
-//
-//        sampler.start_pixel((0, 0).into());
-//
-//        let arr = sampler.get_1d_array(id1);
-//        assert_eq!(arr.len(), 10);
-//        assert!(arr.iter().any(|&x| x > 0.0));
-//
-//        let arr = sampler.get_1d_array(id2);
-//        assert_eq!(arr.len(), 7);
-//        assert!(arr.iter().any(|&x| x > 0.0));
-//
-//        assert!(sampler.start_next_sample());
-//
-//        let arr = sampler.get_1d_array(id1);
-//        assert_eq!(arr.len(), 10);
-//        assert!(arr.iter().any(|&x| x > 0.0));
-//
-//        let arr = sampler.get_1d_array(id2);
-//        assert_eq!(arr.len(), 7);
-//        assert!(arr.iter().any(|&x| x > 0.0));
-//
-//        assert!(!sampler.start_next_sample());
-//
-//        sampler.start_pixel((1, 1).into());
-//
-//        let arr = sampler.get_1d_array(id1);
-//        assert_eq!(arr.len(), 10);
-//        assert!(arr.iter().any(|&x| x > 0.0));
-//
-//        let arr = sampler.get_1d_array(id2);
-//        assert_eq!(arr.len(), 7);
-//        assert!(arr.iter().any(|&x| x > 0.0));
-//
-//        assert!(sampler.start_next_sample());
-//
-//        let arr = sampler.get_1d_array(id1);
-//        assert_eq!(arr.len(), 10);
-//        assert!(arr.iter().any(|&x| x > 0.0));
-//
-//        let arr = sampler.get_1d_array(id2);
-//        assert_eq!(arr.len(), 7);
-//        assert!(arr.iter().any(|&x| x > 0.0));
-//
-//        assert!(!sampler.start_next_sample());
-//    }
+    #[test]
+    fn test_get_sample_arrays() {
+        let mut sampler = RandomSampler::new_with_seed(2, 0);
+
+        let id1 = sampler.request_1d_array(10);
+        let id2 = sampler.request_2d_array(7);
+
+        for &pixel in &[(0, 0), (1, 1)] {
+            sampler.start_pixel(pixel.into());
+
+            let mut previous = None;
+            while sampler.start_next_sample() {
+                let arr = sampler.get_1d_array(id1);
+                assert_eq!(arr.len(), 10);
+                assert!(arr.iter().any(|&x| x > 0.0));
+                assert_ne!(previous, Some(arr.to_vec()));
+                previous = Some(arr.to_vec());
+
+                let arr = sampler.get_2d_array(id2);
+                assert_eq!(arr.len(), 7);
+                assert!(arr.iter().any(|p| p.x > 0.0 && p.y > 0.0));
+            }
+        }
+    }
 }
@@ -21,13 +21,13 @@ impl Sampler for RandomSampler {
     fn start_pixel(&mut self, pixel: Point2i) {
         self.state.start_pixel(pixel);
         let rng = &mut self.rng;
-//        self.state.sample_array_1d.iter_mut().flatten().for_each(|x| {
-//            *x = rng.gen();
-//        });
-//
-//        self.state.sample_array_2d.iter_mut().flatten().for_each(|p| {
-//            *p = Point2f::new(rng.gen(), rng.gen());
-//        });
+        self.state.sample_array_1d.iter_mut().flatten().for_each(|x| {
+            *x = rng.gen();
+        });
+
+        self.state.sample_array_2d.iter_mut().flatten().for_each(|p| {
+            *p = Point2f::new(rng.gen(), rng.gen());
+        });
     }
 
     fn start_next_sample(&mut self) -> bool {
@@ -66,11 +66,15 @@ impl Sampler for RandomSampler {
         }
     }
 
+    fn boxed_clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        Box::new(self.clone_with_seed(seed))
+    }
+
     fn samples_per_pixel(&self) -> usize {
         self.state.samples_per_pixel
     }
 
     fn set_sample_number(&mut self, sample_num: u64) -> bool {
-        unimplemented!()
+        self.state.set_sample_number(sample_num)
     }
 }
@@ -0,0 +1,226 @@
+use crate::{Point2i, Point2f, Float, Bounds2i};
+use crate::math::{mix_bits, ONE_MINUS_EPSILON};
+use crate::sampler::{Sampler, SamplerState, SampleArrayId};
+use crate::lowdiscrepancy::{sobol_sample, sobol_interval_to_index, NUM_SOBOL_DIMENSIONS};
+
+/// The first dimension used for sample arrays. Dimensions before this are reserved for the
+/// camera sample (film position, lens position and time).
+const ARRAY_START_DIM: usize = 5;
+
+/// A global sampler using the Sobol' sequence over the whole image. The first two dimensions
+/// of the sequence are scaled to cover a square power of two resolution containing the image,
+/// and each pixel gets the samples of the sequence that fall inside it, so a pixel sample is
+/// fully determined by the pixel and its sample number.
+///
+/// Only the dimensions with tabulated direction numbers come from the sequence, and later
+/// ones are hashed from the sample index. The number of samples per pixel should be a power
+/// of two.
+#[derive(Clone)]
+pub struct SobolSampler {
+    state: SamplerState,
+    sample_bounds: Bounds2i,
+    resolution: i32,
+    log2_resolution: u32,
+
+    dimension: usize,
+    interval_sample_index: u64,
+    array_end_dim: usize,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: usize, sample_bounds: Bounds2i) -> Self {
+        let diag = sample_bounds.diagonal();
+        let resolution = (diag.x.max(diag.y).max(1) as u32).next_power_of_two();
+        Self {
+            state: SamplerState::new(samples_per_pixel.next_power_of_two()),
+            sample_bounds,
+            resolution: resolution as i32,
+            log2_resolution: resolution.trailing_zeros(),
+            dimension: 0,
+            interval_sample_index: 0,
+            array_end_dim: ARRAY_START_DIM,
+        }
+    }
+
+    /// The global index of the `sample_num`th sample in the current pixel.
+    fn index_for_sample(&self, sample_num: u64) -> u64 {
+        let pixel = self.state.current_pixel();
+        let min = self.sample_bounds.min;
+        sobol_interval_to_index(self.log2_resolution, sample_num, Point2i::new(pixel.x - min.x, pixel.y - min.y))
+    }
+
+    fn sample_dimension(&self, index: u64, dim: usize) -> Float {
+        if dim >= NUM_SOBOL_DIMENSIONS {
+            // Past the tabulated dimensions, hash the sample index instead. This is still
+            // determined by the sample, but isn't stratified.
+            let hash = mix_bits(index ^ mix_bits(dim as u64));
+            return ((hash >> 40) as Float * 2.0f32.powi(-24)).min(ONE_MINUS_EPSILON);
+        }
+        let v = sobol_sample(index, dim, 0);
+        if dim < 2 {
+            // Map the film position back from the whole image to an offset within the pixel.
+            let s = v * self.resolution as Float + self.sample_bounds.min[dim] as Float;
+            (s - self.state.current_pixel()[dim] as Float).clamp(0.0, ONE_MINUS_EPSILON)
+        } else {
+            v
+        }
+    }
+
+    fn next_dimension(&mut self, n_dims: usize) -> usize {
+        // Skip over the dimensions used for sample arrays.
+        if self.dimension + n_dims > ARRAY_START_DIM && self.dimension < self.array_end_dim {
+            self.dimension = self.array_end_dim;
+        }
+        let dim = self.dimension;
+        self.dimension += n_dims;
+        dim
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel(&mut self, pixel: Point2i) {
+        self.state.start_pixel(pixel);
+        self.dimension = 0;
+        self.interval_sample_index = self.index_for_sample(0);
+
+        let mut arrays_1d = std::mem::take(&mut self.state.sample_array_1d);
+        let mut arrays_2d = std::mem::take(&mut self.state.sample_array_2d);
+        self.array_end_dim = ARRAY_START_DIM + arrays_1d.len() + 2 * arrays_2d.len();
+
+        // The values for all pixel samples of an array come from consecutive samples of the
+        // pixel in a single dimension.
+        for (i, array) in arrays_1d.iter_mut().enumerate() {
+            let dim = ARRAY_START_DIM + i;
+            for (j, x) in array.iter_mut().enumerate() {
+                *x = self.sample_dimension(self.index_for_sample(j as u64), dim);
+            }
+        }
+
+        let start_dim_2d = ARRAY_START_DIM + arrays_1d.len();
+        for (i, array) in arrays_2d.iter_mut().enumerate() {
+            let dim = start_dim_2d + 2 * i;
+            for (j, p) in array.iter_mut().enumerate() {
+                let index = self.index_for_sample(j as u64);
+                *p = Point2f::new(self.sample_dimension(index, dim), self.sample_dimension(index, dim + 1));
+            }
+        }
+
+        self.state.sample_array_1d = arrays_1d;
+        self.state.sample_array_2d = arrays_2d;
+    }
+
+    fn start_next_sample(&mut self) -> bool {
+        let more = self.state.start_next_sample();
+        self.dimension = 0;
+        self.interval_sample_index = self.index_for_sample(self.state.current_sample_index() as u64);
+        more
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let dim = self.next_dimension(1);
+        self.sample_dimension(self.interval_sample_index, dim)
+    }
+
+    fn get_2d(&mut self) -> Point2f {
+        let dim = self.next_dimension(2);
+        Point2f::new(
+            self.sample_dimension(self.interval_sample_index, dim),
+            self.sample_dimension(self.interval_sample_index, dim + 1),
+        )
+    }
+
+    fn request_1d_array(&mut self, len: usize) -> SampleArrayId {
+        self.state.request_1d_array(len)
+    }
+
+    fn request_2d_array(&mut self, len: usize) -> SampleArrayId {
+        self.state.request_2d_array(len)
+    }
+
+    fn get_1d_array(&self, id: SampleArrayId) -> &[Float] {
+        self.state.get_1d_array(id)
+    }
+
+    fn get_2d_array(&self, id: SampleArrayId) -> &[Point2f] {
+        self.state.get_2d_array(id)
+    }
+
+    fn round_count(&self, n: usize) -> usize {
+        n.next_power_of_two()
+    }
+
+    fn clone_with_seed(&self, _seed: u64) -> Self where Self: Sized {
+        // The sequence is deterministic, so every clone generates the same samples.
+        self.clone()
+    }
+
+    fn boxed_clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        Box::new(self.clone_with_seed(seed))
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        self.state.samples_per_pixel
+    }
+
+    fn set_sample_number(&mut self, sample_num: u64) -> bool {
+        self.dimension = 0;
+        self.interval_sample_index = self.index_for_sample(sample_num);
+        self.state.set_sample_number(sample_num)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_sampler(spp: usize) -> SobolSampler {
+        SobolSampler::new(spp, Bounds2i::with_bounds(Point2i::new(0, 0), Point2i::new(20, 10)))
+    }
+
+    #[test]
+    fn test_sobol_film_samples_are_stratified() {
+        // The samples of a pixel are the points of a (0, 2)-sequence that fall inside it, so
+        // they're stratified within the pixel too.
+        let mut sampler = make_sampler(16);
+        sampler.start_pixel(Point2i::new(3, 4));
+
+        let mut film_cells = vec![];
+        while sampler.start_next_sample() {
+            let p = sampler.get_2d();
+            film_cells.push((p.y * 4.0) as usize * 4 + (p.x * 4.0) as usize);
+        }
+
+        film_cells.sort();
+        assert_eq!(film_cells, (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_sobol_dimensions_past_arrays() {
+        let mut sampler = make_sampler(8);
+        let id = sampler.request_2d_array(4);
+        sampler.start_pixel(Point2i::new(19, 9));
+        let mut dims = vec![];
+        while sampler.start_next_sample() {
+            assert_eq!(sampler.get_2d_array(id).len(), 4);
+            let samples: Vec<Float> = (0..2 * NUM_SOBOL_DIMENSIONS).map(|_| sampler.get_1d()).collect();
+            assert!(samples.iter().all(|&x| x >= 0.0 && x < 1.0));
+            dims.push(samples);
+        }
+        assert_ne!(dims[0], dims[1]);
+    }
+
+    #[test]
+    fn test_sobol_set_sample_number() {
+        let mut sampler = make_sampler(8);
+        sampler.start_pixel(Point2i::new(1, 2));
+        let mut samples = vec![];
+        while sampler.start_next_sample() {
+            samples.push((sampler.get_2d(), sampler.get_1d(), sampler.get_2d()));
+        }
+
+        for &n in &[3, 0, 7] {
+            sampler.set_sample_number(n as u64);
+            assert_eq!(samples[n], (sampler.get_2d(), sampler.get_1d(), sampler.get_2d()));
+        }
+    }
+}
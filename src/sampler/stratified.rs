@@ -0,0 +1,166 @@
+use crate::{Point2i, Point2f, Float};
+use rand_xoshiro::Xoshiro256Plus;
+use rand::SeedableRng;
+use crate::sampler::{Sampler, SamplerState, SampleArrayId, PixelSamples};
+use crate::sampling::{stratified_sample_1d, stratified_sample_2d, latin_hypercube, shuffle};
+
+/// Divides the pixel into `x_samples` by `y_samples` strata and places one sample in each,
+/// randomly jittered within the stratum unless `jitter` is false. The first
+/// `n_sampled_dimensions` 1D and 2D dimensions are stratified and shuffled independently;
+/// any further dimensions are uniform values hashed from the pixel, sample and seed.
+pub struct StratifiedSampler {
+    rng: Xoshiro256Plus,
+    seed: u64,
+    state: SamplerState,
+    pixel_samples: PixelSamples,
+    x_samples: usize,
+    y_samples: usize,
+    jitter: bool,
+}
+
+impl StratifiedSampler {
+    pub fn new_with_seed(x_samples: usize, y_samples: usize, jitter: bool, n_sampled_dimensions: usize, seed: u64) -> Self {
+        let spp = x_samples * y_samples;
+        Self {
+            rng: Xoshiro256Plus::seed_from_u64(seed),
+            seed,
+            state: SamplerState::new(spp),
+            pixel_samples: PixelSamples::new(spp, n_sampled_dimensions),
+            x_samples,
+            y_samples,
+            jitter,
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel(&mut self, pixel: Point2i) {
+        self.state.start_pixel(pixel);
+        self.pixel_samples.start_pixel(pixel, self.seed);
+
+        let rng = &mut self.rng;
+        let (nx, ny, jitter) = (self.x_samples, self.y_samples, self.jitter);
+
+        for samples in self.pixel_samples.samples_1d_mut() {
+            stratified_sample_1d(samples, rng, jitter);
+            shuffle(samples, rng);
+        }
+
+        for samples in self.pixel_samples.samples_2d_mut() {
+            stratified_sample_2d(samples, nx, ny, rng, jitter);
+            shuffle(samples, rng);
+        }
+
+        // Each pixel sample's array is stratified on its own. Since the array length needn't
+        // be a product of two integers, 2D arrays use Latin hypercube sampling.
+        for array in self.state.sample_arrays_1d_mut() {
+            for samples in array {
+                stratified_sample_1d(samples, rng, jitter);
+                shuffle(samples, rng);
+            }
+        }
+
+        for array in self.state.sample_arrays_2d_mut() {
+            for samples in array {
+                latin_hypercube(samples, rng);
+            }
+        }
+    }
+
+    fn start_next_sample(&mut self) -> bool {
+        self.pixel_samples.start_sample();
+        self.state.start_next_sample()
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let idx = self.state.current_sample_index();
+        self.pixel_samples.get_1d(idx)
+    }
+
+    fn get_2d(&mut self) -> Point2f {
+        let idx = self.state.current_sample_index();
+        self.pixel_samples.get_2d(idx)
+    }
+
+    fn request_1d_array(&mut self, len: usize) -> SampleArrayId {
+        self.state.request_1d_array(len)
+    }
+
+    fn request_2d_array(&mut self, len: usize) -> SampleArrayId {
+        self.state.request_2d_array(len)
+    }
+
+    fn get_1d_array(&self, id: SampleArrayId) -> &[Float] {
+        self.state.get_1d_array(id)
+    }
+
+    fn get_2d_array(&self, id: SampleArrayId) -> &[Point2f] {
+        self.state.get_2d_array(id)
+    }
+
+    fn clone_with_seed(&self, seed: u64) -> Self where Self: Sized {
+        Self {
+            rng: Xoshiro256Plus::seed_from_u64(seed),
+            seed,
+            state: self.state.clone(),
+            pixel_samples: self.pixel_samples.clone(),
+            x_samples: self.x_samples,
+            y_samples: self.y_samples,
+            jitter: self.jitter,
+        }
+    }
+
+    fn boxed_clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        Box::new(self.clone_with_seed(seed))
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        self.state.samples_per_pixel
+    }
+
+    fn set_sample_number(&mut self, sample_num: u64) -> bool {
+        self.pixel_samples.start_sample();
+        self.state.set_sample_number(sample_num)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stratified_samples_cover_every_stratum() {
+        let mut sampler = StratifiedSampler::new_with_seed(4, 2, true, 2, 0);
+        sampler.start_pixel(Point2i::new(0, 0));
+
+        let mut strata_1d = vec![];
+        let mut strata_2d = vec![];
+        while sampler.start_next_sample() {
+            strata_1d.push((sampler.get_1d() * 8.0) as usize);
+            let p = sampler.get_2d();
+            strata_2d.push(((p.y * 2.0) as usize) * 4 + (p.x * 4.0) as usize);
+        }
+        strata_1d.sort();
+        strata_2d.sort();
+
+        assert_eq!(strata_1d, (0..8).collect::<Vec<_>>());
+        assert_eq!(strata_2d, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_stratified_set_sample_number() {
+        // Only one dimension of each kind is stratified, the rest are hashed.
+        let mut sampler = StratifiedSampler::new_with_seed(2, 2, true, 1, 3);
+        sampler.start_pixel(Point2i::new(1, 2));
+        let mut samples = vec![];
+        while sampler.start_next_sample() {
+            samples.push((sampler.get_2d(), sampler.get_1d(), sampler.get_2d(), sampler.get_1d()));
+        }
+        assert_ne!(samples[0].2, samples[1].2);
+
+        for &n in &[3, 0, 2] {
+            sampler.set_sample_number(n as u64);
+            assert_eq!(samples[n], (sampler.get_2d(), sampler.get_1d(), sampler.get_2d(), sampler.get_1d()));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{Point2i, Point2f, Float};
+use crate::math::mix_bits;
+use rand_xoshiro::Xoshiro256Plus;
+use rand::SeedableRng;
+use crate::sampler::{Sampler, SamplerState, SampleArrayId, PixelSamples};
+use crate::lowdiscrepancy::{van_der_corput, sobol_2d};
+
+/// Generates the samples for each pixel from a randomly scrambled (0, 2)-sequence, which is
+/// stratified over every elementary interval rather than just a grid. Like the stratified
+/// sampler, dimensions are shuffled independently, but the number of samples per pixel is
+/// rounded up to a power of two.
+///
+/// The random number generator is reseeded from the pixel coordinates in `start_pixel`, so the
+/// samples of a pixel don't depend on which pixels were rendered before it.
+pub struct ZeroTwoSequenceSampler {
+    rng: Xoshiro256Plus,
+    seed: u64,
+    state: SamplerState,
+    pixel_samples: PixelSamples,
+}
+
+impl ZeroTwoSequenceSampler {
+    pub fn new_with_seed(samples_per_pixel: usize, n_sampled_dimensions: usize, seed: u64) -> Self {
+        let spp = samples_per_pixel.next_power_of_two();
+        Self {
+            rng: Xoshiro256Plus::seed_from_u64(seed),
+            seed,
+            state: SamplerState::new(spp),
+            pixel_samples: PixelSamples::new(spp, n_sampled_dimensions),
+        }
+    }
+}
+
+impl Sampler for ZeroTwoSequenceSampler {
+    fn start_pixel(&mut self, pixel: Point2i) {
+        self.state.start_pixel(pixel);
+        self.pixel_samples.start_pixel(pixel, self.seed);
+
+        let pixel_bits = ((pixel.x as u32 as u64) << 32) | pixel.y as u32 as u64;
+        self.rng = Xoshiro256Plus::seed_from_u64(mix_bits(pixel_bits ^ mix_bits(self.seed)));
+        let rng = &mut self.rng;
+
+        for samples in self.pixel_samples.samples_1d_mut() {
+            van_der_corput(1, samples, rng);
+        }
+
+        for samples in self.pixel_samples.samples_2d_mut() {
+            sobol_2d(1, samples, rng);
+        }
+
+        let spp = self.state.samples_per_pixel;
+        for array in self.state.sample_array_1d.iter_mut().filter(|a| !a.is_empty()) {
+            let len = array.len() / spp;
+            van_der_corput(len, array, rng);
+        }
+
+        for array in self.state.sample_array_2d.iter_mut().filter(|a| !a.is_empty()) {
+            let len = array.len() / spp;
+            sobol_2d(len, array, rng);
+        }
+    }
+
+    fn start_next_sample(&mut self) -> bool {
+        self.pixel_samples.start_sample();
+        self.state.start_next_sample()
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let idx = self.state.current_sample_index();
+        self.pixel_samples.get_1d(idx)
+    }
+
+    fn get_2d(&mut self) -> Point2f {
+        let idx = self.state.current_sample_index();
+        self.pixel_samples.get_2d(idx)
+    }
+
+    fn request_1d_array(&mut self, len: usize) -> SampleArrayId {
+        self.state.request_1d_array(len)
+    }
+
+    fn request_2d_array(&mut self, len: usize) -> SampleArrayId {
+        self.state.request_2d_array(len)
+    }
+
+    fn get_1d_array(&self, id: SampleArrayId) -> &[Float] {
+        self.state.get_1d_array(id)
+    }
+
+    fn get_2d_array(&self, id: SampleArrayId) -> &[Point2f] {
+        self.state.get_2d_array(id)
+    }
+
+    fn round_count(&self, n: usize) -> usize {
+        n.next_power_of_two()
+    }
+
+    fn clone_with_seed(&self, _seed: u64) -> Self where Self: Sized {
+        // The generator is reseeded for every pixel, so the clone keeps the original seed.
+        Self {
+            rng: self.rng.clone(),
+            seed: self.seed,
+            state: self.state.clone(),
+            pixel_samples: self.pixel_samples.clone(),
+        }
+    }
+
+    fn boxed_clone_with_seed(&self, seed: u64) -> Box<dyn Sampler> {
+        Box::new(self.clone_with_seed(seed))
+    }
+
+    fn samples_per_pixel(&self) -> usize {
+        self.state.samples_per_pixel
+    }
+
+    fn set_sample_number(&mut self, sample_num: u64) -> bool {
+        self.pixel_samples.start_sample();
+        self.state.set_sample_number(sample_num)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_two_sequence_is_stratified() {
+        let mut sampler = ZeroTwoSequenceSampler::new_with_seed(12, 2, 0);
+        assert_eq!(sampler.samples_per_pixel(), 16);
+        let id = sampler.request_2d_array(sampler.round_count(3));
+
+        sampler.start_pixel(Point2i::new(0, 0));
+        let mut strata = vec![];
+        while sampler.start_next_sample() {
+            let p = sampler.get_2d();
+            strata.push(((p.x * 16.0) as usize, (p.y * 16.0) as usize));
+
+            let mut array_strata: Vec<usize> = sampler.get_2d_array(id).iter()
+                .map(|p| (p.x * 4.0) as usize)
+                .collect();
+            array_strata.sort();
+            assert_eq!(array_strata, vec![0, 1, 2, 3]);
+        }
+
+        let (mut xs, mut ys): (Vec<_>, Vec<_>) = strata.into_iter().unzip();
+        xs.sort();
+        ys.sort();
+        assert_eq!(xs, (0..16).collect::<Vec<_>>());
+        assert_eq!(ys, (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_zero_two_sequence_set_sample_number() {
+        let mut sampler = ZeroTwoSequenceSampler::new_with_seed(4, 1, 5);
+        sampler.start_pixel(Point2i::new(7, 3));
+        let mut samples = vec![];
+        while sampler.start_next_sample() {
+            samples.push((sampler.get_2d(), sampler.get_2d(), sampler.get_1d(), sampler.get_1d()));
+        }
+
+        for &n in &[2, 0, 3] {
+            sampler.set_sample_number(n as u64);
+            assert_eq!(samples[n], (sampler.get_2d(), sampler.get_2d(), sampler.get_1d(), sampler.get_1d()));
+        }
+    }
+}
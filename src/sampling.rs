@@ -1,6 +1,8 @@
 use crate::{Point2f, Vec2f, Vec3f, Float, Point3f};
 use std::f32;
 use rand::Rng;
+use rand::seq::SliceRandom;
+use crate::math::ONE_MINUS_EPSILON;
 
 pub fn concentric_sample_disk(u: Point2f) -> Point2f {
     // map sample from [0, 1] to [-1, 1]
@@ -50,12 +52,91 @@ pub fn uniform_sample_triangle(u: Point2f) -> Point2f {
     Point2f::new(1.0 - su0, u[1] * su0)
 }
 
+/// Samples `x` in [0, 1] with a density that varies linearly from `a` at 0 to `b` at 1.
+pub fn sample_linear(u: Float, a: Float, b: Float) -> Float {
+    if u == 0.0 && a == 0.0 {
+        return 0.0;
+    }
+    let x = u * (a + b) / (a + Float::sqrt(crate::math::lerp(u, a * a, b * b)));
+    x.min(ONE_MINUS_EPSILON)
+}
+
+/// Samples a point in the unit square with a density proportional to the bilinear
+/// interpolation of the corner weights `w`, given in the order (0, 0), (1, 0), (0, 1), (1, 1).
+pub fn sample_bilinear(u: Point2f, w: [Float; 4]) -> Point2f {
+    let y = sample_linear(u[1], w[0] + w[1], w[2] + w[3]);
+    let x = sample_linear(u[0], crate::math::lerp(y, w[0], w[2]), crate::math::lerp(y, w[1], w[3]));
+    Point2f::new(x, y)
+}
+
+pub fn bilinear_pdf(p: Point2f, w: [Float; 4]) -> Float {
+    let sum = w[0] + w[1] + w[2] + w[3];
+    if sum == 0.0 {
+        return 1.0;
+    }
+    4.0 * ((1.0 - p[0]) * (1.0 - p[1]) * w[0] + p[0] * (1.0 - p[1]) * w[1] +
+        (1.0 - p[0]) * p[1] * w[2] + p[0] * p[1] * w[3]) / sum
+}
+
 pub fn power_heuristic(nf: u32, f_pdf: Float, ng: u32, g_pdf: Float) -> Float {
     let f = nf as Float * f_pdf;
     let g = ng  as Float * g_pdf;
     (f * f) / (f * f + g * g)
 }
 
+/// Fills `samples` with one sample from each of `samples.len()` equal strata of [0, 1).
+/// If `jitter` is false the samples are placed at the center of each stratum.
+pub fn stratified_sample_1d(samples: &mut [Float], rng: &mut impl Rng, jitter: bool) {
+    let inv_n_samples = 1.0 / samples.len() as Float;
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let delta: Float = if jitter { rng.gen() } else { 0.5 };
+        *sample = ((i as Float + delta) * inv_n_samples).min(ONE_MINUS_EPSILON);
+    }
+}
+
+/// Fills `samples` with one sample from each cell of an `nx` by `ny` grid over [0, 1)^2.
+pub fn stratified_sample_2d(samples: &mut [Point2f], nx: usize, ny: usize, rng: &mut impl Rng, jitter: bool) {
+    debug_assert_eq!(samples.len(), nx * ny);
+    let dx = 1.0 / nx as Float;
+    let dy = 1.0 / ny as Float;
+    for y in 0..ny {
+        for x in 0..nx {
+            let (jx, jy): (Float, Float) = if jitter { (rng.gen(), rng.gen()) } else { (0.5, 0.5) };
+            samples[y * nx + x] = Point2f::new(
+                ((x as Float + jx) * dx).min(ONE_MINUS_EPSILON),
+                ((y as Float + jy) * dy).min(ONE_MINUS_EPSILON),
+            );
+        }
+    }
+}
+
+/// Generates an arbitrary number of 2D samples that are well distributed in each dimension
+/// separately: each dimension is stratified into `samples.len()` strata and then the
+/// dimensions are shuffled independently of each other.
+pub fn latin_hypercube(samples: &mut [Point2f], rng: &mut impl Rng) {
+    let n_samples = samples.len();
+    let inv_n_samples = 1.0 / n_samples as Float;
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let x = (i as Float + rng.gen::<Float>()) * inv_n_samples;
+        let y = (i as Float + rng.gen::<Float>()) * inv_n_samples;
+        *sample = Point2f::new(x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON));
+    }
+
+    for dim in 0..2 {
+        for i in 0..n_samples {
+            let other = rng.gen_range(i, n_samples);
+            let tmp = samples[i][dim];
+            samples[i][dim] = samples[other][dim];
+            samples[other][dim] = tmp;
+        }
+    }
+}
+
+/// Randomly permutes the samples, decorrelating the strata chosen for different dimensions.
+pub fn shuffle<T>(samples: &mut [T], rng: &mut impl Rng) {
+    samples.shuffle(rng);
+}
+
 #[derive(Debug)]
 pub struct Distribution1D {
     func: Vec<Float>,
@@ -197,6 +278,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sample_bilinear_matches_pdf() {
+        let w = [1.0, 3.0, 2.0, 0.5];
+        // The sampled point's cdf along each axis should match the uniform sample.
+        let p = sample_bilinear(Point2f::new(0.5, 0.5), w);
+        let y_cdf = {
+            let (a, b) = (w[0] + w[1], w[2] + w[3]);
+            p.y * (a + 0.5 * (b - a) * p.y) / (0.5 * (a + b))
+        };
+        assert!((y_cdf - 0.5).abs() < 1e-4, "{}", y_cdf);
+        assert!(bilinear_pdf(p, w) > 0.0);
+
+        // Constant weights give uniform samples.
+        let p = sample_bilinear(Point2f::new(0.25, 0.75), [1.0; 4]);
+        assert!((p.x - 0.25).abs() < 1e-5 && (p.y - 0.75).abs() < 1e-5);
+        assert_eq!(bilinear_pdf(p, [1.0; 4]), 1.0);
+    }
+
+    #[test]
+    fn test_latin_hypercube_stratifies_each_dimension() {
+        use rand::SeedableRng;
+        let mut rng = rand_xoshiro::Xoshiro256Plus::seed_from_u64(7);
+        let mut samples = vec![Point2f::origin(); 16];
+        latin_hypercube(&mut samples, &mut rng);
+        for dim in 0..2 {
+            let mut strata: Vec<usize> = samples.iter().map(|p| (p[dim] * 16.0) as usize).collect();
+            strata.sort();
+            assert_eq!(strata, (0..16).collect::<Vec<_>>());
+        }
+    }
+
     #[test]
     fn test_concentric_sample_disk() {
         for _ in 0..100 {
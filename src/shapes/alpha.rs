@@ -0,0 +1,161 @@
+use crate::{Float, Transform, Point2f, Vec3f};
+use crate::geometry::Ray;
+use crate::geometry::bounds::Bounds3f;
+use crate::interaction::{SurfaceHit, SurfaceInteraction};
+use crate::math::mix_bits;
+use crate::shapes::Shape;
+use crate::texture::{Texture, TextureRef};
+
+/// Whether a hit survives an alpha mask. Hits with an alpha of zero are always skipped, and
+/// hits with a fractional alpha are kept with probability equal to the alpha, using a hash of
+/// the ray so that the same ray always makes the same choice.
+pub fn alpha_test(alpha: &TextureRef<Float>, ray: &Ray, isect: &SurfaceInteraction) -> bool {
+    let a = alpha.evaluate(isect);
+    if a >= 1.0 {
+        true
+    } else if a <= 0.0 {
+        false
+    } else {
+        hash_ray(ray) < a
+    }
+}
+
+/// Hashes the ray's origin and direction to a value in [0, 1).
+fn hash_ray(ray: &Ray) -> Float {
+    let components = [ray.origin.x, ray.origin.y, ray.origin.z, ray.dir.x, ray.dir.y, ray.dir.z];
+    let hash = components.iter()
+        .fold(0u64, |h, c| mix_bits(h ^ c.to_bits() as u64));
+    // Use the top 24 bits so the result is exactly representable and less than one.
+    (hash >> 40) as Float / (1u64 << 24) as Float
+}
+
+/// A shape with alpha masks, for shapes that don't store them themselves. Hits that are
+/// masked out continue along the ray, so later hits on the same shape can still be found.
+pub struct AlphaMasked<S: Shape> {
+    shape: S,
+    alpha: Option<TextureRef<Float>>,
+    shadow_alpha: Option<TextureRef<Float>>,
+}
+
+impl<S: Shape> AlphaMasked<S> {
+    pub fn new(shape: S, alpha: Option<TextureRef<Float>>, shadow_alpha: Option<TextureRef<Float>>) -> Self {
+        Self { shape, alpha, shadow_alpha }
+    }
+
+    fn passes(&self, ray: &Ray, isect: &SurfaceInteraction, shadow_ray: bool) -> bool {
+        let shadow_alpha = if shadow_ray { self.shadow_alpha.as_ref() } else { None };
+        self.alpha.iter().chain(shadow_alpha)
+            .all(|alpha| alpha_test(alpha, ray, isect))
+    }
+
+    fn intersect_masked(&self, ray: &Ray, shadow_ray: bool) -> Option<(Float, SurfaceInteraction)> {
+        let mut ray = *ray;
+        let mut t_offset = 0.0;
+        loop {
+            let (t_hit, isect) = self.shape.intersect(&ray)?;
+            if self.passes(&ray, &isect, shadow_ray) {
+                return Some((t_offset + t_hit, isect));
+            }
+            // The continuation ray has the same direction, so its parametric distances add on
+            // to those of the original ray.
+            let t_max = ray.t_max - t_hit;
+            ray = isect.hit.spawn_ray(ray.dir);
+            ray.t_max = t_max;
+            t_offset += t_hit;
+        }
+    }
+}
+
+impl<S: Shape> Shape for AlphaMasked<S> {
+    fn object_bound(&self) -> Bounds3f {
+        self.shape.object_bound()
+    }
+
+    fn world_bound(&self) -> Bounds3f {
+        self.shape.world_bound()
+    }
+
+    fn object_to_world(&self) -> &Transform {
+        self.shape.object_to_world()
+    }
+
+    fn world_to_object(&self) -> &Transform {
+        self.shape.world_to_object()
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.shape.reverse_orientation()
+    }
+
+    fn flip_normals(&self) -> bool {
+        self.shape.flip_normals()
+    }
+
+    fn area(&self) -> Float {
+        self.shape.area()
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+        self.intersect_masked(ray, false)
+    }
+
+    fn intersect_test(&self, ray: &Ray) -> bool {
+        self.intersect_masked(ray, true).is_some()
+    }
+
+    fn sample(&self, u: Point2f) -> SurfaceHit {
+        self.shape.sample(u)
+    }
+
+    fn pdf(&self, hit: &SurfaceHit) -> Float {
+        self.shape.pdf(hit)
+    }
+
+    fn sample_from_ref(&self, reference: &SurfaceHit, u: Point2f) -> SurfaceHit {
+        self.shape.sample_from_ref(reference, u)
+    }
+
+    fn pdf_from_ref(&self, reference: &SurfaceHit, wi: Vec3f) -> Float {
+        self.shape.pdf_from_ref(reference, wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::Point3f;
+    use crate::shapes::sphere::Sphere;
+    use crate::texture::ConstantTexture;
+
+    #[test]
+    fn test_alpha_masked_sphere() {
+        let sphere = || Sphere::whole(Transform::IDENTITY, Transform::IDENTITY, 1.0);
+        let ray = Ray::new(Point3f::new(0.0, 0.0, -3.0), vec3f!(0.0, 0.0, 1.0));
+
+        let opaque = AlphaMasked::new(sphere(), Some(Arc::new(ConstantTexture(1.0))), None);
+        let (t, _) = opaque.intersect(&ray).expect("opaque sphere should be hit");
+        assert_abs_diff_eq!(t, 2.0, epsilon = 1e-4);
+
+        let cutout = AlphaMasked::new(sphere(), Some(Arc::new(ConstantTexture(0.0))), None);
+        assert!(cutout.intersect(&ray).is_none());
+        assert!(!cutout.intersect_test(&ray));
+
+        // A shadow mask only hides the shape from shadow rays.
+        let no_shadow = AlphaMasked::new(sphere(), None, Some(Arc::new(ConstantTexture(0.0))));
+        assert!(no_shadow.intersect(&ray).is_some());
+        assert!(!no_shadow.intersect_test(&ray));
+    }
+
+    #[test]
+    fn test_hash_ray_in_unit_interval() {
+        for i in 0..100 {
+            let ray = Ray::new(Point3f::new(i as Float, 0.5, -1.0), vec3f!(0.1, 0.2, i as Float));
+            let u = hash_ray(&ray);
+            assert!(u >= 0.0 && u < 1.0);
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::sync::Arc;
+
+use cgmath::{EuclideanSpace, InnerSpace};
+
+use crate::{Point3f, Transform, Bounds3f, Ray, Float, SurfaceInteraction, Normal3, Vec3f, Point2f, ComponentWiseExt, coordinate_system, faceforward};
+use crate::err_float::gamma;
+use crate::interaction::{DiffGeom, SurfaceHit};
+use crate::sampling::{sample_bilinear, bilinear_pdf};
+use crate::shapes::{Shape, normal_derivatives};
+
+/// A mesh of bilinear patches, each given by the four vertices at its (u, v) corners in the
+/// order (0, 0), (1, 0), (0, 1), (1, 1). Like `TriangleMesh`, the vertices are stored in world
+/// space.
+#[derive(Debug)]
+pub struct BilinearPatchMesh {
+    pub n_patches: u32,
+
+    vertex_indices: Vec<u32>,
+
+    vertices: Vec<Point3f>,
+
+    normals: Option<Vec<Normal3>>,
+
+    tex_coords: Option<Vec<Point2f>>,
+
+    reverse_orientation: bool,
+
+    object_to_world: Transform,
+
+    world_to_object: Transform,
+}
+
+impl BilinearPatchMesh {
+    pub fn new(
+        object_to_world: Transform,
+        vertex_indices: Vec<u32>,
+        mut vertices: Vec<Point3f>,
+        mut normals: Option<Vec<Normal3>>,
+        tex_coords: Option<Vec<Point2f>>,
+        reverse_orientation: bool,
+    ) -> Self {
+        assert_eq!(vertex_indices.len() % 4, 0);
+        let n_patches = vertex_indices.len() as u32 / 4;
+        let n_vertices = vertices.len();
+
+        for v in &mut vertices {
+            *v = object_to_world.transform(*v);
+        }
+
+        if let Some(ref mut normals) = normals {
+            assert_eq!(normals.len(), n_vertices);
+            for n in normals {
+                *n = object_to_world.transform(*n);
+            }
+        }
+
+        if let Some(ref tex_coords) = tex_coords {
+            assert_eq!(tex_coords.len(), n_vertices);
+        }
+
+        Self {
+            n_patches,
+            vertex_indices,
+            vertices,
+            normals,
+            tex_coords,
+            reverse_orientation,
+            world_to_object: object_to_world.inverse(),
+            object_to_world,
+        }
+    }
+
+    pub fn iter_patches(self: Arc<Self>) -> impl Iterator<Item=BilinearPatch> {
+        (0..self.n_patches).map(move |patch_id| {
+            BilinearPatch::new(Arc::clone(&self), patch_id)
+        })
+    }
+}
+
+pub struct BilinearPatch {
+    mesh: Arc<BilinearPatchMesh>,
+    patch_id: u32,
+    area: Float,
+}
+
+impl BilinearPatch {
+    pub fn new(mesh: Arc<BilinearPatchMesh>, patch_id: u32) -> Self {
+        let mut patch = Self { mesh, patch_id, area: 0.0 };
+        patch.area = patch.compute_area();
+        patch
+    }
+
+    fn vertex_indices(&self) -> [usize; 4] {
+        let idx = 4 * self.patch_id as usize;
+        let v = &self.mesh.vertex_indices;
+        [v[idx] as usize, v[idx + 1] as usize, v[idx + 2] as usize, v[idx + 3] as usize]
+    }
+
+    fn get_vertices(&self) -> [Point3f; 4] {
+        let v = self.vertex_indices();
+        let p = &self.mesh.vertices;
+        [p[v[0]], p[v[1]], p[v[2]], p[v[3]]]
+    }
+
+    fn get_uvs(&self) -> Option<[Point2f; 4]> {
+        self.mesh.tex_coords.as_ref().map(|uvs| {
+            let v = self.vertex_indices();
+            [uvs[v[0]], uvs[v[1]], uvs[v[2]], uvs[v[3]]]
+        })
+    }
+
+    fn get_normals(&self) -> Option<[Normal3; 4]> {
+        self.mesh.normals.as_ref().map(|normals| {
+            let v = self.vertex_indices();
+            [normals[v[0]], normals[v[1]], normals[v[2]], normals[v[3]]]
+        })
+    }
+
+    /// Whether the patch is a planar rectangle, which can be sampled uniformly in (u, v).
+    fn is_rectangle(&self) -> bool {
+        let [p00, p10, p01, p11] = self.get_vertices();
+        if p00 == p01 || p01 == p11 || p11 == p10 || p10 == p00 {
+            return false;
+        }
+        let n = (p10 - p00).cross(p01 - p00).normalize();
+        if (p11 - p00).normalize().dot(n).abs() > 1e-5 {
+            return false;
+        }
+        // A planar quad is a rectangle if its center is the same distance from all corners.
+        let center = Point3f::from_vec((p00.to_vec() + p01.to_vec() + p10.to_vec() + p11.to_vec()) / 4.0);
+        let d2 = [p00, p10, p01, p11].iter()
+            .map(|&p| (p - center).magnitude2())
+            .collect::<Vec<_>>();
+        d2[1..].iter().all(|&d| (d - d2[0]).abs() <= 1e-4 * d2[0])
+    }
+
+    fn compute_area(&self) -> Float {
+        let [p00, p10, p01, p11] = self.get_vertices();
+        if self.is_rectangle() {
+            return (p01 - p00).magnitude() * (p10 - p00).magnitude();
+        }
+
+        // Approximate the area by splitting the patch into a grid of quadrilaterals.
+        const N: usize = 3;
+        let point = |i: usize, j: usize| {
+            self.point_at(Point2f::new(i as Float / N as Float, j as Float / N as Float), p00, p10, p01, p11)
+        };
+        let mut area = 0.0;
+        for i in 0..N {
+            for j in 0..N {
+                let diag0 = point(i + 1, j + 1) - point(i, j);
+                let diag1 = point(i + 1, j) - point(i, j + 1);
+                area += 0.5 * diag0.cross(diag1).magnitude();
+            }
+        }
+        area
+    }
+
+    fn point_at(&self, uv: Point2f, p00: Point3f, p10: Point3f, p01: Point3f, p11: Point3f) -> Point3f {
+        let p0 = p00.to_vec() * (1.0 - uv.y) + p01.to_vec() * uv.y;
+        let p1 = p10.to_vec() * (1.0 - uv.y) + p11.to_vec() * uv.y;
+        Point3f::from_vec(p0 * (1.0 - uv.x) + p1 * uv.x)
+    }
+
+    /// The partial derivatives of the position with respect to the patch's own (u, v).
+    fn derivatives(uv: Point2f, p00: Point3f, p10: Point3f, p01: Point3f, p11: Point3f) -> (Vec3f, Vec3f) {
+        let dpdu = (p10 - p00) * (1.0 - uv.y) + (p11 - p01) * uv.y;
+        let dpdv = (p01 - p00) * (1.0 - uv.x) + (p11 - p10) * uv.x;
+        (dpdu, dpdv)
+    }
+
+    /// Weights proportional to the area density at each corner, for sampling non-rectangular
+    /// patches.
+    fn corner_weights(&self) -> [Float; 4] {
+        let [p00, p10, p01, p11] = self.get_vertices();
+        [
+            (p10 - p00).cross(p01 - p00).magnitude(),
+            (p10 - p00).cross(p11 - p10).magnitude(),
+            (p01 - p00).cross(p11 - p01).magnitude(),
+            (p11 - p10).cross(p11 - p01).magnitude(),
+        ]
+    }
+
+    /// Finds the patch's (u, v) for a point on it, by projecting the patch onto the plane
+    /// where it has the largest extent and inverting the bilinear interpolation there. Returns
+    /// `None` if the point isn't on the patch.
+    fn invert(&self, p: Point3f) -> Option<Point2f> {
+        let [p00, p10, p01, p11] = self.get_vertices();
+        let n = (p10 - p00).cross(p11 - p00) + (p11 - p00).cross(p01 - p00);
+        let n = n.abs();
+        let (i, j) = if n.x > n.y && n.x > n.z {
+            (1, 2)
+        } else if n.y > n.z {
+            (0, 2)
+        } else {
+            (0, 1)
+        };
+        let project = |p: Point3f| Point2f::new(p[i], p[j]);
+        invert_bilinear(project(p), [project(p00), project(p10), project(p01), project(p11)])
+    }
+}
+
+/// Solves `a x^2 + b x + c = 0` in floating point, falling back to the linear equation when
+/// `a` is zero, as it is for planar parallelograms.
+fn quadratic(a: Float, b: Float, c: Float) -> Option<(Float, Float)> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        let t = -c / b;
+        return Some((t, t));
+    }
+    let discrim = b as f64 * b as f64 - 4.0 * a as f64 * c as f64;
+    if discrim < 0.0 {
+        return None;
+    }
+    let root_discrim = discrim.sqrt() as Float;
+    let q = -0.5 * (b + root_discrim.copysign(b));
+    let (t0, t1) = (q / a, c / q);
+    if t0 > t1 { Some((t1, t0)) } else { Some((t0, t1)) }
+}
+
+/// Inverts the bilinear interpolation of the 2D corners `v`, given in the order (0, 0),
+/// (1, 0), (0, 1), (1, 1). Returns `None` if no solution lies inside the patch.
+fn invert_bilinear(p: Point2f, v: [Point2f; 4]) -> Option<Point2f> {
+    let cross2d = |a: Vec3f, b: Vec3f| a.x * b.y - a.y * b.x;
+    let to_vec = |p: Point2f| vec3f!(p.x, p.y, 0.0);
+    let e = to_vec(v[1]) - to_vec(v[0]);
+    let f = to_vec(v[2]) - to_vec(v[0]);
+    let g = to_vec(v[0]) - to_vec(v[1]) + to_vec(v[3]) - to_vec(v[2]);
+    let h = to_vec(p) - to_vec(v[0]);
+
+    let k2 = cross2d(g, f);
+    let k1 = cross2d(e, f) + cross2d(h, g);
+    let k0 = cross2d(h, e);
+
+    // With parallel edges, the equation for v is linear.
+    let (v0, v1) = if k2.abs() < 1e-6 {
+        let v = -k0 / k1;
+        (v, v)
+    } else {
+        quadratic(k2, k1, k0)?
+    };
+    let u_at = |v: Float| if (e.x + g.x * v).abs() > (e.y + g.y * v).abs() {
+        (h.x - f.x * v) / (e.x + g.x * v)
+    } else {
+        (h.y - f.y * v) / (e.y + g.y * v)
+    };
+
+    // Allow for a little round-off at the edges of the patch, and clamp it away.
+    const EPS: Float = 1e-3;
+    let in_patch = |x: Float| (-EPS..=1.0 + EPS).contains(&x);
+    [v0, v1].iter()
+        .map(|&v| Point2f::new(u_at(v), v))
+        .find(|uv| in_patch(uv.x) && in_patch(uv.y))
+        .map(|uv| Point2f::new(uv.x.clamp(0.0, 1.0), uv.y.clamp(0.0, 1.0)))
+}
+
+impl Shape for BilinearPatch {
+    fn object_bound(&self) -> Bounds3f {
+        // The vertices are already in world space.
+        self.world_bound()
+    }
+
+    fn world_bound(&self) -> Bounds3f {
+        let [p00, p10, p01, p11] = self.get_vertices();
+        Bounds3f::empty().join_point(p00).join_point(p10).join_point(p01).join_point(p11)
+    }
+
+    fn object_to_world(&self) -> &Transform {
+        &self.mesh.object_to_world
+    }
+
+    fn world_to_object(&self) -> &Transform {
+        &self.mesh.world_to_object
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.mesh.reverse_orientation
+    }
+
+    fn area(&self) -> Float {
+        self.area
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+        let [p00, p10, p01, p11] = self.get_vertices();
+
+        // Find the u values where the ray meets the line between the points at u on the
+        // v = 0 and v = 1 edges.
+        let a = (p10 - p00).cross(p01 - p11).dot(ray.dir);
+        let c = (p00 - ray.origin).cross(ray.dir).dot(p01 - p00);
+        let b = (p10 - ray.origin).cross(ray.dir).dot(p11 - p10) - (a + c);
+        let (u1, u2) = quadratic(a, b, c)?;
+
+        let max_comp = |v: Vec3f| v.x.abs().max(v.y.abs()).max(v.z.abs());
+        let eps = gamma(10) * (max_comp(ray.origin.to_vec()) + max_comp(ray.dir) +
+            max_comp(p00.to_vec()) + max_comp(p10.to_vec()) + max_comp(p01.to_vec()) + max_comp(p11.to_vec()));
+
+        // For a root u, find v and t on the line across the patch at u.
+        let hit_at = |u: Float| -> Option<(Float, Float)> {
+            if !(0.0..=1.0).contains(&u) {
+                return None;
+            }
+            let uo = p00 + (p10 - p00) * u;
+            let ud = (p01 + (p11 - p01) * u) - uo;
+            let delta_o = uo - ray.origin;
+            let perp = ray.dir.cross(ud);
+            let p2 = perp.magnitude2();
+            let v = delta_o.dot(ray.dir.cross(perp));
+            let t = delta_o.dot(ud.cross(perp));
+            if t > p2 * eps && 0.0 <= v && v <= p2 {
+                Some((t / p2, v / p2))
+            } else {
+                None
+            }
+        };
+
+        let hit1 = hit_at(u1).map(|(t, v)| (t, u1, v));
+        let hit2 = if u2 != u1 { hit_at(u2).map(|(t, v)| (t, u2, v)) } else { None };
+        let (t, u, v) = match (hit1, hit2) {
+            (Some(h1), Some(h2)) => if h2.0 < h1.0 { h2 } else { h1 },
+            (Some(h), None) | (None, Some(h)) => h,
+            (None, None) => return None,
+        };
+        if t >= ray.t_max {
+            return None;
+        }
+
+        let uv = Point2f::new(u, v);
+        let p_hit = self.point_at(uv, p00, p10, p01, p11);
+        let (mut dpdu, mut dpdv) = Self::derivatives(uv, p00, p10, p01, p11);
+        let mut d2pduu = vec3f!(0.0, 0.0, 0.0);
+        let mut d2pduv = (p00 - p01) + (p11 - p10);
+        let mut d2pdvv = vec3f!(0.0, 0.0, 0.0);
+
+        // Use the mesh's texture coordinates as the surface parameterization if it has them.
+        let st = if let Some([uv00, uv10, uv01, uv11]) = self.get_uvs() {
+            let lerp2 = |t: Float, a: Point2f, b: Point2f| a + (b - a) * t;
+            let st = lerp2(u, lerp2(v, uv00, uv01), lerp2(v, uv10, uv11));
+
+            let dsdu = -uv00.x + uv10.x + v * (uv00.x - uv01.x - uv10.x + uv11.x);
+            let dsdv = -uv00.x + uv01.x + u * (uv00.x - uv01.x - uv10.x + uv11.x);
+            let dtdu = -uv00.y + uv10.y + v * (uv00.y - uv01.y - uv10.y + uv11.y);
+            let dtdv = -uv00.y + uv01.y + u * (uv00.y - uv01.y - uv10.y + uv11.y);
+
+            let inv_or_zero = |x: Float| if x.abs() < 1e-8 { 0.0 } else { 1.0 / x };
+            let (duds, dudt) = (inv_or_zero(dsdu), inv_or_zero(dtdu));
+            let (dvds, dvdt) = (inv_or_zero(dsdv), inv_or_zero(dtdv));
+
+            let dpds = dpdu * duds + dpdv * dvds;
+            let mut dpdt = dpdu * dudt + dpdv * dvdt;
+            if dpds.cross(dpdt).magnitude2() > 0.0 {
+                // Keep the orientation of the original parameterization
+                if dpdu.cross(dpdv).dot(dpds.cross(dpdt)) < 0.0 {
+                    dpdt = -dpdt;
+                }
+                dpdu = dpds;
+                dpdv = dpdt;
+
+                let d2pdss = d2pduu * (duds * duds) + d2pduv * (2.0 * duds * dvds) + d2pdvv * (dvds * dvds);
+                let d2pdst = d2pduu * (duds * dudt) + d2pduv * (duds * dvdt + dudt * dvds) + d2pdvv * (dvds * dvdt);
+                let d2pdtt = d2pduu * (dudt * dudt) + d2pduv * (2.0 * dudt * dvdt) + d2pdvv * (dvdt * dvdt);
+                d2pduu = d2pdss;
+                d2pduv = d2pdst;
+                d2pdvv = d2pdtt;
+            }
+            st
+        } else {
+            uv
+        };
+
+        let (dndu, dndv) = normal_derivatives(dpdu, dpdv, d2pduu, d2pduv, d2pdvv);
+
+        let p_err = gamma(6) * vec3f!(
+            p00.x.abs().max(p10.x.abs()).max(p01.x.abs()).max(p11.x.abs()),
+            p00.y.abs().max(p10.y.abs()).max(p01.y.abs()).max(p11.y.abs()),
+            p00.z.abs().max(p10.z.abs()).max(p01.z.abs()).max(p11.z.abs())
+        );
+
+        let mut n = dpdu.cross(dpdv).normalize();
+        if self.flip_normals() {
+            n *= -1.0;
+        }
+
+        let mut isect = SurfaceInteraction::new(
+            p_hit,
+            p_err,
+            ray.time,
+            st,
+            -ray.dir,
+            Normal3(n),
+            DiffGeom { dpdu, dpdv, dndu, dndv }
+        );
+
+        if let Some([n00, n10, n01, n11]) = self.get_normals() {
+            let lerp_n = |t: Float, a: Normal3, b: Normal3| a.0 * (1.0 - t) + b.0 * t;
+            let ns = lerp_n(u, Normal3(lerp_n(v, n00, n01)), Normal3(lerp_n(v, n10, n11)));
+            if ns.magnitude2() > 0.0 {
+                let ns = ns.normalize();
+
+                // compute shading tangent and bitangent, as for triangles
+                let ss = isect.geom.dpdu.normalize();
+                let ts = ns.cross(ss);
+                let (ts, ss) = if ts.magnitude2() > 0.0 {
+                    let ts = ts.normalize();
+                    (ts, ts.cross(ns))
+                } else {
+                    coordinate_system(ns)
+                };
+
+                let dndu = Normal3(lerp_n(v, n10, n11) - lerp_n(v, n00, n01));
+                let dndv = Normal3(lerp_n(u, n01, n11) - lerp_n(u, n00, n10));
+                isect.shading_geom = DiffGeom { dpdu: ss, dpdv: ts, dndu, dndv };
+                isect.shading_n = Normal3(ns);
+                isect.hit.n = Normal3(faceforward(isect.hit.n.0, ns));
+            }
+        }
+
+        Some((t, isect))
+    }
+
+    fn sample(&self, u: Point2f) -> SurfaceHit {
+        let [p00, p10, p01, p11] = self.get_vertices();
+        let uv = if self.is_rectangle() {
+            u
+        } else {
+            sample_bilinear(u, self.corner_weights())
+        };
+        let p = self.point_at(uv, p00, p10, p01, p11);
+        let (dpdu, dpdv) = Self::derivatives(uv, p00, p10, p01, p11);
+
+        let mut n = Normal3(dpdu.cross(dpdv).normalize());
+        if let Some([n00, n10, n01, n11]) = self.get_normals() {
+            let lerp_n = |t: Float, a: Normal3, b: Normal3| a.0 * (1.0 - t) + b.0 * t;
+            let ns = lerp_n(uv.x, Normal3(lerp_n(uv.y, n00, n01)), Normal3(lerp_n(uv.y, n10, n11)));
+            n = n.faceforward(ns);
+        } else if self.flip_normals() {
+            n = n * -1.0;
+        }
+
+        let p_err = gamma(6) * vec3f!(
+            p00.x.abs().max(p10.x.abs()).max(p01.x.abs()).max(p11.x.abs()),
+            p00.y.abs().max(p10.y.abs()).max(p01.y.abs()).max(p11.y.abs()),
+            p00.z.abs().max(p10.z.abs()).max(p01.z.abs()).max(p11.z.abs())
+        );
+        SurfaceHit {
+            p,
+            p_err,
+            time: 0.0,
+            n
+        }
+    }
+
+    fn pdf(&self, hit: &SurfaceHit) -> Float {
+        if self.is_rectangle() {
+            return 1.0 / self.area;
+        }
+        let [p00, p10, p01, p11] = self.get_vertices();
+        let uv = match self.invert(hit.p) {
+            Some(uv) => uv,
+            None => return 0.0,
+        };
+        let (dpdu, dpdv) = Self::derivatives(uv, p00, p10, p01, p11);
+        let cross_len = dpdu.cross(dpdv).magnitude();
+        if cross_len == 0.0 {
+            0.0
+        } else {
+            bilinear_pdf(uv, self.corner_weights()) / cross_len
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    fn single_patch(vertices: Vec<Point3f>) -> BilinearPatch {
+        let mesh = Arc::new(BilinearPatchMesh::new(Transform::IDENTITY, vec![0, 1, 2, 3], vertices, None, None, false));
+        mesh.iter_patches().next().unwrap()
+    }
+
+    #[test]
+    fn test_planar_patch() {
+        let patch = single_patch(vec![
+            Point3f::new(0.0, 0.0, 0.0), Point3f::new(2.0, 0.0, 0.0),
+            Point3f::new(0.0, 1.0, 0.0), Point3f::new(2.0, 1.0, 0.0),
+        ]);
+        assert!(patch.is_rectangle());
+        assert_abs_diff_eq!(patch.area(), 2.0, epsilon = 1e-5);
+
+        let ray = Ray::new(Point3f::new(0.5, 0.25, 1.0), vec3f!(0.0, 0.0, -1.0));
+        let (t, isect) = patch.intersect(&ray).expect("ray should hit the patch");
+        assert_abs_diff_eq!(t, 1.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(isect.uv, Point2f::new(0.25, 0.25), epsilon = 1e-5);
+        assert_abs_diff_eq!(isect.hit.n.z.abs(), 1.0, epsilon = 1e-5);
+
+        let miss = Ray::new(Point3f::new(2.5, 0.25, 1.0), vec3f!(0.0, 0.0, -1.0));
+        assert!(patch.intersect(&miss).is_none());
+    }
+
+    #[test]
+    fn test_twisted_patch() {
+        // A hyperbolic paraboloid, z = (2u - 1)(2v - 1) over the unit square.
+        let patch = single_patch(vec![
+            Point3f::new(0.0, 0.0, 1.0), Point3f::new(1.0, 0.0, -1.0),
+            Point3f::new(0.0, 1.0, -1.0), Point3f::new(1.0, 1.0, 1.0),
+        ]);
+        assert!(!patch.is_rectangle());
+
+        let ray = Ray::new(Point3f::new(0.75, 0.5, 3.0), vec3f!(0.0, 0.0, -1.0));
+        let (t, isect) = patch.intersect(&ray).expect("ray should hit the patch");
+        assert_abs_diff_eq!(t, 3.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(isect.uv, Point2f::new(0.75, 0.5), epsilon = 1e-4);
+
+        // Sampled points should lie on the patch, and be mapped back to the same uv for the pdf.
+        for &u in &[Point2f::new(0.2, 0.3), Point2f::new(0.9, 0.6)] {
+            let hit = patch.sample(u);
+            let expected_z = (2.0 * hit.p.x - 1.0) * (2.0 * hit.p.y - 1.0);
+            assert_abs_diff_eq!(hit.p.z, expected_z, epsilon = 1e-4);
+            let uv = patch.invert(hit.p).expect("sampled point should be on the patch");
+            assert_abs_diff_eq!(uv, Point2f::new(hit.p.x, hit.p.y), epsilon = 1e-3);
+            assert!(patch.pdf(&hit) > 0.0);
+        }
+
+        // Points off the patch have no (u, v), rather than one from the wrong root.
+        assert!(patch.invert(Point3f::new(1.5, 0.5, 0.0)).is_none());
+        assert!(patch.invert(Point3f::new(0.5, -0.5, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_patch_transforms() {
+        let tf = Transform::translate(vec3f!(1.0, 2.0, 3.0));
+        let mesh = Arc::new(BilinearPatchMesh::new(tf, vec![0, 1, 2, 3], vec![
+            Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 0.0, 0.0),
+            Point3f::new(0.0, 1.0, 0.0), Point3f::new(1.0, 1.0, 0.0),
+        ], None, None, false));
+        let patch = mesh.iter_patches().next().unwrap();
+
+        assert_eq!(patch.object_bound(), patch.world_bound());
+        let p = Point3f::new(1.5, 2.5, 3.0);
+        assert_abs_diff_eq!(patch.world_to_object().transform(p), Point3f::new(0.5, 0.5, 0.0), epsilon = 1e-5);
+    }
+}
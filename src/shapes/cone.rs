@@ -0,0 +1,168 @@
+use cgmath::InnerSpace;
+
+use crate::{EFloat, Float, Normal3, Point2f, Point3f};
+use crate::err_float::gamma;
+use crate::geometry::{Ray, Transform};
+use crate::geometry::bounds::Bounds3;
+use crate::interaction::{DiffGeom, SurfaceHit};
+use crate::interaction::SurfaceInteraction;
+use crate::math::quadratic;
+use crate::shapes::{Shape, efloat_ray, nearest_quadric_hit, normal_derivatives};
+use std::borrow::Borrow;
+
+/// A cone around the z axis with its base at `z = 0` and its apex at `z = height`.
+#[derive(Debug, PartialEq)]
+pub struct Cone<T: Borrow<Transform>=Transform> {
+    object_to_world: T,
+    world_to_object: T,
+    reverse_orientation: bool,
+
+    height: Float,
+    radius: Float,
+    phi_max: Float,
+}
+
+impl<T: Borrow<Transform>> Cone<T> {
+    pub fn new(
+        object_to_world: T,
+        world_to_object: T,
+        reverse_orientation: bool,
+        height: Float,
+        radius: Float,
+        phi_max: Float
+    ) -> Self {
+        Self {
+            object_to_world, world_to_object, reverse_orientation,
+            height,
+            radius,
+            phi_max: phi_max.clamp(0.0, 360.0).to_radians()
+        }
+    }
+}
+
+impl<T: Borrow<Transform> + Sync + Send> Shape for Cone<T> {
+    fn object_bound(&self) -> Bounds3<f32> {
+        bounds3f!((-self.radius, -self.radius, 0.0), (self.radius, self.radius, self.height))
+    }
+
+    fn object_to_world(&self) -> &Transform {
+        self.object_to_world.borrow()
+    }
+
+    fn world_to_object(&self) -> &Transform {
+        self.world_to_object.borrow()
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+
+    fn area(&self) -> Float {
+        self.radius * (self.height * self.height + self.radius * self.radius).sqrt() * self.phi_max / 2.0
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+        let (ray, (origin_err, dir_err)) = self.world_to_object().tf_exact_to_err(*ray);
+        let ([ox, oy, oz], [dirx, diry, dirz]) = efloat_ray(&ray, origin_err, dir_err);
+
+        let k = EFloat::new(self.radius) / EFloat::new(self.height);
+        let k = k * k;
+        let oz_h = oz - EFloat::new(self.height);
+        let a = dirx * dirx + diry * diry - k * dirz * dirz;
+        let b = 2.0 * (dirx * ox + diry * oy - k * dirz * oz_h);
+        let c = ox * ox + oy * oy - k * oz_h * oz_h;
+
+        let (t0, t1) = quadratic(a, b, c)?;
+
+        let (t_shape_hit, (p_hit, phi)) = nearest_quadric_hit(t0, t1, ray.t_max, |t| {
+            let p_hit = ray.at(t.into());
+            let mut phi = Float::atan2(p_hit.y, p_hit.x);
+            if phi < 0.0 { phi += 2.0 * std::f32::consts::PI }
+            if p_hit.z < 0.0 || p_hit.z > self.height || phi > self.phi_max {
+                None
+            } else {
+                Some((p_hit, phi))
+            }
+        })?;
+
+        let u = phi / self.phi_max;
+        let v = p_hit.z / self.height;
+
+        let dpdu = vec3f!(-self.phi_max * p_hit.y, self.phi_max * p_hit.x, 0.0);
+        let dpdv = vec3f!(-p_hit.x / (1.0 - v), -p_hit.y / (1.0 - v), self.height);
+
+        let d2pduu = -self.phi_max * self.phi_max * vec3f!(p_hit.x, p_hit.y, 0.0);
+        let d2pduv = self.phi_max / (1.0 - v) * vec3f!(p_hit.y, -p_hit.x, 0.0);
+        let d2pdvv = vec3f!(0.0, 0.0, 0.0);
+        let (dndu, dndv) = normal_derivatives(dpdu, dpdv, d2pduu, d2pduv, d2pdvv);
+
+        // Bound the error from evaluating the ray at the hit's parametric distance.
+        let px = ox + t_shape_hit * dirx;
+        let py = oy + t_shape_hit * diry;
+        let pz = oz + t_shape_hit * dirz;
+        let p_err = vec3f!(px.absolute_err(), py.absolute_err(), pz.absolute_err());
+
+        let mut n = dpdu.cross(dpdv).normalize();
+        if self.flip_normals() {
+            n *= -1.0;
+        }
+
+        let interact = SurfaceInteraction::new(
+            p_hit,
+            p_err,
+            ray.time,
+            Point2f::new(u, v),
+            -ray.dir,
+            Normal3(n),
+            DiffGeom { dpdu, dpdv, dndu, dndv }
+        );
+
+        let world_intersect = self.object_to_world().transform(interact);
+        Some((t_shape_hit.into(), world_intersect))
+    }
+
+    fn sample(&self, u: Point2f) -> SurfaceHit {
+        // The circumference grows linearly towards the base, so the distance from the apex is
+        // distributed with a linear density.
+        let v = 1.0 - u[0].sqrt();
+        let phi = u[1] * self.phi_max;
+        let r = self.radius * (1.0 - v);
+        let p_obj = Point3f::new(r * phi.cos(), r * phi.sin(), v * self.height);
+
+        let n_obj = vec3f!(self.height * phi.cos(), self.height * phi.sin(), self.radius);
+        let mut n = Normal3(self.object_to_world().transform(Normal3(n_obj)).normalize());
+        if self.reverse_orientation {
+            n *= -1.0;
+        }
+        let p_obj_err = gamma(5) * vec3f!(p_obj.x.abs(), p_obj.y.abs(), p_obj.z.abs());
+        let (p, p_err) = self.object_to_world().tf_err_to_err(p_obj, p_obj_err);
+        SurfaceHit {
+            p,
+            p_err,
+            time: 0.0,
+            n
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_cone_intersect_normal_matches_sample() {
+        let cone = Cone::new(Transform::IDENTITY, Transform::IDENTITY, false, 2.0, 1.0, 360.0);
+
+        // Halfway up, the cone's radius is 0.5.
+        let ray = Ray::new(Point3f::new(3.0, 0.0, 1.0), vec3f!(-1.0, 0.0, 0.0));
+        let (t, isect) = cone.intersect(&ray).expect("ray should hit the cone");
+        assert_abs_diff_eq!(t, 2.5, epsilon = 1e-4);
+
+        let sampled = cone.sample(Point2f::new(0.25, 0.0));
+        assert_abs_diff_eq!(sampled.p.x, 0.5, epsilon = 1e-4);
+        assert_abs_diff_eq!(sampled.p.z, 1.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(isect.hit.n.dot(sampled.n.0), 1.0, epsilon = 1e-4);
+    }
+}
@@ -0,0 +1,461 @@
+use cgmath::{Deg, EuclideanSpace, InnerSpace};
+
+use crate::{Float, Normal3, Point2f, Point3f, Vec3f};
+use crate::geometry::{Ray, Transform, coordinate_system};
+use crate::geometry::bounds::Bounds3;
+use crate::interaction::{DiffGeom, SurfaceHit};
+use crate::interaction::SurfaceInteraction;
+use crate::math::lerp;
+use crate::shapes::Shape;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurveType {
+    /// A flat strip that always faces the incoming ray.
+    Flat,
+    /// Like `Flat`, but with its shading normal rotated around the curve so it looks like a
+    /// cylinder.
+    Cylinder,
+    /// A flat strip whose orientation is interpolated between the normals at its endpoints.
+    Ribbon,
+}
+
+impl CurveType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flat" => Some(Self::Flat),
+            "cylinder" => Some(Self::Cylinder),
+            "ribbon" => Some(Self::Ribbon),
+            _ => None
+        }
+    }
+}
+
+/// The data shared by all of the segments of a curve after it is split up.
+#[derive(Debug)]
+pub struct CurveCommon {
+    object_to_world: Transform,
+    world_to_object: Transform,
+    reverse_orientation: bool,
+
+    curve_type: CurveType,
+    cp_obj: [Point3f; 4],
+    width: [Float; 2],
+    n: [Vec3f; 2],
+    normal_angle: Float,
+    inv_sin_normal_angle: Float,
+}
+
+impl CurveCommon {
+    /// Creates a cubic Bézier curve. The normals at the endpoints are only used by ribbons.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        object_to_world: Transform,
+        world_to_object: Transform,
+        reverse_orientation: bool,
+        curve_type: CurveType,
+        cp_obj: [Point3f; 4],
+        width0: Float,
+        width1: Float,
+        normals: Option<[Normal3; 2]>,
+    ) -> Self {
+        let (n, normal_angle) = match normals {
+            Some([n0, n1]) => {
+                let n = [n0.0.normalize(), n1.0.normalize()];
+                (n, n[0].dot(n[1]).clamp(0.0, 1.0).acos())
+            },
+            None => ([Vec3f::new(0.0, 0.0, 0.0); 2], 0.0),
+        };
+
+        Self {
+            object_to_world,
+            world_to_object,
+            reverse_orientation,
+            curve_type,
+            cp_obj,
+            width: [width0, width1],
+            n,
+            normal_angle,
+            inv_sin_normal_angle: 1.0 / normal_angle.sin(),
+        }
+    }
+
+    fn width_at(&self, u: Float) -> Float {
+        lerp(u, self.width[0], self.width[1])
+    }
+
+    /// Spherically interpolates between the endpoint normals of a ribbon.
+    fn ribbon_normal(&self, u: Float) -> Vec3f {
+        if self.normal_angle == 0.0 {
+            return self.n[0];
+        }
+        let sin0 = ((1.0 - u) * self.normal_angle).sin() * self.inv_sin_normal_angle;
+        let sin1 = (u * self.normal_angle).sin() * self.inv_sin_normal_angle;
+        sin0 * self.n[0] + sin1 * self.n[1]
+    }
+}
+
+/// A segment `[u_min, u_max]` of a cubic Bézier curve with a varying width, meant for thin
+/// geometry like hair and fur. Intersections are found by recursively subdividing the curve
+/// until each piece is close to a line segment.
+#[derive(Debug)]
+pub struct Curve {
+    common: Arc<CurveCommon>,
+    u_min: Float,
+    u_max: Float,
+}
+
+fn lerp_point(t: Float, p0: Point3f, p1: Point3f) -> Point3f {
+    p0 + t * (p1 - p0)
+}
+
+/// Evaluates the blossom of a cubic Bézier curve, which is the point on the curve at `u` when
+/// all three parameters are `u`.
+fn blossom_bezier(p: &[Point3f; 4], u0: Float, u1: Float, u2: Float) -> Point3f {
+    let a = [
+        lerp_point(u0, p[0], p[1]),
+        lerp_point(u0, p[1], p[2]),
+        lerp_point(u0, p[2], p[3]),
+    ];
+    let b = [lerp_point(u1, a[0], a[1]), lerp_point(u1, a[1], a[2])];
+    lerp_point(u2, b[0], b[1])
+}
+
+/// Splits a cubic Bézier curve in half, returning the control points of both halves with the
+/// middle point shared.
+fn subdivide_bezier(cp: &[Point3f; 4]) -> [Point3f; 7] {
+    [
+        cp[0],
+        Point3f::midpoint(cp[0], cp[1]),
+        Point3f::from_vec((cp[0].to_vec() + 2.0 * cp[1].to_vec() + cp[2].to_vec()) / 4.0),
+        Point3f::from_vec((cp[0].to_vec() + 3.0 * cp[1].to_vec() + 3.0 * cp[2].to_vec() + cp[3].to_vec()) / 8.0),
+        Point3f::from_vec((cp[1].to_vec() + 2.0 * cp[2].to_vec() + cp[3].to_vec()) / 4.0),
+        Point3f::midpoint(cp[2], cp[3]),
+        cp[3],
+    ]
+}
+
+/// Returns the point on a cubic Bézier curve at `u`, and the derivative there.
+fn eval_bezier(cp: &[Point3f; 4], u: Float) -> (Point3f, Vec3f) {
+    let cp1 = [
+        lerp_point(u, cp[0], cp[1]),
+        lerp_point(u, cp[1], cp[2]),
+        lerp_point(u, cp[2], cp[3]),
+    ];
+    let cp2 = [lerp_point(u, cp1[0], cp1[1]), lerp_point(u, cp1[1], cp1[2])];
+    let deriv = if (cp2[1] - cp2[0]).magnitude2() > 0.0 {
+        3.0 * (cp2[1] - cp2[0])
+    } else {
+        // For a degenerate curve, the first and last control points may coincide with their
+        // neighbors.
+        cp[3] - cp[0]
+    };
+    (lerp_point(u, cp2[0], cp2[1]), deriv)
+}
+
+impl Curve {
+    pub fn new(common: Arc<CurveCommon>, u_min: Float, u_max: Float) -> Self {
+        Self { common, u_min, u_max }
+    }
+
+    /// Splits a curve into `2^split_depth` segments with their own bounds, so that they can be
+    /// culled separately by an acceleration structure.
+    pub fn split(common: Arc<CurveCommon>, split_depth: u32) -> Vec<Self> {
+        let n_segments = 1 << split_depth;
+        (0..n_segments)
+            .map(|i| {
+                let u_min = i as Float / n_segments as Float;
+                let u_max = (i + 1) as Float / n_segments as Float;
+                Self::new(common.clone(), u_min, u_max)
+            })
+            .collect()
+    }
+
+    fn segment_control_points(&self) -> [Point3f; 4] {
+        let cp = &self.common.cp_obj;
+        let (u0, u1) = (self.u_min, self.u_max);
+        [
+            blossom_bezier(cp, u0, u0, u0),
+            blossom_bezier(cp, u0, u0, u1),
+            blossom_bezier(cp, u0, u1, u1),
+            blossom_bezier(cp, u1, u1, u1),
+        ]
+    }
+
+    /// Intersects the ray with a piece of the curve whose control points `cp` are in a
+    /// coordinate system with the ray starting at the origin and pointing down the z axis.
+    #[allow(clippy::too_many_arguments)]
+    fn recursive_intersect(
+        &self,
+        ray: &Ray,
+        t_max: Float,
+        cp: &[Point3f; 4],
+        ray_to_object: &Transform,
+        u0: Float,
+        u1: Float,
+        depth: u32,
+    ) -> Option<(Float, SurfaceInteraction)> {
+        let ray_length = ray.dir.magnitude();
+        let z_max = ray_length * t_max;
+
+        if depth > 0 {
+            let cp_split = subdivide_bezier(cp);
+            let u = [u0, (u0 + u1) / 2.0, u1];
+            let mut closest = None;
+            let mut t_max = t_max;
+            for seg in 0..2 {
+                let cps = [cp_split[3 * seg], cp_split[3 * seg + 1], cp_split[3 * seg + 2], cp_split[3 * seg + 3]];
+                let half_width = 0.5 * Float::max(self.common.width_at(u[seg]), self.common.width_at(u[seg + 1]));
+                let z_max = ray_length * t_max;
+
+                // Skip the segment if its bounds, expanded by the width, don't overlap the ray.
+                let (min, max) = cps.iter().fold(
+                    (cps[0], cps[0]),
+                    |(min, max), p| (
+                        Point3f::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                        Point3f::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                    )
+                );
+                if max.x + half_width < 0.0 || min.x - half_width > 0.0
+                    || max.y + half_width < 0.0 || min.y - half_width > 0.0
+                    || max.z + half_width < 0.0 || min.z - half_width > z_max {
+                    continue;
+                }
+
+                if let Some((t, isect)) = self.recursive_intersect(ray, t_max, &cps, ray_to_object, u[seg], u[seg + 1], depth - 1) {
+                    t_max = t;
+                    closest = Some((t, isect));
+                }
+            }
+            return closest;
+        }
+
+        // Test the ray against the planes at the ends of the segment, perpendicular to the
+        // tangents there, so that neighboring segments don't both report a hit.
+        let edge = (cp[1].y - cp[0].y) * -cp[0].y + cp[0].x * (cp[0].x - cp[1].x);
+        if edge < 0.0 {
+            return None;
+        }
+        let edge = (cp[2].y - cp[3].y) * -cp[3].y + cp[3].x * (cp[3].x - cp[2].x);
+        if edge < 0.0 {
+            return None;
+        }
+
+        // Find the closest point on the segment's line to the ray, which is at the origin
+        let segment_dir = Point2f::new(cp[3].x - cp[0].x, cp[3].y - cp[0].y);
+        let denom = segment_dir.x * segment_dir.x + segment_dir.y * segment_dir.y;
+        if denom == 0.0 {
+            return None;
+        }
+        let w = -(cp[0].x * segment_dir.x + cp[0].y * segment_dir.y) / denom;
+
+        let u = lerp(w, u0, u1).clamp(u0, u1);
+        let mut hit_width = self.common.width_at(u);
+        let n_hit = if self.common.curve_type == CurveType::Ribbon {
+            let n_hit = self.common.ribbon_normal(u);
+            // Ribbons seen edge-on get narrower
+            hit_width *= n_hit.dot(ray.dir).abs() / ray_length;
+            n_hit
+        } else {
+            Vec3f::new(0.0, 0.0, 0.0)
+        };
+
+        let (pc, dpcdw) = eval_bezier(cp, w.clamp(0.0, 1.0));
+        let pt_curve_dist_sq = pc.x * pc.x + pc.y * pc.y;
+        if pt_curve_dist_sq > hit_width * hit_width * 0.25 {
+            return None;
+        }
+        if pc.z < 0.0 || pc.z > z_max {
+            return None;
+        }
+
+        // v goes across the width of the curve, depending on which side of it the ray passes.
+        let pt_curve_dist = pt_curve_dist_sq.sqrt();
+        let edge_func = dpcdw.x * -pc.y + pc.x * dpcdw.y;
+        let v = if edge_func > 0.0 {
+            0.5 + pt_curve_dist / hit_width
+        } else {
+            0.5 - pt_curve_dist / hit_width
+        };
+
+        let t_hit = pc.z / ray_length;
+        let p_err = Vec3f::new(2.0 * hit_width, 2.0 * hit_width, 2.0 * hit_width);
+
+        let (_, dpdu) = eval_bezier(&self.common.cp_obj, u);
+        let dpdv = if self.common.curve_type == CurveType::Ribbon {
+            n_hit.cross(dpdu).normalize() * hit_width
+        } else {
+            // The curve faces the ray, so dpdv is perpendicular to the ray and the curve.
+            let dpdu_plane = ray_to_object.inverse().transform(dpdu);
+            let mut dpdv_plane = Vec3f::new(-dpdu_plane.y, dpdu_plane.x, 0.0).normalize() * hit_width;
+            if self.common.curve_type == CurveType::Cylinder {
+                // Rotate around the curve so that the normal follows a cylinder's.
+                let theta = lerp(v, -90.0, 90.0);
+                dpdv_plane = Transform::rotate(Deg(-theta), dpdu_plane).transform(dpdv_plane);
+            }
+            ray_to_object.transform(dpdv_plane)
+        };
+
+        let mut n = dpdu.cross(dpdv).normalize();
+        if self.flip_normals() {
+            n *= -1.0;
+        }
+
+        let zero = Normal3(Vec3f::new(0.0, 0.0, 0.0));
+        let interact = SurfaceInteraction::new(
+            ray.at(t_hit),
+            p_err,
+            ray.time,
+            Point2f::new(u, v),
+            -ray.dir,
+            Normal3(n),
+            DiffGeom { dpdu, dpdv, dndu: zero, dndv: zero }
+        );
+        Some((t_hit, interact))
+    }
+}
+
+impl Shape for Curve {
+    fn object_bound(&self) -> Bounds3<f32> {
+        let cp = self.segment_control_points();
+        let bounds = cp.iter().fold(Bounds3::new(cp[0], cp[1]), |b, &p| b.join_point(p));
+        let half_width = 0.5 * Float::max(self.common.width_at(self.u_min), self.common.width_at(self.u_max));
+        let expand = Vec3f::new(half_width, half_width, half_width);
+        Bounds3::with_bounds(bounds.min - expand, bounds.max + expand)
+    }
+
+    fn object_to_world(&self) -> &Transform {
+        &self.common.object_to_world
+    }
+
+    fn world_to_object(&self) -> &Transform {
+        &self.common.world_to_object
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.common.reverse_orientation
+    }
+
+    /// Approximates the area using the length of the control polygon.
+    fn area(&self) -> Float {
+        let cp = self.segment_control_points();
+        let avg_width = 0.5 * (self.common.width_at(self.u_min) + self.common.width_at(self.u_max));
+        let approx_length: Float = cp.windows(2).map(|p| (p[1] - p[0]).magnitude()).sum();
+        approx_length * avg_width
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+        let (ray, _) = self.world_to_object().tf_exact_to_err(*ray);
+
+        // Transform the control points to a coordinate system looking down the ray, oriented so
+        // that the curve's endpoints are along the y axis when possible.
+        let cp_obj = self.segment_control_points();
+        let mut dx = ray.dir.cross(cp_obj[3] - cp_obj[0]);
+        if dx.magnitude2() == 0.0 {
+            dx = coordinate_system(ray.dir.normalize()).0;
+        }
+        let object_to_ray = Transform::look_at(ray.origin, ray.origin + ray.dir, dx);
+        let cp = [
+            object_to_ray.transform(cp_obj[0]),
+            object_to_ray.transform(cp_obj[1]),
+            object_to_ray.transform(cp_obj[2]),
+            object_to_ray.transform(cp_obj[3]),
+        ];
+
+        // Choose the number of subdivisions so that the pieces are close enough to lines,
+        // relative to the width of the curve.
+        let l0 = (0..2)
+            .map(|i| {
+                let d = cp[i].to_vec() - 2.0 * cp[i + 1].to_vec() + cp[i + 2].to_vec();
+                d.x.abs().max(d.y.abs()).max(d.z.abs())
+            })
+            .fold(0.0, Float::max);
+        let eps = Float::max(self.common.width[0], self.common.width[1]) * 0.05;
+        let max_depth = if l0 > 0.0 {
+            let r0 = (std::f32::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)).log2() / 2.0;
+            r0.clamp(0.0, 10.0) as u32
+        } else {
+            0
+        };
+
+        let ray_to_object = object_to_ray.inverse();
+        let (t, isect) = self.recursive_intersect(&ray, ray.t_max, &cp, &ray_to_object, self.u_min, self.u_max, max_depth)?;
+        Some((t, self.object_to_world().transform(isect)))
+    }
+
+    /// Samples a point uniformly in the curve's parameter space, which is only approximately
+    /// uniform by area. Flat and cylinder curves are oriented towards the ray when intersected,
+    /// so an arbitrary orientation is used for their width.
+    fn sample(&self, u: Point2f) -> SurfaceHit {
+        let t = lerp(u[0], self.u_min, self.u_max);
+        let (p, dpdu) = eval_bezier(&self.common.cp_obj, t);
+        let width = self.common.width_at(t);
+        let across = if self.common.curve_type == CurveType::Ribbon {
+            self.common.ribbon_normal(t).cross(dpdu).normalize()
+        } else {
+            coordinate_system(dpdu.normalize()).0
+        };
+        let p_obj = p + (u[1] - 0.5) * width * across;
+
+        let mut n = Normal3(self.object_to_world().transform(Normal3(dpdu.cross(across))).normalize());
+        if self.reverse_orientation() {
+            n *= -1.0;
+        }
+        let p_obj_err = Vec3f::new(width, width, width);
+        let (p, p_err) = self.object_to_world().tf_err_to_err(p_obj, p_obj_err);
+        SurfaceHit {
+            p,
+            p_err,
+            time: 0.0,
+            n
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    fn straight_curve(curve_type: CurveType, normals: Option<[Normal3; 2]>) -> Arc<CurveCommon> {
+        let cp = [
+            Point3f::new(-1.0, 0.0, 0.0),
+            Point3f::new(-1.0 / 3.0, 0.0, 0.0),
+            Point3f::new(1.0 / 3.0, 0.0, 0.0),
+            Point3f::new(1.0, 0.0, 0.0),
+        ];
+        Arc::new(CurveCommon::new(Transform::IDENTITY, Transform::IDENTITY, false, curve_type, cp, 0.2, 0.2, normals))
+    }
+
+    #[test]
+    fn test_flat_curve_intersect() {
+        let segments = Curve::split(straight_curve(CurveType::Flat, None), 2);
+        assert_eq!(segments.len(), 4);
+
+        let ray = Ray::new(Point3f::new(0.1, 0.05, 5.0), vec3f!(0.0, 0.0, -1.0));
+        let hits: Vec<_> = segments.iter().filter_map(|c| c.intersect(&ray)).collect();
+        assert_eq!(hits.len(), 1);
+        let (t, isect) = &hits[0];
+        assert_abs_diff_eq!(*t, 5.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(isect.uv.x, 0.55, epsilon = 1e-4);
+        assert_abs_diff_eq!(isect.hit.n.z.abs(), 1.0, epsilon = 1e-4);
+
+        // Outside of the curve's width
+        let ray = Ray::new(Point3f::new(0.1, 0.15, 5.0), vec3f!(0.0, 0.0, -1.0));
+        assert!(segments.iter().all(|c| c.intersect(&ray).is_none()));
+    }
+
+    #[test]
+    fn test_ribbon_seen_edge_on() {
+        let n = Normal3::new(0.0, 0.0, 1.0);
+        let curve = Curve::new(straight_curve(CurveType::Ribbon, Some([n, n])), 0.0, 1.0);
+
+        let ray = Ray::new(Point3f::new(0.3, 0.05, 5.0), vec3f!(0.0, 0.0, -1.0));
+        assert!(curve.intersect(&ray).is_some());
+
+        // The ribbon lies in the xy plane, so it has no width when seen from the side.
+        let ray = Ray::new(Point3f::new(0.3, -5.0, 0.01), vec3f!(0.0, 1.0, 0.0));
+        assert!(curve.intersect(&ray).is_none());
+    }
+}
@@ -0,0 +1,181 @@
+use cgmath::InnerSpace;
+
+use crate::{ComponentWiseExt, EFloat, Float, Normal3, Point2f, Point3f};
+use crate::err_float::gamma;
+use crate::geometry::{Ray, Transform};
+use crate::geometry::bounds::Bounds3;
+use crate::interaction::{DiffGeom, SurfaceHit};
+use crate::interaction::SurfaceInteraction;
+use crate::math::{lerp, quadratic};
+use crate::shapes::{Shape, efloat_ray, nearest_quadric_hit, normal_derivatives};
+use std::borrow::Borrow;
+
+/// An open cylinder around the z axis, between `z_min` and `z_max`.
+#[derive(Debug, PartialEq)]
+pub struct Cylinder<T: Borrow<Transform>=Transform> {
+    object_to_world: T,
+    world_to_object: T,
+    reverse_orientation: bool,
+
+    radius: Float,
+    z_min: Float,
+    z_max: Float,
+    phi_max: Float,
+}
+
+impl<T: Borrow<Transform>> Cylinder<T> {
+    pub fn new(
+        object_to_world: T,
+        world_to_object: T,
+        reverse_orientation: bool,
+        radius: Float,
+        z_min: Float,
+        z_max: Float,
+        phi_max: Float
+    ) -> Self {
+        Self {
+            object_to_world, world_to_object, reverse_orientation,
+            radius,
+            z_min: Float::min(z_min, z_max),
+            z_max: Float::max(z_min, z_max),
+            phi_max: phi_max.clamp(0.0, 360.0).to_radians()
+        }
+    }
+
+    /// Projects a point onto the cylinder, returning the point and its angle around the z axis.
+    fn reproject(&self, mut p_hit: Point3f) -> (Point3f, Float) {
+        let hit_rad = (p_hit.x * p_hit.x + p_hit.y * p_hit.y).sqrt();
+        p_hit.x *= self.radius / hit_rad;
+        p_hit.y *= self.radius / hit_rad;
+        let mut phi = Float::atan2(p_hit.y, p_hit.x);
+        if phi < 0.0 { phi += 2.0 * std::f32::consts::PI }
+        (p_hit, phi)
+    }
+}
+
+impl<T: Borrow<Transform> + Sync + Send> Shape for Cylinder<T> {
+    fn object_bound(&self) -> Bounds3<f32> {
+        bounds3f!((-self.radius, -self.radius, self.z_min), (self.radius, self.radius, self.z_max))
+    }
+
+    fn object_to_world(&self) -> &Transform {
+        self.object_to_world.borrow()
+    }
+
+    fn world_to_object(&self) -> &Transform {
+        self.world_to_object.borrow()
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+
+    fn area(&self) -> Float {
+        (self.z_max - self.z_min) * self.radius * self.phi_max
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+        let (ray, (origin_err, dir_err)) = self.world_to_object().tf_exact_to_err(*ray);
+        let ([ox, oy, _], [dirx, diry, _]) = efloat_ray(&ray, origin_err, dir_err);
+
+        let a = dirx * dirx + diry * diry;
+        let b = 2.0 * (dirx * ox + diry * oy);
+        let c = ox * ox + oy * oy - EFloat::new(self.radius) * EFloat::new(self.radius);
+
+        let (t0, t1) = quadratic(a, b, c)?;
+
+        let (t_shape_hit, (p_hit, phi)) = nearest_quadric_hit(t0, t1, ray.t_max, |t| {
+            let (p_hit, phi) = self.reproject(ray.at(t.into()));
+            if p_hit.z < self.z_min || p_hit.z > self.z_max || phi > self.phi_max {
+                None
+            } else {
+                Some((p_hit, phi))
+            }
+        })?;
+
+        let u = phi / self.phi_max;
+        let v = (p_hit.z - self.z_min) / (self.z_max - self.z_min);
+
+        let dpdu = vec3f!(-self.phi_max * p_hit.y, self.phi_max * p_hit.x, 0.0);
+        let dpdv = vec3f!(0.0, 0.0, self.z_max - self.z_min);
+
+        let d2pduu = -self.phi_max * self.phi_max * vec3f!(p_hit.x, p_hit.y, 0.0);
+        let d2pduv = vec3f!(0.0, 0.0, 0.0);
+        let d2pdvv = vec3f!(0.0, 0.0, 0.0);
+        let (dndu, dndv) = normal_derivatives(dpdu, dpdv, d2pduu, d2pduv, d2pdvv);
+
+        let p_err = gamma(3) * vec3f!(p_hit.x, p_hit.y, 0.0).abs();
+
+        let mut n = dpdu.cross(dpdv).normalize();
+        if self.flip_normals() {
+            n *= -1.0;
+        }
+
+        let interact = SurfaceInteraction::new(
+            p_hit,
+            p_err,
+            ray.time,
+            Point2f::new(u, v),
+            -ray.dir,
+            Normal3(n),
+            DiffGeom { dpdu, dpdv, dndu, dndv }
+        );
+
+        let world_intersect = self.object_to_world().transform(interact);
+        Some((t_shape_hit.into(), world_intersect))
+    }
+
+    fn sample(&self, u: Point2f) -> SurfaceHit {
+        let z = lerp(u[0], self.z_min, self.z_max);
+        let phi = u[1] * self.phi_max;
+        let p_obj = Point3f::new(self.radius * phi.cos(), self.radius * phi.sin(), z);
+
+        let mut n = Normal3(self.object_to_world().transform(Normal3(vec3f!(p_obj.x, p_obj.y, 0.0))).normalize());
+        if self.reverse_orientation {
+            n *= -1.0;
+        }
+        let p_obj_err = gamma(3) * vec3f!(p_obj.x, p_obj.y, 0.0).abs();
+        let (p, p_err) = self.object_to_world().tf_err_to_err(p_obj, p_obj_err);
+        SurfaceHit {
+            p,
+            p_err,
+            time: 0.0,
+            n
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_cylinder_intersect_from_inside_and_outside() {
+        let cylinder = Cylinder::new(Transform::IDENTITY, Transform::IDENTITY, false, 1.0, -1.0, 1.0, 360.0);
+
+        let ray = Ray::new(Point3f::new(-3.0, 0.0, 0.5), vec3f!(1.0, 0.0, 0.0));
+        let (t, isect) = cylinder.intersect(&ray).expect("ray should hit the cylinder");
+        assert_abs_diff_eq!(t, 2.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(isect.hit.n.x, -1.0, epsilon = 1e-5);
+
+        let ray = Ray::new(Point3f::new(0.0, 0.0, 0.5), vec3f!(1.0, 0.0, 0.0));
+        let (t, _) = cylinder.intersect(&ray).expect("ray should hit the cylinder from inside");
+        assert_abs_diff_eq!(t, 1.0, epsilon = 1e-5);
+
+        let ray = Ray::new(Point3f::new(-3.0, 0.0, 1.5), vec3f!(1.0, 0.0, 0.0));
+        assert!(cylinder.intersect(&ray).is_none());
+    }
+
+    #[test]
+    fn test_cylinder_samples_lie_on_surface() {
+        let cylinder = Cylinder::new(Transform::IDENTITY, Transform::IDENTITY, false, 2.0, 0.0, 3.0, 90.0);
+        for &(u, v) in &[(0.0, 0.0), (0.3, 0.7), (0.99, 0.5)] {
+            let hit = cylinder.sample(Point2f::new(u, v));
+            assert_abs_diff_eq!((hit.p.x * hit.p.x + hit.p.y * hit.p.y).sqrt(), 2.0, epsilon = 1e-4);
+            assert!(hit.p.x >= -1e-4 && hit.p.y >= -1e-4);
+            assert!(hit.p.z >= 0.0 && hit.p.z <= 3.0);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use cgmath::InnerSpace;
+
+use crate::{Float, Normal3, Point2f, Vec3f, Point3f};
+use crate::geometry::{Ray, Transform};
+use crate::geometry::bounds::Bounds3;
+use crate::interaction::{DiffGeom, SurfaceHit};
+use crate::interaction::SurfaceInteraction;
+use crate::shapes::Shape;
+use std::borrow::Borrow;
+
+/// A disk or annulus in the plane `z = height`, centered on the z axis.
+#[derive(Debug, PartialEq)]
+pub struct Disk<T: Borrow<Transform>=Transform> {
+    object_to_world: T,
+    world_to_object: T,
+    reverse_orientation: bool,
+
+    height: Float,
+    radius: Float,
+    inner_radius: Float,
+    phi_max: Float,
+}
+
+impl<T: Borrow<Transform>> Disk<T> {
+    pub fn new(
+        object_to_world: T,
+        world_to_object: T,
+        reverse_orientation: bool,
+        height: Float,
+        radius: Float,
+        inner_radius: Float,
+        phi_max: Float
+    ) -> Self {
+        Self {
+            object_to_world, world_to_object, reverse_orientation,
+            height,
+            radius,
+            inner_radius,
+            phi_max: phi_max.clamp(0.0, 360.0).to_radians()
+        }
+    }
+}
+
+impl<T: Borrow<Transform> + Sync + Send> Shape for Disk<T> {
+    fn object_bound(&self) -> Bounds3<f32> {
+        bounds3f!((-self.radius, -self.radius, self.height), (self.radius, self.radius, self.height))
+    }
+
+    fn object_to_world(&self) -> &Transform {
+        self.object_to_world.borrow()
+    }
+
+    fn world_to_object(&self) -> &Transform {
+        self.world_to_object.borrow()
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+
+    fn area(&self) -> Float {
+        self.phi_max * 0.5 * (self.radius * self.radius - self.inner_radius * self.inner_radius)
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+        let (ray, _) = self.world_to_object().tf_exact_to_err(*ray);
+
+        // Rays parallel to the disk's plane can't hit it.
+        if ray.dir.z == 0.0 {
+            return None;
+        }
+        let t_shape_hit = (self.height - ray.origin.z) / ray.dir.z;
+        if t_shape_hit <= 0.0 || t_shape_hit >= ray.t_max {
+            return None;
+        }
+
+        let mut p_hit = ray.at(t_shape_hit);
+        let dist_sq = p_hit.x * p_hit.x + p_hit.y * p_hit.y;
+        if dist_sq > self.radius * self.radius || dist_sq < self.inner_radius * self.inner_radius {
+            return None;
+        }
+
+        let mut phi = Float::atan2(p_hit.y, p_hit.x);
+        if phi < 0.0 { phi += 2.0 * std::f32::consts::PI }
+        if phi > self.phi_max {
+            return None;
+        }
+
+        let u = phi / self.phi_max;
+        let r_hit = dist_sq.sqrt();
+        let v = (self.radius - r_hit) / (self.radius - self.inner_radius);
+        let dpdu = vec3f!(-self.phi_max * p_hit.y, self.phi_max * p_hit.x, 0.0);
+        let dpdv = vec3f!(p_hit.x, p_hit.y, 0.0) * (self.inner_radius - self.radius) / r_hit;
+        let dndu = Normal3(vec3f!(0.0, 0.0, 0.0));
+        let dndv = Normal3(vec3f!(0.0, 0.0, 0.0));
+
+        // The hit point is exactly on the plane, so there is no error.
+        p_hit.z = self.height;
+        let p_err = vec3f!(0.0, 0.0, 0.0);
+
+        let mut n = dpdu.cross(dpdv).normalize();
+        if self.flip_normals() {
+            n *= -1.0;
+        }
+
+        let interact = SurfaceInteraction::new(
+            p_hit,
+            p_err,
+            ray.time,
+            Point2f::new(u, v),
+            -ray.dir,
+            Normal3(n),
+            DiffGeom { dpdu, dpdv, dndu, dndv }
+        );
+
+        let world_intersect = self.object_to_world().transform(interact);
+        Some((t_shape_hit, world_intersect))
+    }
+
+    fn sample(&self, u: Point2f) -> SurfaceHit {
+        // Choose the radius so that points are uniformly distributed by area over the annulus.
+        let r_sq_min = self.inner_radius * self.inner_radius;
+        let r = (r_sq_min + u[0] * (self.radius * self.radius - r_sq_min)).sqrt();
+        let phi = u[1] * self.phi_max;
+        let p_obj = Point3f::new(r * phi.cos(), r * phi.sin(), self.height);
+
+        let mut n = Normal3(self.object_to_world().transform(Normal3(vec3f!(0.0, 0.0, 1.0))).normalize());
+        if self.reverse_orientation {
+            n *= -1.0;
+        }
+        let (p, p_err) = self.object_to_world().tf_err_to_err(p_obj, Vec3f::new(0.0, 0.0, 0.0));
+        SurfaceHit {
+            p,
+            p_err,
+            time: 0.0,
+            n
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_annulus_intersect() {
+        let disk = Disk::new(Transform::IDENTITY, Transform::IDENTITY, false, 1.0, 2.0, 1.0, 180.0);
+
+        let ray = Ray::new(Point3f::new(0.0, 1.5, 5.0), vec3f!(0.0, 0.0, -1.0));
+        let (t, isect) = disk.intersect(&ray).expect("ray should hit the annulus");
+        assert_abs_diff_eq!(t, 4.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(isect.hit.p, Point3f::new(0.0, 1.5, 1.0), epsilon = 1e-5);
+        assert_abs_diff_eq!(isect.hit.n.0, vec3f!(0.0, 0.0, 1.0), epsilon = 1e-5);
+
+        // Inside the hole, past the outer radius and outside of the swept half
+        for &origin in &[(0.0, 0.5, 5.0), (0.0, 2.5, 5.0), (0.0, -1.5, 5.0)] {
+            let ray = Ray::new(origin.into(), vec3f!(0.0, 0.0, -1.0));
+            assert!(disk.intersect(&ray).is_none());
+        }
+    }
+}
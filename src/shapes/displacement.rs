@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace};
+
+use crate::{Transform, Point2f, Point3f, Normal3, Float, SurfaceInteraction, coordinate_system};
+use crate::interaction::DiffGeom;
+use crate::shapes::triangle::{TriangleMesh, vertex_normals};
+use crate::texture::{Texture, TextureRef};
+
+/// How finely to tessellate a mesh before displacing its vertices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tessellation {
+    /// Split every triangle into four this many times.
+    Levels(u32),
+
+    /// Split edges until none is longer than this in world space.
+    EdgeLength(Float),
+}
+
+/// Tessellation stops before a split that would leave the mesh with more triangles than this.
+const MAX_TRIANGLES: usize = 1 << 22;
+
+/// Tessellates a triangle mesh and moves each vertex along its normal by the value of the
+/// displacement texture there. Both the texture lookup and the displacement happen in world
+/// space at the undisplaced vertex, and the normals of the result are recomputed from the
+/// displaced triangles.
+///
+/// The displaced vertices are stored in the mesh, so the triangles' bounds already cover the
+/// displaced surface.
+pub fn displace_mesh(
+    obj_to_world: &Transform,
+    reverse_orientation: bool,
+    vertices: &[Point3f],
+    vertex_indices: &[u32],
+    normals: Option<&[Normal3]>,
+    tex_coords: Option<&[Point2f]>,
+    displacement: &TextureRef<Float>,
+    tessellation: Tessellation,
+) -> TriangleMesh {
+    let n = normals.map_or_else(|| vertex_normals(vertices, vertex_indices), |n| n.to_vec());
+    let mut mesh = DisplacedVertices {
+        p: vertices.iter().map(|&p| obj_to_world.transform(p)).collect(),
+        n: n.into_iter().map(|n| obj_to_world.transform(n)).collect(),
+        uv: tex_coords.map(|uv| uv.to_vec()),
+    };
+    let indices = mesh.tessellate(vertex_indices.to_vec(), tessellation);
+
+    for i in 0..mesh.p.len() {
+        let n = mesh.n[i].0.normalize();
+        let d = evaluate_at_vertex(displacement, mesh.p[i], Normal3(n), mesh.uv_at(i));
+        mesh.p[i] += d * n;
+    }
+
+    // Keep the new normals on the same side as the ones that were interpolated before
+    // displacement, which may not agree with the winding of the triangles.
+    let n: Vec<Normal3> = vertex_normals(&mesh.p, &indices).into_iter()
+        .zip(mesh.n.iter())
+        .map(|(new, old)| if new.0.dot(old.0) < 0.0 { Normal3(-new.0) } else { new })
+        .collect();
+
+    // The mesh takes its vertices in object space.
+    let world_to_obj = obj_to_world.inverse();
+    TriangleMesh::new(
+        obj_to_world.clone(),
+        indices,
+        mesh.p.into_iter().map(|p| world_to_obj.transform(p)).collect(),
+        Some(n.into_iter().map(|n| world_to_obj.transform(n)).collect()),
+        None,
+        mesh.uv,
+        reverse_orientation,
+    )
+}
+
+fn evaluate_at_vertex(displacement: &TextureRef<Float>, p: Point3f, n: Normal3, uv: Point2f) -> Float {
+    let (dpdu, dpdv) = coordinate_system(n.0);
+    let si = SurfaceInteraction::new(
+        p,
+        vec3f!(0.0, 0.0, 0.0),
+        0.0,
+        uv,
+        n.0,
+        n,
+        DiffGeom {
+            dpdu,
+            dpdv,
+            dndu: Normal3::zero(),
+            dndv: Normal3::zero(),
+        }
+    );
+    displacement.evaluate(&si)
+}
+
+/// The per-vertex data of a mesh while it is being tessellated, in world space.
+struct DisplacedVertices {
+    p: Vec<Point3f>,
+    n: Vec<Normal3>,
+    uv: Option<Vec<Point2f>>,
+}
+
+impl DisplacedVertices {
+    fn uv_at(&self, i: usize) -> Point2f {
+        self.uv.as_ref().map_or(Point2f::new(0.0, 0.0), |uv| uv[i])
+    }
+
+    fn tessellate(&mut self, mut indices: Vec<u32>, tessellation: Tessellation) -> Vec<u32> {
+        let passes = match tessellation {
+            Tessellation::Levels(levels) => levels,
+            Tessellation::EdgeLength(_) => u32::MAX,
+        };
+        let split_edge = |p: &[Point3f], a: u32, b: u32| match tessellation {
+            Tessellation::Levels(_) => true,
+            Tessellation::EdgeLength(max_len) => (p[a as usize] - p[b as usize]).magnitude() > max_len,
+        };
+        for _ in 0..passes {
+            match self.split(&indices, split_edge) {
+                Some(new_indices) => indices = new_indices,
+                None => break,
+            }
+        }
+        indices
+    }
+
+    /// Splits every edge for which `split_edge` is true at its midpoint, and divides each
+    /// triangle into two, three or four to match, returning the new vertex indices. Whether
+    /// to split only depends on the edge, and the midpoints are shared between the triangles
+    /// on either side, so the mesh stays watertight.
+    ///
+    /// Returns `None` if there was nothing to split, or if splitting would go over the
+    /// triangle budget.
+    fn split(&mut self, indices: &[u32], split_edge: impl Fn(&[Point3f], u32, u32) -> bool) -> Option<Vec<u32>> {
+        let edges_split: Vec<[bool; 3]> = indices.chunks_exact(3)
+            .map(|tri| [
+                split_edge(&self.p, tri[0], tri[1]),
+                split_edge(&self.p, tri[1], tri[2]),
+                split_edge(&self.p, tri[2], tri[0]),
+            ])
+            .collect();
+        let n_splits: usize = edges_split.iter().flatten().filter(|&&s| s).count();
+        if n_splits == 0 {
+            return None;
+        }
+        // Each edge split adds a triangle.
+        let n_triangles = indices.len() / 3 + n_splits;
+        if n_triangles > MAX_TRIANGLES {
+            tracing::warn!("Stopping tessellation of displaced mesh at {} triangles", indices.len() / 3);
+            return None;
+        }
+
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut new_indices = Vec::with_capacity(3 * n_triangles);
+        for (tri, split) in indices.chunks_exact(3).zip(edges_split) {
+            let v = [tri[0], tri[1], tri[2]];
+            let m = [0, 1, 2].map(|i| {
+                if split[i] { Some(self.midpoint(&mut midpoints, v[i], v[(i + 1) % 3])) } else { None }
+            });
+
+            // With one or two edges split, start from the vertex that makes the pattern line
+            // up, so the new triangles keep the winding of the old one.
+            match m {
+                [None, None, None] => new_indices.extend_from_slice(&v),
+                [Some(m01), Some(m12), Some(m20)] => new_indices.extend_from_slice(&[
+                    v[0], m01, m20,
+                    m01, v[1], m12,
+                    m20, m12, v[2],
+                    m01, m12, m20,
+                ]),
+                _ if m.iter().flatten().count() == 1 => {
+                    let i = split.iter().position(|&s| s).unwrap();
+                    let (a, b, c) = (v[i], v[(i + 1) % 3], v[(i + 2) % 3]);
+                    let mab = m[i].unwrap();
+                    new_indices.extend_from_slice(&[a, mab, c, mab, b, c]);
+                },
+                _ => {
+                    let i = split.iter().position(|&s| !s).unwrap();
+                    let (a, b, c) = (v[(i + 1) % 3], v[(i + 2) % 3], v[i]);
+                    let (mab, mbc) = (m[(i + 1) % 3].unwrap(), m[(i + 2) % 3].unwrap());
+                    new_indices.extend_from_slice(&[mab, b, mbc, a, mab, mbc, a, mbc, c]);
+                },
+            }
+        }
+        Some(new_indices)
+    }
+
+    fn midpoint(&mut self, midpoints: &mut HashMap<(u32, u32), u32>, a: u32, b: u32) -> u32 {
+        let key = (a.min(b), a.max(b));
+        if let Some(&i) = midpoints.get(&key) {
+            return i;
+        }
+        let (a, b) = (a as usize, b as usize);
+        let i = self.p.len() as u32;
+
+        let p = self.p[a].midpoint(self.p[b]);
+        let n = self.n[a].0 + self.n[b].0;
+        let n = if n.magnitude2() > 0.0 { Normal3(n.normalize()) } else { self.n[a] };
+        self.p.push(p);
+        self.n.push(n);
+        if let Some(ref mut uv) = self.uv {
+            let mid = uv[a].midpoint(uv[b]);
+            uv.push(mid);
+        }
+
+        midpoints.insert(key, i);
+        i
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::Bounds3f;
+    use crate::shapes::Shape;
+    use crate::texture::ConstantTexture;
+
+    fn unit_square() -> (Vec<Point3f>, Vec<u32>) {
+        let p = vec![
+            Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 0.0, 0.0),
+            Point3f::new(1.0, 1.0, 0.0), Point3f::new(0.0, 1.0, 0.0),
+        ];
+        (p, vec![0, 1, 2, 0, 2, 3])
+    }
+
+    #[test]
+    fn test_displace_square() {
+        let (p, indices) = unit_square();
+        let displacement: TextureRef<Float> = Arc::new(ConstantTexture(0.5));
+        let mesh = Arc::new(displace_mesh(
+            &Transform::IDENTITY, false, &p, &indices, None, None, &displacement, Tessellation::Levels(2)
+        ));
+        assert_eq!(mesh.n_triangles, 2 * 4 * 4);
+
+        let bounds = mesh.iter_triangles()
+            .fold(Bounds3f::empty(), |b, tri| b.join(&tri.world_bound()));
+        assert_abs_diff_eq!(bounds.min, Point3f::new(0.0, 0.0, 0.5), epsilon = 1e-6);
+        assert_abs_diff_eq!(bounds.max, Point3f::new(1.0, 1.0, 0.5), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_displace_scaled_square() {
+        // The displacement is a distance in world space.
+        let (p, indices) = unit_square();
+        let displacement: TextureRef<Float> = Arc::new(ConstantTexture(0.5));
+        let mesh = Arc::new(displace_mesh(
+            &Transform::scale(2.0, 2.0, 2.0), false, &p, &indices, None, None, &displacement, Tessellation::Levels(1)
+        ));
+        let bounds = mesh.iter_triangles()
+            .fold(Bounds3f::empty(), |b, tri| b.join(&tri.world_bound()));
+        assert_abs_diff_eq!(bounds.min, Point3f::new(0.0, 0.0, 0.5), epsilon = 1e-6);
+        assert_abs_diff_eq!(bounds.max, Point3f::new(2.0, 2.0, 0.5), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_edge_length_tessellation() {
+        let (p, indices) = unit_square();
+        let mut mesh = DisplacedVertices {
+            p: p.clone(),
+            n: vertex_normals(&p, &indices),
+            uv: None,
+        };
+        let max_len = 0.3;
+        let indices = mesh.tessellate(indices, Tessellation::EdgeLength(max_len));
+
+        let mut edge_counts: HashMap<(u32, u32), usize> = HashMap::new();
+        for tri in indices.chunks_exact(3) {
+            for (&a, &b) in tri.iter().zip(tri.iter().cycle().skip(1)) {
+                assert!((mesh.p[a as usize] - mesh.p[b as usize]).magnitude() <= max_len);
+                *edge_counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+
+        // Without any T-junctions, the edges only used by one triangle are exactly the
+        // outline of the square.
+        let perimeter: Float = edge_counts.iter()
+            .filter(|&(_, &count)| count == 1)
+            .map(|(&(a, b), _)| (mesh.p[a as usize] - mesh.p[b as usize]).magnitude())
+            .sum();
+        assert_abs_diff_eq!(perimeter, 4.0, epsilon = 1e-5);
+        assert!(edge_counts.values().all(|&count| count <= 2));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{Transform, Point2f, Point3f, Normal3, Float};
+use crate::shapes::triangle::{TriangleMesh, vertex_normals};
+
+/// Tessellates a grid of `nu` by `nv` heights over the unit square in xy into a triangle mesh.
+/// Heights are stored row by row with u varying fastest, and the mesh gets uvs matching the
+/// xy position of each vertex and normals averaged from the surrounding triangles.
+pub fn heightfield(
+    obj_to_world: &Transform,
+    reverse_orientation: bool,
+    nu: usize,
+    nv: usize,
+    heights: &[Float],
+) -> TriangleMesh {
+    let (indices, p, uv, n) = tessellate(nu, nv, heights);
+    TriangleMesh::new(
+        obj_to_world.clone(),
+        indices,
+        p,
+        Some(n),
+        None,
+        Some(uv),
+        reverse_orientation,
+    )
+}
+
+/// Returns the vertex indices, positions, uvs and normals of the tessellated grid.
+fn tessellate(
+    nu: usize,
+    nv: usize,
+    heights: &[Float],
+) -> (Vec<u32>, Vec<Point3f>, Vec<Point2f>, Vec<Normal3>) {
+    assert!(nu >= 2 && nv >= 2, "Heightfield needs at least 2x2 samples");
+    assert_eq!(heights.len(), nu * nv);
+
+    let mut p = Vec::with_capacity(nu * nv);
+    let mut uv = Vec::with_capacity(nu * nv);
+    for v in 0..nv {
+        for u in 0..nu {
+            let st = Point2f::new(u as Float / (nu - 1) as Float, v as Float / (nv - 1) as Float);
+            p.push(Point3f::new(st.x, st.y, heights[v * nu + u]));
+            uv.push(st);
+        }
+    }
+
+    let vert = |u: usize, v: usize| (v * nu + u) as u32;
+    let mut indices = Vec::with_capacity(6 * (nu - 1) * (nv - 1));
+    for v in 0..nv - 1 {
+        for u in 0..nu - 1 {
+            indices.extend_from_slice(&[vert(u, v), vert(u + 1, v), vert(u + 1, v + 1)]);
+            indices.extend_from_slice(&[vert(u, v), vert(u + 1, v + 1), vert(u, v + 1)]);
+        }
+    }
+
+    let n = vertex_normals(&p, &indices);
+    (indices, p, uv, n)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+    use crate::Bounds3f;
+    use crate::shapes::Shape;
+
+    #[test]
+    fn test_heightfield_triangles_and_bounds() {
+        let heights = [
+            0.0, 0.5, 0.0,
+            0.5, 2.0, 0.5,
+            0.0, 0.5, -1.0,
+            0.0, 0.0, 0.0,
+        ];
+        let mesh = Arc::new(heightfield(&Transform::IDENTITY, false, 3, 4, &heights));
+        assert_eq!(mesh.n_triangles, 2 * 2 * 3);
+
+        let bounds = mesh.iter_triangles()
+            .fold(Bounds3f::empty(), |b, tri| b.join(&tri.world_bound()));
+        assert_abs_diff_eq!(bounds.min, Point3f::new(0.0, 0.0, -1.0));
+        assert_abs_diff_eq!(bounds.max, Point3f::new(1.0, 1.0, 2.0));
+    }
+
+    #[test]
+    fn test_flat_heightfield_normals() {
+        let (indices, _, uv, n) = tessellate(2, 2, &[1.0; 4]);
+        assert_eq!(indices.len(), 6);
+        assert_eq!(uv[3], Point2f::new(1.0, 1.0));
+        for normal in n {
+            assert_abs_diff_eq!(normal.0, vec3f!(0.0, 0.0, 1.0), epsilon = 1e-6);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use cgmath::InnerSpace;
+
+use crate::{ComponentWiseExt, EFloat, Float, Normal3, Point2f, Point3f, Vec3f};
+use crate::err_float::gamma;
+use crate::geometry::{Ray, Transform};
+use crate::geometry::bounds::Bounds3;
+use crate::interaction::{DiffGeom, SurfaceHit};
+use crate::interaction::SurfaceInteraction;
+use crate::math::quadratic;
+use crate::sampling::Distribution1D;
+use crate::shapes::{Shape, efloat_ray, nearest_quadric_hit, normal_derivatives};
+use cgmath::EuclideanSpace;
+use std::borrow::Borrow;
+
+/// The number of segments the line between `p1` and `p2` is split into to tabulate the
+/// surface area along it.
+const N_AREA_SEGMENTS: usize = 256;
+
+/// The surface swept by rotating the line segment from `p1` to `p2` around the z axis. This is
+/// a hyperboloid of one sheet in general, or a cylinder, cone or disk for special cases of the
+/// endpoints. The endpoints must have different z values.
+#[derive(Debug)]
+pub struct Hyperboloid<T: Borrow<Transform>=Transform> {
+    object_to_world: T,
+    world_to_object: T,
+    reverse_orientation: bool,
+
+    p1: Point3f,
+    p2: Point3f,
+    z_min: Float,
+    z_max: Float,
+    r_max: Float,
+    phi_max: Float,
+
+    /// The length of the cross product of the tangents, divided by `phi_max`, at points
+    /// along the line, so that points can be sampled uniformly by area.
+    area_distrib: Distribution1D,
+}
+
+impl<T: Borrow<Transform>> Hyperboloid<T> {
+    pub fn new(
+        object_to_world: T,
+        world_to_object: T,
+        reverse_orientation: bool,
+        p1: Point3f,
+        p2: Point3f,
+        phi_max: Float
+    ) -> Self {
+        let r1 = (p1.x * p1.x + p1.y * p1.y).sqrt();
+        let r2 = (p2.x * p2.x + p2.y * p2.y).sqrt();
+
+        let d = p2 - p1;
+        let area_func = (0..N_AREA_SEGMENTS)
+            .map(|i| {
+                let v = (i as Float + 0.5) / N_AREA_SEGMENTS as Float;
+                let pr = p1 + v * d;
+                vec3f!(-pr.y, pr.x, 0.0).cross(d).magnitude()
+            })
+            .collect();
+
+        Self {
+            object_to_world, world_to_object, reverse_orientation,
+            p1,
+            p2,
+            z_min: Float::min(p1.z, p2.z),
+            z_max: Float::max(p1.z, p2.z),
+            r_max: Float::max(r1, r2),
+            phi_max: phi_max.clamp(0.0, 360.0).to_radians(),
+            area_distrib: Distribution1D::new(area_func),
+        }
+    }
+
+    /// The point on the line from `p1` to `p2` at parameter `v`.
+    fn line_point(&self, v: Float) -> Point3f {
+        self.p1 + v * (self.p2 - self.p1)
+    }
+
+    /// The parameter along the line of the circle at height `z`.
+    fn v_at(&self, z: Float) -> Float {
+        (z - self.p1.z) / (self.p2.z - self.p1.z)
+    }
+
+    /// Rotates a vector around the z axis by `phi`.
+    fn rotate(v: Vec3f, phi: Float) -> Vec3f {
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        vec3f!(v.x * cos_phi - v.y * sin_phi, v.x * sin_phi + v.y * cos_phi, v.z)
+    }
+}
+
+impl<T: Borrow<Transform> + Sync + Send> Shape for Hyperboloid<T> {
+    fn object_bound(&self) -> Bounds3<f32> {
+        bounds3f!((-self.r_max, -self.r_max, self.z_min), (self.r_max, self.r_max, self.z_max))
+    }
+
+    fn object_to_world(&self) -> &Transform {
+        self.object_to_world.borrow()
+    }
+
+    fn world_to_object(&self) -> &Transform {
+        self.world_to_object.borrow()
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+
+    fn area(&self) -> Float {
+        self.phi_max * self.area_distrib.func_integral()
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+        let (ray, (origin_err, dir_err)) = self.world_to_object().tf_exact_to_err(*ray);
+        let ([ox, oy, oz], [dirx, diry, dirz]) = efloat_ray(&ray, origin_err, dir_err);
+
+        // The squared radius at height z is quadratic in the line parameter v, which is linear
+        // in z, so write both sides of x^2 + y^2 = r(v)^2 in terms of v along the ray.
+        let d = self.p2 - self.p1;
+        let r_sq_0 = EFloat::new(self.p1.x * self.p1.x + self.p1.y * self.p1.y);
+        let r_sq_1 = EFloat::new(self.p1.x * d.x + self.p1.y * d.y);
+        let r_sq_2 = EFloat::new(d.x * d.x + d.y * d.y);
+
+        let inv_dz = EFloat::new(1.0) / EFloat::new(d.z);
+        let v0 = (oz - EFloat::new(self.p1.z)) * inv_dz;
+        let vt = dirz * inv_dz;
+
+        let a = dirx * dirx + diry * diry - r_sq_2 * vt * vt;
+        let b = 2.0 * (dirx * ox + diry * oy - r_sq_1 * vt - r_sq_2 * v0 * vt);
+        let c = ox * ox + oy * oy - r_sq_0 - 2.0 * r_sq_1 * v0 - r_sq_2 * v0 * v0;
+
+        let (t0, t1) = quadratic(a, b, c)?;
+
+        let (t_shape_hit, (p_hit, v, phi)) = nearest_quadric_hit(t0, t1, ray.t_max, |t| {
+            let p_hit = ray.at(t.into());
+            let v = self.v_at(p_hit.z);
+            if !(0.0..=1.0).contains(&v) {
+                return None;
+            }
+
+            // The angle is relative to the line, which may not start at phi = 0
+            let pr = self.line_point(v);
+            let mut phi = Float::atan2(pr.x * p_hit.y - p_hit.x * pr.y, p_hit.x * pr.x + p_hit.y * pr.y);
+            if phi < 0.0 { phi += 2.0 * std::f32::consts::PI }
+            if phi > self.phi_max {
+                None
+            } else {
+                Some((p_hit, v, phi))
+            }
+        })?;
+
+        let u = phi / self.phi_max;
+
+        let dpdu = vec3f!(-self.phi_max * p_hit.y, self.phi_max * p_hit.x, 0.0);
+        let dpdv = Self::rotate(d, phi);
+
+        let d2pduu = -self.phi_max * self.phi_max * vec3f!(p_hit.x, p_hit.y, 0.0);
+        let d2pduv = self.phi_max * vec3f!(-dpdv.y, dpdv.x, 0.0);
+        let d2pdvv = vec3f!(0.0, 0.0, 0.0);
+        let (dndu, dndv) = normal_derivatives(dpdu, dpdv, d2pduu, d2pduv, d2pdvv);
+
+        let p_err = gamma(5) * p_hit.to_vec().abs();
+
+        let mut n = dpdu.cross(dpdv).normalize();
+        if self.flip_normals() {
+            n *= -1.0;
+        }
+
+        let interact = SurfaceInteraction::new(
+            p_hit,
+            p_err,
+            ray.time,
+            Point2f::new(u, v),
+            -ray.dir,
+            Normal3(n),
+            DiffGeom { dpdu, dpdv, dndu, dndv }
+        );
+
+        let world_intersect = self.object_to_world().transform(interact);
+        Some((t_shape_hit.into(), world_intersect))
+    }
+
+    fn sample(&self, u: Point2f) -> SurfaceHit {
+        let (v, _, _) = self.area_distrib.sample_continuous(u[0]);
+        let phi = u[1] * self.phi_max;
+        let p_obj = Point3f::from_vec(Self::rotate(self.line_point(v).to_vec(), phi));
+
+        let dpdu = vec3f!(-p_obj.y, p_obj.x, 0.0);
+        let dpdv = Self::rotate(self.p2 - self.p1, phi);
+        let mut n = Normal3(self.object_to_world().transform(Normal3(dpdu.cross(dpdv))).normalize());
+        if self.reverse_orientation {
+            n *= -1.0;
+        }
+        let p_obj_err = gamma(5) * p_obj.to_vec().abs();
+        let (p, p_err) = self.object_to_world().tf_err_to_err(p_obj, p_obj_err);
+        SurfaceHit {
+            p,
+            p_err,
+            time: 0.0,
+            n
+        }
+    }
+
+    fn pdf(&self, hit: &SurfaceHit) -> Float {
+        // Points are sampled with a piecewise-constant density along the line rather than
+        // exactly by area, so find the density of the segment containing the point.
+        let p_obj = self.world_to_object().transform(hit.p);
+        let v = self.v_at(p_obj.z).clamp(0.0, 1.0);
+        let func = self.area_distrib.func();
+        let idx = ((v * func.len() as Float) as usize).min(func.len() - 1);
+        let pdf_v = func[idx] / self.area_distrib.func_integral();
+
+        let pr = self.line_point(v);
+        let tangent_area = vec3f!(-pr.y, pr.x, 0.0).cross(self.p2 - self.p1).magnitude();
+        pdf_v / (self.phi_max * tangent_area)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_hyperboloid_as_cylinder() {
+        // A vertical line sweeps out a cylinder, so the area and intersections should match.
+        let hyperboloid = Hyperboloid::new(
+            Transform::IDENTITY, Transform::IDENTITY, false,
+            Point3f::new(1.0, 0.0, -1.0), Point3f::new(1.0, 0.0, 1.0), 360.0
+        );
+        assert_abs_diff_eq!(hyperboloid.area(), 4.0 * std::f32::consts::PI, epsilon = 1e-3);
+
+        let ray = Ray::new(Point3f::new(0.0, -3.0, 0.5), vec3f!(0.0, 1.0, 0.0));
+        let (t, isect) = hyperboloid.intersect(&ray).expect("ray should hit the hyperboloid");
+        assert_abs_diff_eq!(t, 2.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(isect.uv.x, 0.75, epsilon = 1e-4);
+        assert_abs_diff_eq!(isect.uv.y, 0.75, epsilon = 1e-4);
+        assert_abs_diff_eq!(hyperboloid.pdf(&isect.hit), 1.0 / hyperboloid.area(), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_hyperboloid_samples_lie_on_surface() {
+        let (p1, p2) = (Point3f::new(1.0, 0.0, -1.0), Point3f::new(0.0, 1.0, 1.0));
+        let hyperboloid = Hyperboloid::new(Transform::IDENTITY, Transform::IDENTITY, false, p1, p2, 360.0);
+        for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            let hit = hyperboloid.sample(Point2f::new(u, v));
+            let pr = hyperboloid.line_point(hyperboloid.v_at(hit.p.z));
+            let r = (pr.x * pr.x + pr.y * pr.y).sqrt();
+            assert_abs_diff_eq!((hit.p.x * hit.p.x + hit.p.y * hit.p.y).sqrt(), r, epsilon = 1e-4);
+
+            // Shooting back at the point from outside should find it again.
+            let origin = Point3f::new(3.0 * hit.p.x, 3.0 * hit.p.y, hit.p.z);
+            let (_, isect) = hyperboloid.intersect(&Ray::new(origin, hit.p - origin))
+                .expect("ray should hit the sampled point");
+            assert_abs_diff_eq!(isect.hit.p.z, hit.p.z, epsilon = 1e-3);
+        }
+    }
+}
@@ -1,10 +1,8 @@
-use crate::{Transform, Point3f};
+use crate::{Transform, Point3f, Vec3f, Normal3, Float};
 use crate::shapes::triangle::TriangleMesh;
-use bumpalo::Bump;
 use crate::id_arena::{Id, IdArena};
 use std::collections::HashMap;
-use cgmath::Array;
-use arrayvec::ArrayVec;
+use cgmath::{EuclideanSpace, InnerSpace};
 
 struct SDVertex {
     p: Point3f,
@@ -57,6 +55,13 @@ impl SDFace {
     pub fn prev_vert(&self, vert: Id<SDVertex>) -> Id<SDVertex> {
         self.v.unwrap()[prev(self.vert_num(vert))]
     }
+
+    /// The vertex of this face that is opposite the edge from `v0` to `v1`.
+    pub fn other_vert(&self, v0: Id<SDVertex>, v1: Id<SDVertex>) -> Id<SDVertex> {
+        *self.v.unwrap().iter()
+            .find(|&&item| item != v0 && item != v1)
+            .expect("Face has no vertex opposite the edge")
+    }
 }
 
 #[derive(Eq, PartialEq, Hash)]
@@ -86,6 +91,18 @@ fn prev(i: usize) -> usize {
     (i + 2) % 3
 }
 
+fn beta(valence: usize) -> Float {
+    if valence == 3 {
+        3.0 / 16.0
+    } else {
+        3.0 / (8.0 * valence as Float)
+    }
+}
+
+fn loop_gamma(valence: usize) -> Float {
+    1.0 / (valence as Float + 3.0 / (8.0 * beta(valence)))
+}
+
 struct SDData {
     vertices: IdArena<SDVertex>,
     faces: IdArena<SDFace>,
@@ -125,17 +142,115 @@ impl SDData {
                 + 1
         }
     }
+
+    /// Returns the positions of the vertices adjacent to a vertex. For boundary vertices, the
+    /// ring starts and ends with the two neighbors along the boundary.
+    pub fn one_ring(&self, vert_id: Id<SDVertex>) -> Vec<Point3f> {
+        let vertex = self.vertices.get(vert_id);
+        let start_face = vertex.start_face.unwrap();
+
+        if !vertex.boundary {
+            let first = std::iter::once(start_face);
+            let rest = self.iter_adjacent_faces_forward(vert_id)
+                .skip(1)
+                .take_while(|&face| face != start_face);
+            first.chain(rest)
+                .map(|face| self.vertices.get(self.faces.get(face).next_vert(vert_id)).p)
+                .collect()
+        } else {
+            // Find the face at the forward end of the fan, then walk backwards across it.
+            let last_face = self.iter_adjacent_faces_forward(vert_id).last().unwrap();
+            let first_p = self.vertices.get(self.faces.get(last_face).next_vert(vert_id)).p;
+            let rest = std::iter::successors(Some(last_face), |&face| {
+                self.faces.get(face).prev_face(vert_id)
+            }).map(|face| self.vertices.get(self.faces.get(face).prev_vert(vert_id)).p);
+            std::iter::once(first_p).chain(rest).collect()
+        }
+    }
+
+    /// Weights a vertex with its whole one ring, with weight `beta` for each neighbor.
+    pub fn weight_one_ring(&self, vert_id: Id<SDVertex>, beta: Float) -> Point3f {
+        let ring = self.one_ring(vert_id);
+        let valence = ring.len();
+        let p = ring.iter()
+            .fold((1.0 - valence as Float * beta) * self.vertices.get(vert_id).p.to_vec(), |p, r| {
+                p + beta * r.to_vec()
+            });
+        Point3f::from_vec(p)
+    }
+
+    /// Weights a boundary vertex with only its two neighbors along the boundary, so that the
+    /// boundary is refined as a curve that doesn't depend on the interior of the mesh.
+    pub fn weight_boundary(&self, vert_id: Id<SDVertex>, beta: Float) -> Point3f {
+        let ring = self.one_ring(vert_id);
+        let p = (1.0 - 2.0 * beta) * self.vertices.get(vert_id).p.to_vec()
+            + beta * ring[0].to_vec()
+            + beta * ring[ring.len() - 1].to_vec();
+        Point3f::from_vec(p)
+    }
+
+    /// Computes the normal of the limit surface at a vertex from the tangents across its one ring.
+    pub fn limit_normal(&self, vert_id: Id<SDVertex>) -> Normal3 {
+        let ring: Vec<Vec3f> = self.one_ring(vert_id).into_iter().map(|p| p.to_vec()).collect();
+        let valence = ring.len();
+        let p = self.vertices.get(vert_id).p.to_vec();
+        let zero = Vec3f::new(0.0, 0.0, 0.0);
+
+        let (s, t) = if !self.vertices.get(vert_id).boundary {
+            ring.iter().enumerate().fold((zero, zero), |(s, t), (i, &r)| {
+                let theta = 2.0 * std::f32::consts::PI * i as Float / valence as Float;
+                (s + theta.cos() * r, t + theta.sin() * r)
+            })
+        } else {
+            let s = ring[valence - 1] - ring[0];
+            let t = match valence {
+                2 => ring[0] + ring[1] - 2.0 * p,
+                3 => ring[1] - p,
+                4 => -ring[0] + 2.0 * ring[1] + 2.0 * ring[2] - ring[3] - 2.0 * p,
+                _ => {
+                    let theta = std::f32::consts::PI / (valence - 1) as Float;
+                    let t = (1..valence - 1).fold(theta.sin() * (ring[0] + ring[valence - 1]), |t, k| {
+                        let wt = (2.0 * theta.cos() - 2.0) * (k as Float * theta).sin();
+                        t + wt * ring[k]
+                    });
+                    -t
+                }
+            };
+            (s, t)
+        };
+        Normal3(s.cross(t).normalize())
+    }
 }
 
+/// Applies `n_levels` of Loop subdivision to a triangle mesh, then moves the vertices to their
+/// positions on the limit surface and computes the limit surface normals.
 pub fn loop_subdivide(
     obj_to_world: &Transform,
+    reverse_orientation: bool,
     n_levels: u32,
     vertices: &[Point3f],
     vertex_indices: &[u32],
 ) -> TriangleMesh {
+    let (indices, p, n) = subdivide(n_levels, vertices, vertex_indices);
+    TriangleMesh::new(
+        obj_to_world.clone(),
+        indices,
+        p,
+        Some(n),
+        None,
+        None,
+        reverse_orientation,
+    )
+}
+
+/// Returns the vertex indices, limit surface positions and normals of the subdivided mesh.
+fn subdivide(
+    n_levels: u32,
+    vertices: &[Point3f],
+    vertex_indices: &[u32],
+) -> (Vec<u32>, Vec<Point3f>, Vec<Normal3>) {
 
     assert_eq!(vertex_indices.len() % 3, 0);
-    let n_faces = vertex_indices.len() / 3;
 
     let mut data = SDData {
         vertices: IdArena::new(),
@@ -147,7 +262,6 @@ pub fn loop_subdivide(
         .map(|p| data.vertices.insert(SDVertex::new(*p)))
         .collect::<Vec<_>>();
 
-    let (face_indices, _) = vertex_indices.as_chunks::<3>();
     let initial_faces = vertex_indices
         .array_chunks::<3>()
         .map(|&[v1, v2, v3]| {
@@ -198,23 +312,24 @@ pub fn loop_subdivide(
         }
     }
 
+    // Vertices not used by any face are dropped from the output mesh.
+    let initial_verts: Vec<_> = initial_verts.into_iter()
+        .filter(|&vert_id| data.vertices.get(vert_id).start_face.is_some())
+        .collect();
+
     for &vert_id in &initial_verts {
         let start_face = data.vertices.get(vert_id).start_face.unwrap();
 
-        let boundary = data.iter_adjacent_faces_forward(vert_id)
+        // An interior vertex's fan of faces loops back around to the start face, while walking
+        // around a boundary vertex runs into a missing neighbor.
+        let interior = data.iter_adjacent_faces_forward(vert_id)
             .skip(1)
             .any(|face| face == start_face);
 
-        data.vertices.get_mut(vert_id).boundary = boundary;
+        data.vertices.get_mut(vert_id).boundary = !interior;
 
         let valence = data.vertex_valence(vert_id);
-        let regular = if !boundary && valence == 6 {
-            true
-        } else if boundary && valence == 4 {
-            true
-        } else {
-            false
-        };
+        let regular = (interior && valence == 6) || (!interior && valence == 4);
 
         data.vertices.get_mut(vert_id).regular = regular;
     }
@@ -222,32 +337,309 @@ pub fn loop_subdivide(
     let mut f = initial_faces;
     let mut v = initial_verts;
     for _level in 0..n_levels {
-        let mut new_faces = Vec::new();
-        let mut new_vertices = Vec::new();
+        let mut new_faces = Vec::with_capacity(4 * f.len());
+        let mut new_vertices = Vec::with_capacity(v.len());
 
         // allocate next level of children in mesh tree
         for &vert_id in &v {
-            let child = data.vertices.insert(SDVertex::new(Point3f::from_value(0.0)));
-            data.vertices.get_mut(child).regular = data.vertices.get(vert_id).regular;
-            data.vertices.get_mut(child).boundary = data.vertices.get_mut(vert_id).boundary;
+            let parent = data.vertices.get(vert_id);
+            let mut child = SDVertex::new(parent.p);
+            child.regular = parent.regular;
+            child.boundary = parent.boundary;
+            let child = data.vertices.insert(child);
             data.vertices.get_mut(vert_id).child = Some(child);
             new_vertices.push(child);
         }
 
         for &face_id in &f {
-            let mut children = ArrayVec::<[_; 4]>::new();
-            for i in 0..4 {
-                children.push(data.faces.insert(SDFace {
+            let mut children = [face_id; 4];
+            for child in children.iter_mut() {
+                *child = data.faces.insert(SDFace {
                     v: None,
                     f: [None; 3],
                     children: None
-                }))
+                });
+                new_faces.push(*child);
             }
-            // TODO clean up arrayvec
-            data.faces[face_id].children = Some(children.into_inner().unwrap());
+            data.faces.get_mut(face_id).children = Some(children);
         }
+
+        // Update positions of the existing ("even") vertices
+        for &vert_id in &v {
+            let vertex = data.vertices.get(vert_id);
+            let p = if !vertex.boundary {
+                if vertex.regular {
+                    data.weight_one_ring(vert_id, 1.0 / 16.0)
+                } else {
+                    data.weight_one_ring(vert_id, beta(data.vertex_valence(vert_id)))
+                }
+            } else {
+                data.weight_boundary(vert_id, 1.0 / 8.0)
+            };
+            let child = data.vertices.get(vert_id).child.unwrap();
+            data.vertices.get_mut(child).p = p;
+        }
+
+        // Create new ("odd") vertices along each edge
+        let mut edge_verts: HashMap<SDEdge, Id<SDVertex>> = HashMap::new();
+        for &face_id in &f {
+            for k in 0..3 {
+                let face = data.faces.get(face_id);
+                let fv = face.v.unwrap();
+                let edge = SDEdge::new(fv[k], fv[next(k)]);
+                if edge_verts.contains_key(&edge) {
+                    continue;
+                }
+
+                let [v0, v1] = edge.v;
+                let p0 = data.vertices.get(v0).p.to_vec();
+                let p1 = data.vertices.get(v1).p.to_vec();
+                let p = match face.f[k] {
+                    None => 0.5 * (p0 + p1),
+                    Some(nbr) => {
+                        let p2 = data.vertices.get(face.other_vert(v0, v1)).p.to_vec();
+                        let p3 = data.vertices.get(data.faces.get(nbr).other_vert(v0, v1)).p.to_vec();
+                        (3.0 / 8.0) * (p0 + p1) + (1.0 / 8.0) * (p2 + p3)
+                    }
+                };
+
+                let mut vert = SDVertex::new(Point3f::from_vec(p));
+                vert.regular = true;
+                vert.boundary = face.f[k].is_none();
+                vert.start_face = Some(face.children.unwrap()[3]);
+                let vert = data.vertices.insert(vert);
+                new_vertices.push(vert);
+                edge_verts.insert(edge, vert);
+            }
+        }
+
+        // Update the start faces of the even vertices
+        for &vert_id in &v {
+            let vertex = data.vertices.get(vert_id);
+            let start_face = data.faces.get(vertex.start_face.unwrap());
+            let child_face = start_face.children.unwrap()[start_face.vert_num(vert_id)];
+            let child = vertex.child.unwrap();
+            data.vertices.get_mut(child).start_face = Some(child_face);
+        }
+
+        // Update the neighbors and vertices of the new faces. The child faces at each corner of a
+        // face are numbered the same as the corner's vertex, and the child in the middle is last.
+        for &face_id in &f {
+            let face = data.faces.get(face_id);
+            let fv = face.v.unwrap();
+            let children = face.children.unwrap();
+
+            let mut neighbors = [[None; 3]; 4];
+            for j in 0..3 {
+                neighbors[3][j] = Some(children[next(j)]);
+                neighbors[j][next(j)] = Some(children[3]);
+
+                let child_across = |nbr: Option<Id<SDFace>>| nbr.map(|nbr| {
+                    let nbr = data.faces.get(nbr);
+                    nbr.children.unwrap()[nbr.vert_num(fv[j])]
+                });
+                neighbors[j][j] = child_across(face.f[j]);
+                neighbors[j][prev(j)] = child_across(face.f[prev(j)]);
+            }
+
+            let mid_verts = [
+                edge_verts[&SDEdge::new(fv[0], fv[1])],
+                edge_verts[&SDEdge::new(fv[1], fv[2])],
+                edge_verts[&SDEdge::new(fv[2], fv[0])],
+            ];
+            let mut child_verts = [mid_verts; 4];
+            for j in 0..3 {
+                child_verts[j][j] = data.vertices.get(fv[j]).child.unwrap();
+                child_verts[j][next(j)] = mid_verts[j];
+                child_verts[j][prev(j)] = mid_verts[prev(j)];
+            }
+
+            for (i, &child) in children.iter().enumerate() {
+                let child = data.faces.get_mut(child);
+                child.f = neighbors[i];
+                child.v = Some(child_verts[i]);
+            }
+        }
+
+        f = new_faces;
+        v = new_vertices;
+    }
+
+    // Push the vertices to the limit surface, after computing all of the limit positions from
+    // the current ones.
+    let limit: Vec<Point3f> = v.iter()
+        .map(|&vert_id| {
+            if data.vertices.get(vert_id).boundary {
+                data.weight_boundary(vert_id, 1.0 / 5.0)
+            } else {
+                data.weight_one_ring(vert_id, loop_gamma(data.vertex_valence(vert_id)))
+            }
+        })
+        .collect();
+    for (&vert_id, &p) in v.iter().zip(limit.iter()) {
+        data.vertices.get_mut(vert_id).p = p;
     }
 
-    unimplemented!()
+    let normals = v.iter().map(|&vert_id| data.limit_normal(vert_id)).collect();
+
+    let vert_indices: HashMap<Id<SDVertex>, u32> = v.iter()
+        .enumerate()
+        .map(|(i, &vert_id)| (vert_id, i as u32))
+        .collect();
+    let indices = f.iter()
+        .flat_map(|&face_id| {
+            let fv = data.faces.get(face_id).v.unwrap();
+            let vert_indices = &vert_indices;
+            (0..3).map(move |i| vert_indices[&fv[i]])
+        })
+        .collect();
+
+    (indices, limit, normals)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    fn octahedron() -> (Vec<Point3f>, Vec<u32>) {
+        let vertices = vec![
+            Point3f::new(1.0, 0.0, 0.0), Point3f::new(-1.0, 0.0, 0.0),
+            Point3f::new(0.0, 1.0, 0.0), Point3f::new(0.0, -1.0, 0.0),
+            Point3f::new(0.0, 0.0, 1.0), Point3f::new(0.0, 0.0, -1.0),
+        ];
+        let indices = vec![
+            0, 2, 4,  2, 1, 4,  1, 3, 4,  3, 0, 4,
+            2, 0, 5,  1, 2, 5,  3, 1, 5,  0, 3, 5,
+        ];
+        (vertices, indices)
+    }
+
+    #[test]
+    fn test_subdivide_connectivity() {
+        let vertices = vec![
+            Point3f::new(1.0, 1.0, 1.0), Point3f::new(1.0, -1.0, -1.0),
+            Point3f::new(-1.0, 1.0, -1.0), Point3f::new(-1.0, -1.0, 1.0),
+        ];
+        let indices = vec![0, 1, 2,  0, 3, 1,  0, 2, 3,  1, 3, 2];
+        let (new_indices, p, _) = subdivide(1, &vertices, &indices);
+        assert_eq!(p.len(), 4 + 6);
+        assert_eq!(new_indices.len(), 3 * 16);
+
+        // Every edge of the closed mesh is shared by two faces that traverse it in opposite
+        // directions, so the child faces are consistently oriented and don't overlap.
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        for tri in new_indices.chunks_exact(3) {
+            assert!(tri[0] != tri[1] && tri[1] != tri[2] && tri[2] != tri[0]);
+            for i in 0..3 {
+                *edges.entry((tri[i], tri[(i + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+        assert_eq!(edges.len(), 2 * 24);
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1);
+            assert_eq!(edges.get(&(b, a)), Some(&1));
+        }
+    }
+
+    #[test]
+    fn test_subdivide_preserves_flat_area() {
+        // The child faces of a flat mesh tile the region inside its new boundary without
+        // folding over, so their signed areas all agree and sum to the enclosed area.
+        let vertices = vec![
+            Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 0.0, 0.0),
+            Point3f::new(1.0, 1.0, 0.0), Point3f::new(0.0, 1.0, 0.0),
+        ];
+        let indices = vec![0, 1, 2,  0, 2, 3];
+        let (new_indices, p, _) = subdivide(1, &vertices, &indices);
+
+        let signed_area = |a: Point3f, b: Point3f, c: Point3f| 0.5 * (b - a).cross(c - a).z;
+        let mut area = 0.0;
+        let mut edges = std::collections::HashSet::new();
+        for tri in new_indices.chunks_exact(3) {
+            let a = signed_area(p[tri[0] as usize], p[tri[1] as usize], p[tri[2] as usize]);
+            assert!(a > 0.0);
+            area += a;
+            for i in 0..3 {
+                edges.insert((tri[i], tri[(i + 1) % 3]));
+            }
+        }
+        let boundary: HashMap<u32, u32> = edges.iter()
+            .filter(|&&(a, b)| !edges.contains(&(b, a)))
+            .cloned()
+            .collect();
+
+        // Walk the boundary edges, which only belong to one face, for the enclosed area.
+        assert_eq!(boundary.len(), 8);
+        let start = *boundary.keys().next().unwrap();
+        let (mut v, mut enclosed) = (start, 0.0);
+        loop {
+            let next = boundary[&v];
+            enclosed += signed_area(Point3f::origin(), p[v as usize], p[next as usize]);
+            v = next;
+            if v == start {
+                break;
+            }
+        }
+        assert_abs_diff_eq!(area, enclosed, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_subdivide_closed_mesh() {
+        let (vertices, indices) = octahedron();
+        let (new_indices, p, n) = subdivide(2, &vertices, &indices);
+
+        // Each level adds a vertex per edge and splits each face in four
+        assert_eq!(p.len(), 6 + 12 + 48);
+        assert_eq!(new_indices.len(), 3 * 8 * 16);
+        assert!(new_indices.iter().all(|&i| (i as usize) < p.len()));
+
+        // The limit surface is symmetric, so normals are all along the direction from the
+        // center, on the same side of the surface.
+        let side = n[0].dot(p[0].to_vec()).signum();
+        for (p, n) in p.iter().zip(n.iter()) {
+            let dir = p.to_vec().normalize();
+            assert_abs_diff_eq!(side * n.dot(dir), 1.0, epsilon = 0.05);
+            assert!(p.to_vec().magnitude() < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_subdivide_open_mesh() {
+        // A square in the z = 0 plane made of two triangles
+        let vertices = vec![
+            Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 0.0, 0.0),
+            Point3f::new(1.0, 1.0, 0.0), Point3f::new(0.0, 1.0, 0.0),
+        ];
+        let indices = vec![0, 1, 2,  0, 2, 3];
+        let (new_indices, p, n) = subdivide(3, &vertices, &indices);
+
+        // V' = V + E per level: 4 -> 9 -> 25 -> 81
+        assert_eq!(p.len(), 81);
+        assert_eq!(new_indices.len(), 3 * 2 * 64);
+
+        for (p, n) in p.iter().zip(n.iter()) {
+            assert_abs_diff_eq!(p.z, 0.0);
+            assert_abs_diff_eq!(n.z.abs(), 1.0, epsilon = 1e-4);
+            assert!(p.x >= 0.0 && p.x <= 1.0 && p.y >= 0.0 && p.y <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_boundary_ignores_interior() {
+        // A square fan around a raised center vertex. The boundary is refined using only other
+        // boundary vertices, so it stays flat.
+        let vertices = vec![
+            Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 0.0, 0.0),
+            Point3f::new(1.0, 1.0, 0.0), Point3f::new(0.0, 1.0, 0.0),
+            Point3f::new(0.5, 0.5, 1.0),
+        ];
+        let indices = vec![0, 1, 4,  1, 2, 4,  2, 3, 4,  3, 0, 4];
+        let (_, p, _) = subdivide(2, &vertices, &indices);
+
+        // 5 -> 13 -> 41 vertices, with 4 * 2^2 of them on the boundary
+        assert_eq!(p.len(), 41);
+        assert_eq!(p.iter().filter(|p| p.z == 0.0).count(), 16);
+        assert_eq!(p.iter().filter(|p| p.z > 0.0).count(), 25);
+    }
+}
@@ -1,11 +1,22 @@
-use crate::{Float, Transform, Point2f, Vec3f, distance_sq, abs_dot};
+use crate::{Float, Transform, Point2f, Vec3f, Normal3, EFloat, distance_sq, abs_dot};
 use crate::geometry::Ray;
 use crate::geometry::bounds::Bounds3f;
 use crate::interaction::{SurfaceInteraction, SurfaceHit};
+use cgmath::InnerSpace;
 
 pub mod sphere;
+pub mod disk;
+pub mod cylinder;
+pub mod cone;
+pub mod paraboloid;
+pub mod hyperboloid;
+pub mod curve;
 pub mod triangle;
 pub mod loop_subdiv;
+pub mod heightfield;
+pub mod bilinear;
+pub mod displacement;
+pub mod alpha;
 
 pub trait Shape: Sync + Send {
     fn object_bound(&self) -> Bounds3f;
@@ -58,11 +69,79 @@ pub trait Shape: Sync + Send {
         if let Some((_, isect_light)) = self.intersect(&ray) {
             // convert from a density with respect to area to a density with respect
             // to solid angle
-            distance_sq(reference.p, isect_light.hit.p) /
-                (abs_dot(isect_light.hit.n.0, -wi) * self.area())
+            distance_sq(reference.p, isect_light.hit.p) * self.pdf(&isect_light.hit) /
+                abs_dot(isect_light.hit.n.0, -wi)
         } else {
             0.0
         }
     }
 
 }
+
+/// Converts a ray that has been transformed into object space, along with the error bounds from
+/// the transformation, into `EFloat` components for robust quadric intersection.
+pub(crate) fn efloat_ray(ray: &Ray, origin_err: Vec3f, dir_err: Vec3f) -> ([EFloat; 3], [EFloat; 3]) {
+    let origin = [
+        EFloat::with_err(ray.origin.x, origin_err.x),
+        EFloat::with_err(ray.origin.y, origin_err.y),
+        EFloat::with_err(ray.origin.z, origin_err.z),
+    ];
+    let dir = [
+        EFloat::with_err(ray.dir.x, dir_err.x),
+        EFloat::with_err(ray.dir.y, dir_err.y),
+        EFloat::with_err(ray.dir.z, dir_err.z),
+    ];
+    (origin, dir)
+}
+
+/// Chooses between the two roots of a quadric intersection, returning the nearer one in
+/// `(0, t_max]` that `accept` (which applies the shape's clipping tests) succeeds for, along
+/// with the result of `accept`.
+pub(crate) fn nearest_quadric_hit<R>(
+    t0: EFloat,
+    t1: EFloat,
+    t_max: Float,
+    mut accept: impl FnMut(EFloat) -> Option<R>
+) -> Option<(EFloat, R)> {
+    if t0.upper_bound() > t_max || t1.lower_bound() <= 0.0 {
+        return None;
+    }
+
+    for &t in [t0, t1].iter() {
+        if t.lower_bound() <= 0.0 {
+            continue;
+        }
+        if t.upper_bound() > t_max {
+            return None;
+        }
+        if let Some(result) = accept(t) {
+            return Some((t, result));
+        }
+    }
+    None
+}
+
+/// Computes the partial derivatives of the surface normal from the first and second partial
+/// derivatives of the surface position, using the Weingarten equations.
+#[allow(non_snake_case)]
+pub(crate) fn normal_derivatives(
+    dpdu: Vec3f,
+    dpdv: Vec3f,
+    d2pduu: Vec3f,
+    d2pduv: Vec3f,
+    d2pdvv: Vec3f,
+) -> (Normal3, Normal3) {
+    let E = dpdu.dot(dpdu);
+    let F = dpdu.dot(dpdv);
+    let G = dpdv.dot(dpdv);
+
+    let N = dpdu.cross(dpdv).normalize();
+    let e = N.dot(d2pduu);
+    let f = N.dot(d2pduv);
+    let g = N.dot(d2pdvv);
+
+    let invEGF2 = 1.0 / (E * G - F * F);
+    let dndu = Normal3((f * F - e * G) * invEGF2 * dpdu + (e * F - f * E) * invEGF2 * dpdv);
+    let dndv = Normal3((g * F - f * G) * invEGF2 * dpdu + (f * F - g * E) * invEGF2 * dpdv);
+    (dndu, dndv)
+}
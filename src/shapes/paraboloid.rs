@@ -0,0 +1,210 @@
+use cgmath::InnerSpace;
+
+use crate::{EFloat, Float, Normal3, Point2f, Point3f};
+use crate::err_float::gamma;
+use crate::geometry::{Ray, Transform};
+use crate::geometry::bounds::Bounds3;
+use crate::interaction::{DiffGeom, SurfaceHit};
+use crate::interaction::SurfaceInteraction;
+use crate::math::{lerp, quadratic};
+use crate::shapes::{Shape, efloat_ray, nearest_quadric_hit, normal_derivatives};
+use std::borrow::Borrow;
+
+/// A paraboloid around the z axis, with its vertex at the origin and reaching `radius` at
+/// `z_max`, clipped between `z_min` and `z_max`.
+#[derive(Debug, PartialEq)]
+pub struct Paraboloid<T: Borrow<Transform>=Transform> {
+    object_to_world: T,
+    world_to_object: T,
+    reverse_orientation: bool,
+
+    radius: Float,
+    z_min: Float,
+    z_max: Float,
+    phi_max: Float,
+}
+
+impl<T: Borrow<Transform>> Paraboloid<T> {
+    pub fn new(
+        object_to_world: T,
+        world_to_object: T,
+        reverse_orientation: bool,
+        radius: Float,
+        z_min: Float,
+        z_max: Float,
+        phi_max: Float
+    ) -> Self {
+        Self {
+            object_to_world, world_to_object, reverse_orientation,
+            radius,
+            z_min: Float::min(z_min, z_max),
+            z_max: Float::max(z_min, z_max),
+            phi_max: phi_max.clamp(0.0, 360.0).to_radians()
+        }
+    }
+
+    /// The curvature `k` of the paraboloid `z = k (x^2 + y^2)`.
+    fn k(&self) -> Float {
+        self.z_max / (self.radius * self.radius)
+    }
+
+    /// `(1 + 4 k z)^(3/2)`, which is proportional to the surface area below height `z`, up to a
+    /// constant.
+    fn area_weight(&self, z: Float) -> Float {
+        (1.0 + 4.0 * self.k() * z).powf(1.5)
+    }
+}
+
+impl<T: Borrow<Transform> + Sync + Send> Shape for Paraboloid<T> {
+    fn object_bound(&self) -> Bounds3<f32> {
+        bounds3f!((-self.radius, -self.radius, self.z_min), (self.radius, self.radius, self.z_max))
+    }
+
+    fn object_to_world(&self) -> &Transform {
+        self.object_to_world.borrow()
+    }
+
+    fn world_to_object(&self) -> &Transform {
+        self.world_to_object.borrow()
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.reverse_orientation
+    }
+
+    fn area(&self) -> Float {
+        let k = self.k();
+        self.phi_max / (12.0 * k * k) * (self.area_weight(self.z_max) - self.area_weight(self.z_min))
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+        let (ray, (origin_err, dir_err)) = self.world_to_object().tf_exact_to_err(*ray);
+        let ([ox, oy, oz], [dirx, diry, dirz]) = efloat_ray(&ray, origin_err, dir_err);
+
+        let k = EFloat::new(self.z_max) / (EFloat::new(self.radius) * EFloat::new(self.radius));
+        let a = k * (dirx * dirx + diry * diry);
+        let b = 2.0 * k * (dirx * ox + diry * oy) - dirz;
+        let c = k * (ox * ox + oy * oy) - oz;
+
+        let (t0, t1) = quadratic(a, b, c)?;
+
+        let (t_shape_hit, (p_hit, phi)) = nearest_quadric_hit(t0, t1, ray.t_max, |t| {
+            let p_hit = ray.at(t.into());
+            let mut phi = Float::atan2(p_hit.y, p_hit.x);
+            if phi < 0.0 { phi += 2.0 * std::f32::consts::PI }
+            if p_hit.z < self.z_min || p_hit.z > self.z_max || phi > self.phi_max {
+                None
+            } else {
+                Some((p_hit, phi))
+            }
+        })?;
+
+        let u = phi / self.phi_max;
+        let v = (p_hit.z - self.z_min) / (self.z_max - self.z_min);
+
+        let dz = self.z_max - self.z_min;
+        let dpdu = vec3f!(-self.phi_max * p_hit.y, self.phi_max * p_hit.x, 0.0);
+        let dpdv = dz * vec3f!(p_hit.x / (2.0 * p_hit.z), p_hit.y / (2.0 * p_hit.z), 1.0);
+
+        let d2pduu = -self.phi_max * self.phi_max * vec3f!(p_hit.x, p_hit.y, 0.0);
+        let d2pduv = dz * self.phi_max *
+            vec3f!(-p_hit.y / (2.0 * p_hit.z), p_hit.x / (2.0 * p_hit.z), 0.0);
+        let d2pdvv = -dz * dz *
+            vec3f!(p_hit.x / (4.0 * p_hit.z * p_hit.z), p_hit.y / (4.0 * p_hit.z * p_hit.z), 0.0);
+        let (dndu, dndv) = normal_derivatives(dpdu, dpdv, d2pduu, d2pduv, d2pdvv);
+
+        // Bound the error from evaluating the ray at the hit's parametric distance.
+        let px = ox + t_shape_hit * dirx;
+        let py = oy + t_shape_hit * diry;
+        let pz = oz + t_shape_hit * dirz;
+        let p_err = vec3f!(px.absolute_err(), py.absolute_err(), pz.absolute_err());
+
+        let mut n = dpdu.cross(dpdv).normalize();
+        if self.flip_normals() {
+            n *= -1.0;
+        }
+
+        let interact = SurfaceInteraction::new(
+            p_hit,
+            p_err,
+            ray.time,
+            Point2f::new(u, v),
+            -ray.dir,
+            Normal3(n),
+            DiffGeom { dpdu, dpdv, dndu, dndv }
+        );
+
+        let world_intersect = self.object_to_world().transform(interact);
+        Some((t_shape_hit.into(), world_intersect))
+    }
+
+    fn sample(&self, u: Point2f) -> SurfaceHit {
+        // Invert the area below each height so that points are uniformly distributed by area.
+        let k = self.k();
+        let w = lerp(u[0], self.area_weight(self.z_min), self.area_weight(self.z_max));
+        let z = (w.powf(2.0 / 3.0) - 1.0) / (4.0 * k);
+        let r = (z / k).max(0.0).sqrt();
+        let phi = u[1] * self.phi_max;
+        let p_obj = Point3f::new(r * phi.cos(), r * phi.sin(), z);
+
+        let n_obj = vec3f!(p_obj.x, p_obj.y, -1.0 / (2.0 * k));
+        let mut n = Normal3(self.object_to_world().transform(Normal3(n_obj)).normalize());
+        if self.reverse_orientation {
+            n *= -1.0;
+        }
+        let p_obj_err = gamma(5) * vec3f!(p_obj.x.abs(), p_obj.y.abs(), p_obj.z.abs());
+        let (p, p_err) = self.object_to_world().tf_err_to_err(p_obj, p_obj_err);
+        SurfaceHit {
+            p,
+            p_err,
+            time: 0.0,
+            n
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_abs_diff_eq;
+
+    use super::*;
+
+    /// The area of the paraboloid `z = k (x^2 + y^2)` between radii `r0` and `r1`, by numerically
+    /// integrating `2 pi r sqrt(1 + (dz/dr)^2)`.
+    fn area_between(k: Float, r0: Float, r1: Float) -> Float {
+        let n = 10_000;
+        let dr = (r1 - r0) / n as Float;
+        (0..n)
+            .map(|i| {
+                let r = r0 + (i as Float + 0.5) * dr;
+                2.0 * std::f32::consts::PI * r * (1.0 + sq!(2.0 * k * r)).sqrt() * dr
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_paraboloid_samples_lie_on_surface() {
+        let (radius, z_min, z_max) = (2.0, 0.5, 3.0);
+        let paraboloid = Paraboloid::new(Transform::IDENTITY, Transform::IDENTITY, false, radius, z_min, z_max, 360.0);
+        let k = z_max / (radius * radius);
+        let r_min = (z_min / k).sqrt();
+        let total_area = area_between(k, r_min, radius);
+
+        for &(u, v) in &[(0.1, 0.2), (0.5, 0.0), (0.9, 0.7)] {
+            let hit = paraboloid.sample(Point2f::new(u, v));
+            let r2 = hit.p.x * hit.p.x + hit.p.y * hit.p.y;
+            assert_abs_diff_eq!(hit.p.z, z_max * r2 / (radius * radius), epsilon = 1e-4);
+            assert!(hit.p.z >= z_min - 1e-4 && hit.p.z <= z_max + 1e-4);
+
+            // The fraction of the area below the sampled point should be the first sample value.
+            assert_abs_diff_eq!(area_between(k, r_min, r2.sqrt()) / total_area, u, epsilon = 1e-3);
+
+            // Shooting back at the point from outside should find it again, with the same normal.
+            let origin = Point3f::new(3.0 * hit.p.x, 3.0 * hit.p.y, hit.p.z);
+            let (_, isect) = paraboloid.intersect(&Ray::new(origin, hit.p - origin))
+                .expect("ray should hit the sampled point");
+            assert_abs_diff_eq!(isect.hit.p.z, hit.p.z, epsilon = 1e-3);
+            assert_abs_diff_eq!(isect.hit.n.dot(hit.n.0), 1.0, epsilon = 1e-4);
+        }
+    }
+}
@@ -5,8 +5,9 @@ use cgmath::{EuclideanSpace, InnerSpace};
 use crate::interaction::{DiffGeom, SurfaceHit};
 use crate::err_float::gamma;
 use crate::sampling::uniform_sample_triangle;
+use crate::shapes::alpha::alpha_test;
+use crate::texture::TextureRef;
 
-#[derive(Debug)]
 pub struct TriangleMesh {
     pub n_triangles: u32,
 
@@ -23,6 +24,33 @@ pub struct TriangleMesh {
     reverse_orientation: bool,
 
     object_to_world: Transform,
+
+    /// Masks out parts of triangles for all rays.
+    alpha_mask: Option<TextureRef<Float>>,
+
+    /// Masks out parts of triangles for shadow rays only.
+    shadow_alpha_mask: Option<TextureRef<Float>>,
+
+    /// An index for each triangle, given by the file it was loaded from.
+    face_indices: Option<Vec<i32>>,
+}
+
+impl std::fmt::Debug for TriangleMesh {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TriangleMesh")
+            .field("n_triangles", &self.n_triangles)
+            .field("vertex_indices", &self.vertex_indices)
+            .field("vertices", &self.vertices)
+            .field("normals", &self.normals)
+            .field("tangents", &self.tangents)
+            .field("tex_coords", &self.tex_coords)
+            .field("reverse_orientation", &self.reverse_orientation)
+            .field("object_to_world", &self.object_to_world)
+            .field("alpha_mask", &self.alpha_mask.is_some())
+            .field("shadow_alpha_mask", &self.shadow_alpha_mask.is_some())
+            .field("face_indices", &self.face_indices)
+            .finish()
+    }
 }
 
 impl TriangleMesh {
@@ -69,10 +97,31 @@ impl TriangleMesh {
             tangents,
             tex_coords,
             reverse_orientation,
-            object_to_world
+            object_to_world,
+            alpha_mask: None,
+            shadow_alpha_mask: None,
+            face_indices: None,
         }
     }
 
+    pub fn with_alpha_masks(
+        mut self,
+        alpha_mask: Option<TextureRef<Float>>,
+        shadow_alpha_mask: Option<TextureRef<Float>>
+    ) -> Self {
+        self.alpha_mask = alpha_mask;
+        self.shadow_alpha_mask = shadow_alpha_mask;
+        self
+    }
+
+    pub fn with_face_indices(mut self, face_indices: Option<Vec<i32>>) -> Self {
+        if let Some(ref face_indices) = face_indices {
+            assert_eq!(face_indices.len(), self.n_triangles as usize);
+        }
+        self.face_indices = face_indices;
+        self
+    }
+
     pub fn iter_triangles(self: Arc<Self>) -> impl Iterator<Item=Triangle> {
         (0..self.n_triangles).map(move |tri_id| {
             Triangle::new(Arc::clone(&self), tri_id)
@@ -80,6 +129,25 @@ impl TriangleMesh {
     }
 }
 
+/// Computes a normal for each vertex by summing the unnormalized normals of the triangles
+/// around it, so that larger triangles contribute more.
+pub fn vertex_normals(vertices: &[Point3f], vertex_indices: &[u32]) -> Vec<Normal3> {
+    let mut n = vec![Normal3::zero(); vertices.len()];
+    for tri in vertex_indices.chunks_exact(3) {
+        let [p0, p1, p2] = [vertices[tri[0] as usize], vertices[tri[1] as usize], vertices[tri[2] as usize]];
+        let face_n = (p1 - p0).cross(p2 - p0);
+        for &i in tri {
+            n[i as usize].0 += face_n;
+        }
+    }
+    for normal in &mut n {
+        if normal.0.magnitude2() > 0.0 {
+            normal.0 = normal.0.normalize();
+        }
+    }
+    n
+}
+
 pub struct Triangle {
     mesh: Arc<TriangleMesh>,
     tri_id: u32,
@@ -93,6 +161,10 @@ impl Triangle {
         }
     }
 
+    pub fn face_index(&self) -> Option<i32> {
+        self.mesh.face_indices.as_ref().map(|f| f[self.tri_id as usize])
+    }
+
     fn vertex_indices(&self) -> [u32; 3] {
         let idx = self.tri_id as usize;
         [
@@ -141,39 +213,10 @@ impl Triangle {
             }
         )
     }
-}
-
-impl Shape for Triangle {
-    fn object_bound(&self) -> Bounds3f {
-        unimplemented!()
-    }
-
-    fn world_bound(&self) -> Bounds3f {
-        let v = self.vertex_indices();
-        let p0 = self.mesh.vertices[v[0] as usize];
-        let p1 = self.mesh.vertices[v[1] as usize];
-        let p2 = self.mesh.vertices[v[2] as usize];
-        Bounds3f::empty().join_point(p0).join_point(p1).join_point(p2)
-    }
-
-    fn object_to_world(&self) -> &Transform {
-        &self.mesh.object_to_world
-    }
-
-    fn world_to_object(&self) -> &Transform {
-        unimplemented!()
-    }
-
-    fn reverse_orientation(&self) -> bool {
-        self.mesh.reverse_orientation
-    }
-
-    fn area(&self) -> Float {
-        let [p0, p1, p2] = self.get_vertices();
-        0.5 * (p1 - p0).cross(p2 - p0).magnitude()
-    }
 
-    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+    /// Intersects the ray with the triangle, skipping hits masked out by the mesh's alpha mask,
+    /// and also by its shadow alpha mask for shadow rays.
+    fn intersect_masked(&self, ray: &Ray, shadow_ray: bool) -> Option<(Float, SurfaceInteraction)> {
         let v = self.vertex_indices();
         let p0 = self.mesh.vertices[v[0] as usize];
         let p1 = self.mesh.vertices[v[1] as usize];
@@ -303,8 +346,6 @@ impl Shape for Triangle {
         let p_hit = Point3f::from_vec(b0 * p0.to_vec() + b1 * p1.to_vec() + b2 * p2.to_vec());
         let uv_hit = Point2f::from_vec(b0 * uv[0].to_vec() + b1 * uv[1].to_vec() + b2 * uv[2].to_vec());
 
-        // TODO: alpha mask
-
         let diff_geom = DiffGeom {
             dpdu,
             dpdv,
@@ -329,6 +370,15 @@ impl Shape for Triangle {
             isect.shading_n *= -1.0;
         }
 
+        if let Some(alpha) = &self.mesh.alpha_mask {
+            if !alpha_test(alpha, ray, &isect) { return None; }
+        }
+        if shadow_ray {
+            if let Some(alpha) = &self.mesh.shadow_alpha_mask {
+                if !alpha_test(alpha, ray, &isect) { return None; }
+            }
+        }
+
         if self.mesh.normals.is_some() || self.mesh.tangents.is_some() {
             // compute shading normal
             let ns = if let Some(normals) = &self.mesh.normals {
@@ -391,6 +441,45 @@ impl Shape for Triangle {
         }
         Some((t, isect))
     }
+}
+
+impl Shape for Triangle {
+    fn object_bound(&self) -> Bounds3f {
+        unimplemented!()
+    }
+
+    fn world_bound(&self) -> Bounds3f {
+        let v = self.vertex_indices();
+        let p0 = self.mesh.vertices[v[0] as usize];
+        let p1 = self.mesh.vertices[v[1] as usize];
+        let p2 = self.mesh.vertices[v[2] as usize];
+        Bounds3f::empty().join_point(p0).join_point(p1).join_point(p2)
+    }
+
+    fn object_to_world(&self) -> &Transform {
+        &self.mesh.object_to_world
+    }
+
+    fn world_to_object(&self) -> &Transform {
+        unimplemented!()
+    }
+
+    fn reverse_orientation(&self) -> bool {
+        self.mesh.reverse_orientation
+    }
+
+    fn area(&self) -> Float {
+        let [p0, p1, p2] = self.get_vertices();
+        0.5 * (p1 - p0).cross(p2 - p0).magnitude()
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(Float, SurfaceInteraction)> {
+        self.intersect_masked(ray, false)
+    }
+
+    fn intersect_test(&self, ray: &Ray) -> bool {
+        self.intersect_masked(ray, true).is_some()
+    }
 
     fn sample(&self, u: Point2f) -> SurfaceHit {
         let b = uniform_sample_triangle(u);
@@ -418,10 +507,6 @@ impl Shape for Triangle {
             n: sample_n
         }
     }
-
-//    fn intersect_test(&self, ray: &Ray) -> bool {
-//        false
-//    }
 }
 
 #[inline]
@@ -453,4 +538,30 @@ mod tests {
     fn test_tri_isect() {
 
     }
+
+    #[test]
+    fn test_tri_alpha_masks() {
+        use crate::texture::ConstantTexture;
+
+        let mesh = || TriangleMesh::new(
+            Transform::IDENTITY,
+            vec![0, 1, 2],
+            vec![Point3f::new(-1.0, -1.0, 0.0), Point3f::new(1.0, -1.0, 0.0), Point3f::new(0.0, 1.0, 0.0)],
+            None,
+            None,
+            None,
+            false
+        );
+        let ray = Ray::new(Point3f::new(0.0, 0.0, -1.0), Vec3f::new(0.0, 0.0, 1.0));
+
+        let cutout = Arc::new(mesh().with_alpha_masks(Some(Arc::new(ConstantTexture(0.0))), None));
+        let tri = cutout.iter_triangles().next().unwrap();
+        assert!(tri.intersect(&ray).is_none());
+        assert!(!tri.intersect_test(&ray));
+
+        let no_shadow = Arc::new(mesh().with_alpha_masks(None, Some(Arc::new(ConstantTexture(0.0)))));
+        let tri = no_shadow.iter_triangles().next().unwrap();
+        assert!(tri.intersect(&ray).is_some());
+        assert!(!tri.intersect_test(&ray));
+    }
 }
\ No newline at end of file
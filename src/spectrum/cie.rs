@@ -0,0 +1,48 @@
+//! Analytic approximations of the CIE 1931 2° color matching functions, from "Simple Analytic
+//! Approximations to the CIE XYZ Color Matching Functions" (Wyman, Sloan and Shirley, 2013).
+
+use crate::Float;
+
+pub const CIE_LAMBDA_START: Float = 360.0;
+pub const CIE_LAMBDA_END: Float = 830.0;
+
+/// Number of 1nm samples used when integrating spectral data against the matching functions.
+pub const N_CIE_SAMPLES: usize = 471;
+
+fn piecewise_gaussian(lambda: Float, mu: Float, sigma_lo: Float, sigma_hi: Float) -> Float {
+    let sigma = if lambda < mu { sigma_lo } else { sigma_hi };
+    let t = (lambda - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+pub fn cie_x(lambda: Float) -> Float {
+    1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2)
+}
+
+pub fn cie_y(lambda: Float) -> Float {
+    0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1)
+}
+
+pub fn cie_z(lambda: Float) -> Float {
+    1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8)
+}
+
+/// Integrates the spectral distribution `spd` (a function of wavelength in nm) against the
+/// matching functions, normalized so that a constant distribution of 1 has a luminance of 1.
+pub fn spd_to_xyz(spd: impl Fn(Float) -> Float) -> [Float; 3] {
+    let mut xyz = [0.0; 3];
+    let mut y_integral = 0.0;
+    for i in 0..N_CIE_SAMPLES {
+        let lambda = CIE_LAMBDA_START + i as Float;
+        let val = spd(lambda);
+        xyz[0] += val * cie_x(lambda);
+        xyz[1] += val * cie_y(lambda);
+        xyz[2] += val * cie_z(lambda);
+        y_integral += cie_y(lambda);
+    }
+    [xyz[0] / y_integral, xyz[1] / y_integral, xyz[2] / y_integral]
+}
@@ -3,6 +3,9 @@ use std::mem::MaybeUninit;
 use crate::Float;
 use approx::AbsDiffEq;
 use std::ops::Add;
+use std::path::Path;
+
+pub mod cie;
 
 pub fn array<F: FnMut(usize) -> Float, const N: usize>(mut init: F) -> [Float; N] {
     let mut arr = MaybeUninit::<[Float; N]>::uninit();
@@ -42,6 +45,69 @@ pub fn rgb_to_xyz(rgb: [Float; 3]) -> [Float; 3] {
     xyz
 }
 
+/// Emitted radiance of a blackbody at temperature `temp` (in Kelvin) for wavelength `lambda`
+/// (in nm), given by Planck's law.
+pub fn blackbody(lambda: Float, temp: Float) -> Float {
+    if temp <= 0.0 {
+        return 0.0;
+    }
+    const C: f64 = 299792458.0;
+    const H: f64 = 6.62606957e-34;
+    const KB: f64 = 1.3806488e-23;
+    let l = lambda as f64 * 1e-9;
+    let le = (2.0 * H * C * C) / (l.powi(5) * (((H * C) / (l * KB * temp as f64)).exp() - 1.0));
+    le as Float
+}
+
+/// Blackbody emission normalized so that the peak of the distribution, found with Wien's
+/// displacement law, has a value of 1.
+pub fn blackbody_normalized(lambda: Float, temp: Float) -> Float {
+    if temp <= 0.0 {
+        return 0.0;
+    }
+    let lambda_max = 2.8977721e-3 / temp * 1e9;
+    blackbody(lambda, temp) / blackbody(lambda_max, temp)
+}
+
+/// Linearly interpolates the piecewise-linear distribution given by `samples` of
+/// (wavelength, value) pairs sorted by wavelength. Values outside the sampled range are
+/// clamped to the nearest endpoint.
+pub fn interpolate_spectrum_samples(samples: &[(Float, Float)], lambda: Float) -> Float {
+    let (first, last) = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return 0.0,
+    };
+    if lambda <= first.0 {
+        return first.1;
+    }
+    if lambda >= last.0 {
+        return last.1;
+    }
+    let i = samples.windows(2).position(|w| lambda < w[1].0).unwrap();
+    let (l0, v0) = samples[i];
+    let (l1, v1) = samples[i + 1];
+    let t = (lambda - l0) / (l1 - l0);
+    (1.0 - t) * v0 + t * v1
+}
+
+/// Reads a pbrt `.spd` file of whitespace separated (wavelength, value) pairs. Anything after a
+/// `#` on a line is a comment.
+pub fn read_spd_file(path: impl AsRef<Path>) -> std::io::Result<Vec<(Float, Float)>> {
+    let contents = std::fs::read_to_string(path)?;
+    let vals = contents.lines()
+        .flat_map(|line| line.split('#').next().unwrap().split_whitespace())
+        .map(|tok| tok.parse::<Float>())
+        .collect::<Result<Vec<Float>, _>>()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if vals.len() % 2 != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Odd number of values in SPD file"
+        ));
+    }
+    Ok(vals.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect())
+}
+
 #[derive(Clone, Copy)]
 pub struct CoefficientSpectrum<const N: usize>([Float; N]);
 
@@ -112,6 +178,23 @@ impl<const N: usize> CoefficientSpectrum<{N}> {
 }
 
 impl CoefficientSpectrum<3> {
+    pub fn from_xyz(xyz: [Float; 3]) -> Self {
+        Self(xyz_to_rgb(xyz))
+    }
+
+    /// Converts a distribution given as (wavelength in nm, value) pairs. The pairs don't need
+    /// to be sorted.
+    pub fn from_sampled(samples: &[(Float, Float)]) -> Self {
+        let mut samples = samples.to_vec();
+        samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self::from_xyz(cie::spd_to_xyz(|lambda| interpolate_spectrum_samples(&samples, lambda)))
+    }
+
+    /// The normalized emission of a blackbody at temperature `temp`, scaled by `scale`.
+    pub fn from_blackbody(temp: Float, scale: Float) -> Self {
+        scale * Self::from_xyz(cie::spd_to_xyz(|lambda| blackbody_normalized(lambda, temp)))
+    }
+
     pub fn to_xyz(self) -> [Float; 3] {
        rgb_to_xyz(self.0)
     }
@@ -302,6 +385,7 @@ impl<const N: usize> approx::UlpsEq for CoefficientSpectrum<{N}> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use approx::assert_abs_diff_eq;
 
 
     #[test]
@@ -310,4 +394,60 @@ mod tests {
         let sum: Spectrum = spectra.into_iter().sum();
         assert_eq!(sum, Spectrum::from([1.0, 2.0, 1.5]));
     }
+
+    #[test]
+    fn test_constant_spd_to_xyz() {
+        let xyz = cie::spd_to_xyz(|_| 1.0);
+        // The matching functions all have (nearly) the same integral
+        assert_abs_diff_eq!(xyz[0], 1.0, epsilon = 0.05);
+        assert_abs_diff_eq!(xyz[1], 1.0, epsilon = 1e-5);
+        assert_abs_diff_eq!(xyz[2], 1.0, epsilon = 0.05);
+
+        let from_samples = Spectrum::from_sampled(&[(700.0, 2.0), (400.0, 2.0)]);
+        assert_abs_diff_eq!(from_samples.to_xyz()[1], 2.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn test_interpolate_spectrum_samples() {
+        let samples = [(400.0, 0.0), (500.0, 1.0), (600.0, 0.5)];
+        assert_eq!(interpolate_spectrum_samples(&samples, 300.0), 0.0);
+        assert_eq!(interpolate_spectrum_samples(&samples, 450.0), 0.5);
+        assert_eq!(interpolate_spectrum_samples(&samples, 550.0), 0.75);
+        assert_eq!(interpolate_spectrum_samples(&samples, 700.0), 0.5);
+    }
+
+    #[test]
+    fn test_blackbody_normalized() {
+        let temp = 6500.0;
+        let lambda_max = 2.8977721e-3 / temp * 1e9;
+        assert_abs_diff_eq!(blackbody_normalized(lambda_max, temp), 1.0, epsilon = 1e-5);
+        assert!(blackbody_normalized(lambda_max - 50.0, temp) < 1.0);
+        assert!(blackbody_normalized(lambda_max + 50.0, temp) < 1.0);
+
+        // A 6500K blackbody is close to white, a 2000K one is much redder
+        let white = Spectrum::from_blackbody(6500.0, 1.0);
+        assert!((white[0] - white[2]).abs() < 0.1 * white[1]);
+        let red = Spectrum::from_blackbody(2000.0, 1.0);
+        assert!(red[0] > red[2]);
+
+        assert_eq!(blackbody_normalized(lambda_max, 0.0), 0.0);
+        assert!(Spectrum::from_blackbody(-100.0, 1.0).is_black());
+    }
+
+    #[test]
+    fn test_cie_matching_functions() {
+        // Tabulated CIE 1931 2° values
+        let table = [
+            (450.0, [0.33620, 0.03800, 1.77211]),
+            (500.0, [0.00490, 0.32300, 0.27200]),
+            (550.0, [0.43345, 0.99500, 0.00875]),
+            (600.0, [1.06220, 0.63100, 0.00080]),
+            (650.0, [0.28350, 0.10700, 0.00000]),
+        ];
+        for &(lambda, [x, y, z]) in &table {
+            assert_abs_diff_eq!(cie::cie_x(lambda), x, epsilon = 0.015);
+            assert_abs_diff_eq!(cie::cie_y(lambda), y, epsilon = 0.015);
+            assert_abs_diff_eq!(cie::cie_z(lambda), z, epsilon = 0.015);
+        }
+    }
 }
@@ -43,11 +43,10 @@ fn furnace_test_path_no_rr() -> anyhow::Result<()> {
 #[test]
 fn furnace_test_directlighting() -> anyhow::Result<()> {
     let (img, (w, h)) =
-        do_render(DirectLightingIntegrator {
-            strategy: LightStrategy::UniformSampleOne,
-            max_depth: 3,
-            n_light_samples: vec![]
-        }, "testscenes/furnace_empty.pbrt")?;
+        do_render(
+            DirectLightingIntegrator::new(LightStrategy::UniformSampleOne, 3),
+            "testscenes/furnace_empty.pbrt"
+        )?;
 
     let expected = 1.0 + 0.5;
     for s in img {
@@ -59,6 +58,46 @@ fn furnace_test_directlighting() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn furnace_test_directlighting_all() -> anyhow::Result<()> {
+    let (img, (w, h)) =
+        do_render(
+            DirectLightingIntegrator::new(LightStrategy::UniformSampleAll, 3),
+            "testscenes/furnace_empty.pbrt"
+        )?;
+
+    let expected = 1.0 + 0.5;
+    for s in img {
+        for comp in s.into_array().iter() {
+            assert_abs_diff_eq!(*comp, expected, epsilon = 0.00001);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn furnace_test_header_integrator() -> anyhow::Result<()> {
+    let fname = "testscenes/furnace_directlighting.pbrt";
+    let parsed = pbrt_parser::PbrtParser::parse_with_includes(fname)?;
+    let mut header = PbrtHeader::new();
+    for stmt in parsed.header {
+        header.exec_stmt(stmt)?;
+    }
+    let integrator = header.make_integrator()?;
+
+    // Direct lighting only picks up one bounce, where a path tracer would converge to 2
+    let (img, (w, h)) = do_render(integrator, fname)?;
+    let expected = 1.0 + 0.5;
+    for s in img {
+        for comp in s.into_array().iter() {
+            assert_abs_diff_eq!(*comp, expected, epsilon = 0.00001);
+        }
+    }
+
+    Ok(())
+}
+
 fn do_render(integrator: impl IntegratorRadiance, fname: impl AsRef<Path>) -> anyhow::Result<(Vec<Spectrum>, (u32, u32))> {
 
     let parsed = pbrt_parser::PbrtParser::parse_with_includes(fname)?;
@@ -70,7 +109,7 @@ fn do_render(integrator: impl IntegratorRadiance, fname: impl AsRef<Path>) -> an
     let filename = header.film_params.get_one("filename").unwrap_or("render.exr".to_string());
     assert!(filename.contains(".exr"));
 
-    let mut scene_builder = PbrtSceneBuilder::new(env!("CARGO_MANIFEST_DIR").into());
+    let mut scene_builder = PbrtSceneBuilder::from_header(env!("CARGO_MANIFEST_DIR").into(), &header);
     for stmt in parsed.world {
         scene_builder.exec_stmt(stmt)?;
     }